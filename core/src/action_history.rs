@@ -0,0 +1,445 @@
+//! Local SQLite store of the actions built by the main loop: the datapoint used, the fee, the
+//! submission result and whether the tx was later confirmed or evicted from the mempool
+
+use std::fmt::Display;
+use std::ops::RangeInclusive;
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use derive_more::From;
+use ergo_lib::ergotree_ir::chain::ergo_box::BoxId;
+use rusqlite::{params, Connection, Row};
+use serde::Serialize;
+use thiserror::Error;
+use utoipa::ToSchema;
+
+use crate::actions::ActionExecError;
+use crate::node_interface::{TxId, TxStatus};
+
+/// A submitted tx that is neither in the mempool nor confirmed this many blocks after it was built
+/// is considered evicted
+pub const EVICTION_GRACE_BLOCKS: u32 = 3;
+
+#[derive(Debug, Error, From)]
+pub enum ActionHistoryError {
+    #[error("sqlite error: {0}")]
+    Sqlite(rusqlite::Error),
+    #[error("invalid input box ids in the history: {0}")]
+    InputBoxIds(serde_json::Error),
+    #[error("unknown action status in the history: {0}")]
+    #[from(ignore)]
+    UnknownStatus(String),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ActionStatus {
+    /// Built but not submitted (read-only mode)
+    Built,
+    /// Submitted and waiting in the mempool
+    Submitted,
+    Failed,
+    Confirmed,
+    Evicted,
+}
+
+impl ActionStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ActionStatus::Built => "built",
+            ActionStatus::Submitted => "submitted",
+            ActionStatus::Failed => "failed",
+            ActionStatus::Confirmed => "confirmed",
+            ActionStatus::Evicted => "evicted",
+        }
+    }
+
+    fn parse(s: &str) -> Result<Self, ActionHistoryError> {
+        match s {
+            "built" => Ok(ActionStatus::Built),
+            "submitted" => Ok(ActionStatus::Submitted),
+            "failed" => Ok(ActionStatus::Failed),
+            "confirmed" => Ok(ActionStatus::Confirmed),
+            "evicted" => Ok(ActionStatus::Evicted),
+            _ => Err(ActionHistoryError::UnknownStatus(s.to_string())),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, ToSchema)]
+pub struct ActionRecord {
+    pub id: i64,
+    /// Same names as the `action` label of the metrics
+    pub action: String,
+    /// Oracle identity the action was built for
+    pub oracle_address: String,
+    /// Inputs of the tx, not spent by the actions built while it's pending
+    #[schema(value_type = Vec<String>)]
+    pub input_box_ids: Vec<BoxId>,
+    /// Unix time (in seconds) the action was built
+    pub built_at: u64,
+    /// Height the action was built at
+    pub height: u32,
+    /// The published datapoint, or the new pool rate of a refresh
+    pub datapoint: i64,
+    /// Tx fee in nanoErgs
+    pub fee: u64,
+    pub tx_id: Option<String>,
+    pub status: ActionStatus,
+    pub error: Option<String>,
+    /// Unix time (in seconds) of the last status change
+    pub updated_at: u64,
+    pub confirmation_height: Option<u32>,
+}
+
+impl ActionRecord {
+    fn from_row(row: &Row) -> Result<Self, ActionHistoryError> {
+        let status: String = row.get("status")?;
+        let input_box_ids: String = row.get("input_box_ids")?;
+        Ok(ActionRecord {
+            id: row.get("id")?,
+            action: row.get("action")?,
+            oracle_address: row.get("oracle_address")?,
+            input_box_ids: serde_json::from_str(&input_box_ids)?,
+            built_at: row.get::<_, i64>("built_at")? as u64,
+            height: row.get("height")?,
+            datapoint: row.get("datapoint")?,
+            fee: row.get::<_, i64>("fee")? as u64,
+            tx_id: row.get("tx_id")?,
+            status: ActionStatus::parse(&status)?,
+            error: row.get("error")?,
+            updated_at: row.get::<_, i64>("updated_at")? as u64,
+            confirmation_height: row.get("confirmation_height")?,
+        })
+    }
+}
+
+/// Shared by the main loop and the REST API thread
+#[derive(Debug)]
+pub struct ActionHistory {
+    conn: Mutex<Connection>,
+}
+
+impl ActionHistory {
+    /// Opens the database file, creating it if it doesn't exist
+    pub fn open(path: &Path) -> Result<Self, ActionHistoryError> {
+        Self::init(Connection::open(path)?)
+    }
+
+    pub fn open_in_memory() -> Result<Self, ActionHistoryError> {
+        Self::init(Connection::open_in_memory()?)
+    }
+
+    fn init(conn: Connection) -> Result<Self, ActionHistoryError> {
+        conn.execute_batch(
+            "CREATE TABLE IF NOT EXISTS actions (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                action TEXT NOT NULL,
+                built_at INTEGER NOT NULL,
+                height INTEGER NOT NULL,
+                datapoint INTEGER NOT NULL,
+                fee INTEGER NOT NULL,
+                tx_id TEXT,
+                status TEXT NOT NULL,
+                error TEXT,
+                updated_at INTEGER NOT NULL,
+                confirmation_height INTEGER,
+                oracle_address TEXT NOT NULL,
+                input_box_ids TEXT NOT NULL
+            );
+            CREATE INDEX IF NOT EXISTS actions_status ON actions (status);",
+        )?;
+        Ok(ActionHistory {
+            conn: Mutex::new(conn),
+        })
+    }
+
+    /// Records a built action, returns the id of the record
+    pub fn record_built(
+        &self,
+        action: &str,
+        oracle_address: &str,
+        height: u32,
+        datapoint: i64,
+        fee: u64,
+        input_box_ids: &[BoxId],
+    ) -> Result<i64, ActionHistoryError> {
+        let input_box_ids = serde_json::to_string(input_box_ids)?;
+        let conn = self.conn.lock().unwrap();
+        let now = unix_now();
+        conn.execute(
+            "INSERT INTO actions (action, built_at, height, datapoint, fee, status, updated_at,
+                oracle_address, input_box_ids)
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?2, ?7, ?8)",
+            params![
+                action,
+                now,
+                height,
+                datapoint,
+                fee as i64,
+                ActionStatus::Built.as_str(),
+                oracle_address,
+                input_box_ids
+            ],
+        )?;
+        Ok(conn.last_insert_rowid())
+    }
+
+    /// Records the result of `execute_action` for the action recorded with `record_built`
+    pub fn record_submission(
+        &self,
+        id: i64,
+        result: &Result<TxId, ActionExecError>,
+    ) -> Result<(), ActionHistoryError> {
+        let (status, tx_id, error) = match result {
+            Ok(tx_id) => (
+                ActionStatus::Submitted,
+                // Node interface returns Tx Id as a JSON string "TxId"
+                Some(tx_id.replace('"', "")),
+                None,
+            ),
+            Err(e) => (ActionStatus::Failed, None, Some(e.to_string())),
+        };
+        self.conn.lock().unwrap().execute(
+            "UPDATE actions SET status = ?2, tx_id = ?3, error = ?4, updated_at = ?5
+            WHERE id = ?1",
+            params![id, status.as_str(), tx_id, error, unix_now()],
+        )?;
+        Ok(())
+    }
+
+    fn set_status(
+        &self,
+        id: i64,
+        status: ActionStatus,
+        confirmation_height: Option<u32>,
+    ) -> Result<(), ActionHistoryError> {
+        self.conn.lock().unwrap().execute(
+            "UPDATE actions SET status = ?2, confirmation_height = ?3, updated_at = ?4
+            WHERE id = ?1",
+            params![id, status.as_str(), confirmation_height, unix_now()],
+        )?;
+        Ok(())
+    }
+
+    /// Submitted actions that are not confirmed or evicted yet, oldest first
+    pub fn pending(&self) -> Result<Vec<ActionRecord>, ActionHistoryError> {
+        self.query(
+            "SELECT * FROM actions WHERE status = ?1 ORDER BY id",
+            params![ActionStatus::Submitted.as_str()],
+        )
+    }
+
+    /// The last `limit` actions, newest first
+    pub fn recent(&self, limit: u32) -> Result<Vec<ActionRecord>, ActionHistoryError> {
+        self.query(
+            "SELECT * FROM actions ORDER BY id DESC LIMIT ?1",
+            params![limit],
+        )
+    }
+
+    fn query(
+        &self,
+        sql: &str,
+        params: &[&dyn rusqlite::ToSql],
+    ) -> Result<Vec<ActionRecord>, ActionHistoryError> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare(sql)?;
+        let mut rows = stmt.query(params)?;
+        let mut records = Vec::new();
+        while let Some(row) = rows.next()? {
+            records.push(ActionRecord::from_row(row)?);
+        }
+        Ok(records)
+    }
+
+    /// Marks the pending actions as confirmed or evicted according to `tx_status`, which is given
+    /// the heights of the blocks the tx could be included in (from the one after it was built to
+    /// `height`). Failing to get the status of a tx only logs the error, it is checked again on
+    /// the next call.
+    pub fn update_pending<E: Display>(
+        &self,
+        height: u32,
+        tx_status: impl Fn(&str, RangeInclusive<u32>) -> Result<TxStatus, E>,
+    ) -> Result<(), ActionHistoryError> {
+        for record in self.pending()? {
+            let tx_id = match &record.tx_id {
+                Some(tx_id) => tx_id,
+                None => continue,
+            };
+            match tx_status(tx_id, record.height + 1..=height) {
+                Ok(TxStatus::InMempool) => (),
+                Ok(TxStatus::Confirmed(confirmation_height)) => {
+                    log::info!(
+                        "{} tx {} confirmed at height {}",
+                        record.action,
+                        tx_id,
+                        confirmation_height
+                    );
+                    self.set_status(
+                        record.id,
+                        ActionStatus::Confirmed,
+                        Some(confirmation_height),
+                    )?;
+                }
+                Ok(TxStatus::NotFound) if height > record.height + EVICTION_GRACE_BLOCKS => {
+                    log::warn!(
+                        "{} tx {} was evicted from the mempool",
+                        record.action,
+                        tx_id
+                    );
+                    self.set_status(record.id, ActionStatus::Evicted, None)?;
+                }
+                // Might be just dropped from the mempool while its block is not applied yet
+                Ok(TxStatus::NotFound) => (),
+                Err(e) => log::warn!("Failed to get the status of tx {}: {}", tx_id, e),
+            }
+        }
+        Ok(())
+    }
+}
+
+fn unix_now() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs() as i64
+}
+
+#[cfg(test)]
+mod tests {
+    use ergo_lib::ergo_chain_types::Digest32;
+
+    use crate::node_interface::find_tx_in_blocks;
+
+    use super::*;
+
+    #[test]
+    fn test_action_history() {
+        let history = ActionHistory::open_in_memory().unwrap();
+        let published = history
+            .record_built(
+                "publish_subsequent_datapoint",
+                "oracle",
+                100,
+                1234,
+                1_100_000,
+                &[],
+            )
+            .unwrap();
+        history
+            .record_submission(published, &Ok("\"aa\"".to_string()))
+            .unwrap();
+        let refreshed = history
+            .record_built("refresh", "oracle", 101, 1200, 1_100_000, &[])
+            .unwrap();
+        let spent = ActionExecError::InputsSpentInMempool("Double spending attempt".to_string());
+        history.record_submission(refreshed, &Err(spent)).unwrap();
+        let evicted = history
+            .record_built(
+                "publish_subsequent_datapoint",
+                "oracle",
+                101,
+                1300,
+                1_100_000,
+                &[],
+            )
+            .unwrap();
+        history
+            .record_submission(evicted, &Ok("bb".to_string()))
+            .unwrap();
+        history
+            .record_built("refresh", "oracle", 102, 1250, 1_100_000, &[])
+            .unwrap();
+
+        let pending = history.pending().unwrap();
+        assert_eq!(pending.len(), 2);
+        assert_eq!(pending[0].tx_id.as_deref(), Some("aa"));
+        assert_eq!(pending[0].datapoint, 1234);
+        assert_eq!(pending[0].oracle_address, "oracle");
+
+        // The txs of the blocks from height 100, "aa" is included at 103 and "cc" at 107
+        let chain = vec![
+            vec![],
+            vec![],
+            vec![],
+            vec!["aa"],
+            vec![],
+            vec![],
+            vec![],
+            vec!["cc"],
+        ];
+        let tx_status = |tx_id: &str, heights: RangeInclusive<u32>| -> Result<TxStatus, String> {
+            find_tx_in_blocks(tx_id, heights, |height| {
+                Ok(chain
+                    .get((height - 100) as usize)
+                    .map(|txs| txs.iter().map(|tx| tx.to_string()).collect())
+                    .unwrap_or_default())
+            })
+        };
+        // Still within the grace period of "bb"
+        history.update_pending(103, tx_status).unwrap();
+        assert_eq!(history.pending().unwrap().len(), 1);
+        history.update_pending(105, tx_status).unwrap();
+        assert!(history.pending().unwrap().is_empty());
+
+        // Only checked past the grace period (e.g. the wallet of the node lags behind or it's
+        // another node after a switch), found in the blocks instead of being considered evicted
+        let late = history
+            .record_built(
+                "publish_subsequent_datapoint",
+                "oracle",
+                102,
+                1280,
+                1_100_000,
+                &[],
+            )
+            .unwrap();
+        history
+            .record_submission(late, &Ok("cc".to_string()))
+            .unwrap();
+        history.update_pending(110, tx_status).unwrap();
+        assert!(history.pending().unwrap().is_empty());
+        let late_record = history.recent(1).unwrap().remove(0);
+        assert_eq!(late_record.status, ActionStatus::Confirmed);
+        assert_eq!(late_record.confirmation_height, Some(107));
+
+        let recent = history.recent(10).unwrap();
+        let statuses: Vec<ActionStatus> = recent.iter().skip(1).map(|r| r.status).collect();
+        assert_eq!(
+            statuses,
+            vec![
+                ActionStatus::Built,
+                ActionStatus::Evicted,
+                ActionStatus::Failed,
+                ActionStatus::Confirmed
+            ]
+        );
+        assert_eq!(recent[4].confirmation_height, Some(103));
+        assert!(recent[3].error.is_some() && recent[3].tx_id.is_none());
+        assert_eq!(history.recent(1).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_input_box_ids() {
+        let history = ActionHistory::open_in_memory().unwrap();
+        let input_box_ids = vec![
+            BoxId::from(Digest32::from([1; 32])),
+            BoxId::from(Digest32::from([2; 32])),
+        ];
+        history
+            .record_built(
+                "refresh",
+                "other oracle",
+                100,
+                1200,
+                1_100_000,
+                &input_box_ids,
+            )
+            .unwrap();
+        let record = history.recent(1).unwrap().remove(0);
+        assert_eq!(record.oracle_address, "other oracle");
+        assert_eq!(record.input_box_ids, input_box_ids);
+    }
+}
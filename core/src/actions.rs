@@ -1,7 +1,8 @@
 /// This file holds all the actions which can be performed
 /// by an oracle part of the oracle pool. These actions
 /// are implemented on the `OraclePool` struct.
-use crate::node_interface::sign_and_submit_transaction;
+use crate::node_interface::{sign_and_submit_transaction, TxId};
+use ergo_lib::chain::ergo_box::box_value::BoxValue;
 use ergo_lib::chain::transaction::unsigned::UnsignedTransaction;
 
 use derive_more::From;
@@ -15,50 +16,114 @@ mod collect;
 pub enum PoolAction {
     Refresh(RefreshAction),
     PublishDatapoint(PublishDataPointAction),
+    ExtractRewardTokens(ExtractRewardTokensAction),
+}
+
+impl PoolAction {
+    /// The published datapoint, the new pool rate of a refresh, or the datapoint kept in the
+    /// oracle box by a reward tokens extraction
+    pub fn datapoint(&self) -> i64 {
+        match self {
+            PoolAction::Refresh(action) => action.rate as i64,
+            PoolAction::PublishDatapoint(action) => action.datapoint,
+            PoolAction::ExtractRewardTokens(action) => action.datapoint,
+        }
+    }
+
+    pub fn fee(&self) -> BoxValue {
+        match self {
+            PoolAction::Refresh(action) => action.fee,
+            PoolAction::PublishDatapoint(action) => action.fee,
+            PoolAction::ExtractRewardTokens(action) => action.fee,
+        }
+    }
+
+    pub fn tx(&self) -> &UnsignedTransaction {
+        match self {
+            PoolAction::Refresh(action) => &action.tx,
+            PoolAction::PublishDatapoint(action) => &action.tx,
+            PoolAction::ExtractRewardTokens(action) => &action.tx,
+        }
+    }
 }
 
 #[derive(Debug)]
 pub struct RefreshAction {
     pub tx: UnsignedTransaction,
+    /// The new pool rate
+    pub rate: u64,
+    pub fee: BoxValue,
 }
 
 #[derive(Debug)]
 pub struct PublishDataPointAction {
     pub tx: UnsignedTransaction,
+    pub datapoint: i64,
+    pub fee: BoxValue,
+}
+
+#[derive(Debug)]
+pub struct ExtractRewardTokensAction {
+    pub tx: UnsignedTransaction,
+    /// The datapoint of the oracle box, 0 if it was collected
+    pub datapoint: i64,
+    /// Number of reward tokens transferred out of the oracle box
+    pub num_reward_tokens: u64,
+    pub fee: BoxValue,
 }
 
 #[derive(Error, Debug, From)]
 pub enum ActionExecError {
     #[error("node error: {0}")]
     NodeError(NodeError),
+    /// Another tx in the mempool spends the same inputs. Our own pending txs are tracked in the
+    /// action history, so this one is either not ours or was submitted before the history existed.
+    #[error("inputs are already spent by a tx in the mempool: {0}")]
+    #[from(ignore)]
+    InputsSpentInMempool(String),
 }
 
-pub fn execute_action(action: PoolAction) -> Result<(), ActionExecError> {
+/// Returns the id of the submitted transaction
+pub fn execute_action(action: PoolAction) -> Result<TxId, ActionExecError> {
     let exec_res = match action {
         PoolAction::Refresh(action) => execute_refresh_action(action),
         PoolAction::PublishDatapoint(action) => execute_publish_datapoint_action(action),
+        PoolAction::ExtractRewardTokens(action) => execute_extract_reward_tokens_action(action),
     };
     match exec_res {
-        Ok(_) => Ok(()),
+        Ok(tx_id) => Ok(tx_id),
         Err(ActionExecError::NodeError(NodeError::BadRequest(msg)))
             if msg.as_str() == "Double spending attempt"
                 || msg.contains("it is invalidated earlier or the pool is full") =>
         {
-            log::info!("Node rejected tx, probably, due to this tx is already in the mempool)");
-            Ok(())
+            Err(ActionExecError::InputsSpentInMempool(msg))
         }
         Err(e) => Err(e),
     }
 }
 
-fn execute_refresh_action(action: RefreshAction) -> Result<(), ActionExecError> {
+fn execute_refresh_action(action: RefreshAction) -> Result<TxId, ActionExecError> {
     let tx_id = sign_and_submit_transaction(&action.tx)?;
     log::info!("Refresh tx published successfully, tx id: {}", tx_id);
-    Ok(())
+    Ok(tx_id)
 }
 
-fn execute_publish_datapoint_action(action: PublishDataPointAction) -> Result<(), ActionExecError> {
+fn execute_publish_datapoint_action(
+    action: PublishDataPointAction,
+) -> Result<TxId, ActionExecError> {
     let tx_id = sign_and_submit_transaction(&action.tx)?;
     log::info!("Datapoint published successfully, tx id: {}", tx_id);
-    Ok(())
+    Ok(tx_id)
+}
+
+fn execute_extract_reward_tokens_action(
+    action: ExtractRewardTokensAction,
+) -> Result<TxId, ActionExecError> {
+    let tx_id = sign_and_submit_transaction(&action.tx)?;
+    log::info!(
+        "{} reward tokens extracted successfully, tx id: {}",
+        action.num_reward_tokens,
+        tx_id
+    );
+    Ok(tx_id)
 }
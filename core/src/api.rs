@@ -1,117 +1,374 @@
 use std::net::SocketAddr;
+use std::sync::{Arc, RwLock};
+use std::thread;
 
-use crate::box_kind::OracleBox;
-use crate::node_interface::current_block_height;
-use crate::oracle_config::{get_core_api_port, get_node_ip, get_node_port, ORACLE_CONFIG};
-use crate::oracle_state::{OraclePool, StageDataSource};
+use crate::action_history::{ActionHistory, ActionHistoryError, ActionRecord};
+use crate::oracle_config::{
+    get_core_api_bind_address, get_core_api_port, get_node_ip, get_node_port, ORACLE_CONFIG,
+};
+use crate::oracle_state::{OraclePool, StageDataSource, StageError};
 use crate::state::PoolState;
-use axum::response::IntoResponse;
-use axum::routing::get;
-use axum::{Json, Router};
+use axum::extract::Query;
+use axum::http::{header, HeaderName, HeaderValue, Method, StatusCode};
+use axum::response::{IntoResponse, Response};
+use axum::routing::{get, post, MethodRouter};
+use axum::{Extension, Json, Router};
+use axum_server::tls_rustls::RustlsConfig;
 use crossbeam::channel::Receiver;
-use serde_json::json;
-use tower_http::cors::CorsLayer;
+use derive_more::From;
+use ergo_lib::ergotree_ir::chain::token::TokenId;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use tower_http::cors::{AllowOrigin, Any, CorsLayer};
+use utoipa::{IntoParams, ToSchema};
+
+pub mod commands;
+pub mod events;
+pub mod health;
+pub mod openapi;
+pub mod v1;
+
+use commands::ApiCommandSender;
+use events::PoolEventSender;
+
+#[derive(Debug, From, Error)]
+pub enum ApiError {
+    #[error("stage error: {0}")]
+    Stage(StageError),
+    #[error("action history error: {0}")]
+    ActionHistory(ActionHistoryError),
+    #[error("{0} is not available yet")]
+    #[from(ignore)]
+    NotAvailable(&'static str),
+    #[error("invalid or missing auth token")]
+    Unauthorized,
+    #[error("write endpoints are disabled, set core_api_auth_token in the config to enable them")]
+    WriteEndpointsDisabled,
+    #[error("main loop is not running")]
+    MainLoopUnavailable,
+    #[error("command failed: {0}")]
+    #[from(ignore)]
+    CommandFailed(String),
+    #[error("{0} is not an oracle address of this oracle core")]
+    #[from(ignore)]
+    UnknownOracle(String),
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        let status = match self {
+            ApiError::NotAvailable(_) => StatusCode::SERVICE_UNAVAILABLE,
+            ApiError::Stage(_) | ApiError::ActionHistory(_) | ApiError::CommandFailed(_) => {
+                StatusCode::INTERNAL_SERVER_ERROR
+            }
+            ApiError::Unauthorized => StatusCode::UNAUTHORIZED,
+            ApiError::WriteEndpointsDisabled => StatusCode::FORBIDDEN,
+            ApiError::MainLoopUnavailable => StatusCode::SERVICE_UNAVAILABLE,
+            ApiError::UnknownOracle(_) => StatusCode::NOT_FOUND,
+        };
+        let body = ErrorResponse {
+            error: self.to_string(),
+        };
+        (status, Json(body)).into_response()
+    }
+}
+
+/// State of the pool read by the main loop on every iteration. The API handlers serve it instead
+/// of querying the node themselves.
+#[derive(Debug, Clone, Default)]
+pub struct ApiSnapshot {
+    pub height: Option<u32>,
+    pub pool_state: Option<PoolState>,
+    pub number_of_oracles: Option<u64>,
+    pub datapoint_address: Option<String>,
+    /// All the oracle identities, `oracle_address` first
+    pub oracles: Option<Vec<v1::OracleResponse>>,
+    pub pool: Option<v1::PoolResponse>,
+    pub epoch: Option<v1::EpochResponse>,
+    pub update: Option<v1::UpdateResponse>,
+    pub health: Option<health::HealthChecks>,
+}
+
+pub type SharedApiSnapshot = Arc<RwLock<ApiSnapshot>>;
+
+impl ApiSnapshot {
+    /// Reads the current state of the pool. Parts that fail to load are left empty.
+    pub fn new(op: &OraclePool, height: u32, pool_state: PoolState) -> Self {
+        ApiSnapshot {
+            height: Some(height),
+            pool_state: Some(pool_state),
+            number_of_oracles: ok_or_log(
+                "number of oracles",
+                op.datapoint_stage
+                    .stage
+                    .number_of_boxes()
+                    .map_err(Into::into),
+            ),
+            datapoint_address: Some(op.datapoint_stage.stage.contract_address.clone()),
+            oracles: ok_or_log("oracles", v1::oracle_responses(op)),
+            pool: ok_or_log("pool", v1::pool_response(op)),
+            epoch: ok_or_log("epoch", v1::epoch_response(op, height)),
+            update: ok_or_log("update", v1::update_response(op)),
+            health: None,
+        }
+    }
+
+    /// The oracle identity with the given address, the one of `oracle_address` if `None`
+    pub fn oracle(&self, address: Option<&str>) -> Result<&v1::OracleResponse, ApiError> {
+        let oracles = self
+            .oracles
+            .as_ref()
+            .ok_or(ApiError::NotAvailable("oracle"))?;
+        match address {
+            Some(address) => oracles
+                .iter()
+                .find(|oracle| oracle.oracle_address == address)
+                .ok_or_else(|| ApiError::UnknownOracle(address.to_string())),
+            None => oracles.first().ok_or(ApiError::NotAvailable("oracle")),
+        }
+    }
+}
+
+/// Selects one of the oracle identities of this oracle core
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct OracleQuery {
+    /// Address of the oracle identity, `oracle_address` of the config if not set
+    pub address: Option<String>,
+}
+
+fn ok_or_log<T>(name: &str, result: Result<T, ApiError>) -> Option<T> {
+    result
+        .map_err(|e| log::debug!("API snapshot: failed to load {}: {}", name, e))
+        .ok()
+}
+
+/// Body of the error responses
+#[derive(Debug, Serialize, ToSchema)]
+pub struct ErrorResponse {
+    pub error: String,
+}
+
+/// `GET /oracleInfo`
+#[derive(Debug, Serialize, ToSchema)]
+pub struct OracleInfoResponse {
+    pub oracle_address: String,
+}
+
+/// `GET /oracleStatus`, zeroes if there is no datapoint
+#[derive(Debug, Serialize, ToSchema)]
+pub struct OracleStatusResponse {
+    pub latest_datapoint: u64,
+    pub latest_datapoint_epoch: u32,
+    pub latest_datapoint_creation_height: u32,
+}
+
+/// `GET /poolInfo`
+#[derive(Debug, Serialize, ToSchema)]
+pub struct PoolInfoResponse {
+    pub number_of_oracles: u64,
+    pub datapoint_address: Option<String>,
+    pub live_epoch_length: i32,
+    pub deviation_range: i32,
+    pub consensus_num: i32,
+    #[schema(value_type = String)]
+    pub oracle_pool_nft_id: TokenId,
+    #[schema(value_type = String)]
+    pub oracle_pool_participant_token_id: TokenId,
+}
+
+/// `GET /nodeInfo`
+#[derive(Debug, Serialize, ToSchema)]
+pub struct NodeInfoResponse {
+    pub node_url: String,
+}
+
+/// `GET /poolStatus`
+#[derive(Debug, Serialize, ToSchema)]
+pub struct PoolStatusResponse {
+    /// "Live Epoch" or "Needs bootstrap"
+    pub current_pool_stage: String,
+    pub latest_datapoint: u64,
+    pub current_epoch_id: String,
+}
 
 /// Basic welcome endpoint
+#[utoipa::path(
+    get,
+    path = "/",
+    tag = "legacy",
+    responses((status = 200, content_type = "text/plain", body = String))
+)]
 async fn root() -> &'static str {
     "This is an Oracle Core. Please use one of the endpoints to interact with it.\n"
 }
 
 /// Basic oracle information
-async fn oracle_info() -> impl IntoResponse {
-    Json(json! ( {
-            "oracle_address": &ORACLE_CONFIG.oracle_address.to_base58(),
-        } ))
+#[utoipa::path(
+    get,
+    path = "/oracleInfo",
+    tag = "legacy",
+    responses((status = 200, body = OracleInfoResponse))
+)]
+async fn oracle_info() -> Json<OracleInfoResponse> {
+    Json(OracleInfoResponse {
+        oracle_address: ORACLE_CONFIG.oracle_address.to_base58(),
+    })
 }
 
 /// Status of the oracle
-async fn oracle_status() -> impl IntoResponse {
-    let op = OraclePool::new().unwrap();
-
+#[utoipa::path(
+    get,
+    path = "/oracleStatus",
+    tag = "legacy",
+    params(OracleQuery),
+    responses(
+        (status = 200, body = OracleStatusResponse),
+        (status = 404, description = "Unknown oracle address", body = ErrorResponse)
+    )
+)]
+async fn oracle_status(
+    Extension(snapshot): Extension<SharedApiSnapshot>,
+    Query(query): Query<OracleQuery>,
+) -> Result<Json<OracleStatusResponse>, ApiError> {
     // Get latest datapoint the local oracle produced/submit
-    let latest_oracle_box = op
-        .get_local_datapoint_box_source()
-        .get_local_oracle_datapoint_box();
-    // let self_datapoint = match latest_oracle_box {
-    //     Ok(Some(ref d)) => d.rate().unwrap_or(0),
-    //     Ok(None) | Err(_) => 0,
-    // };
-    // // Get latest datapoint submit epoch
-    // let datapoint_epoch = match latest_oracle_box {
-    //     Ok(Some(ref d)) => d.epoch_counter().unwrap_or(0),
-    //     Ok(None) | Err(_) => 0,
-    // };
-    // Get latest datapoint submit epoch
-    let datapoint_creation = match latest_oracle_box {
-        Ok(Some(ref d)) => d.get_box().creation_height,
-        Ok(None) | Err(_) => 0,
+    let snapshot = snapshot.read().unwrap();
+    let latest_oracle_box = match snapshot.oracle(query.address.as_deref()) {
+        Ok(oracle) => oracle.oracle_box.clone(),
+        Err(ApiError::NotAvailable(_)) => None,
+        Err(e) => return Err(e),
+    };
+    // A collected box has neither a datapoint nor an epoch
+    let (self_datapoint, datapoint_epoch, datapoint_creation) = match latest_oracle_box {
+        Some(d) => (
+            d.rate.unwrap_or(0),
+            d.epoch_counter.unwrap_or(0),
+            d.creation_height,
+        ),
+        None => (0, 0, 0),
     };
 
-    Json(json! ({
-        // "latest_datapoint": self_datapoint,
-        // "latest_datapoint_epoch": datapoint_epoch,
-        "latest_datapoint_creation_height": datapoint_creation,
+    Ok(Json(OracleStatusResponse {
+        latest_datapoint: self_datapoint,
+        latest_datapoint_epoch: datapoint_epoch,
+        latest_datapoint_creation_height: datapoint_creation,
     }))
 }
 
 // Basic information about the oracle pool
-async fn pool_info() -> impl IntoResponse {
+#[utoipa::path(
+    get,
+    path = "/poolInfo",
+    tag = "legacy",
+    responses(
+        (status = 200, body = PoolInfoResponse),
+        (status = 503, description = "Not read by the main loop yet", body = ErrorResponse)
+    )
+)]
+async fn pool_info(
+    Extension(snapshot): Extension<SharedApiSnapshot>,
+) -> Result<Json<PoolInfoResponse>, ApiError> {
     let parameters = &ORACLE_CONFIG;
-    let op = OraclePool::new().unwrap();
-    let datapoint_stage = op.datapoint_stage;
-    let num_of_oracles = datapoint_stage.stage.number_of_boxes().unwrap_or(10);
-
-    Json(json!({
-        "number_of_oracles": num_of_oracles,
-        "datapoint_address": datapoint_stage.stage.contract_address,
-        "live_epoch_length": parameters.refresh_box_wrapper_inputs.contract_inputs.contract_parameters().epoch_length(),
-        "deviation_range": parameters.refresh_box_wrapper_inputs.contract_inputs.contract_parameters().max_deviation_percent(),
-        "consensus_num": parameters.refresh_box_wrapper_inputs.contract_inputs.contract_parameters().min_data_points(),
-        "oracle_pool_nft_id": parameters.token_ids.pool_nft_token_id,
-        "oracle_pool_participant_token_id": parameters.token_ids.oracle_token_id,
+    let refresh_contract_parameters = parameters
+        .refresh_box_wrapper_inputs
+        .contract_inputs
+        .contract_parameters();
+    let snapshot = snapshot.read().unwrap();
+    let num_of_oracles = snapshot
+        .number_of_oracles
+        .ok_or(ApiError::NotAvailable("number of oracles"))?;
 
+    Ok(Json(PoolInfoResponse {
+        number_of_oracles: num_of_oracles,
+        datapoint_address: snapshot.datapoint_address.clone(),
+        live_epoch_length: refresh_contract_parameters.epoch_length(),
+        deviation_range: refresh_contract_parameters.max_deviation_percent(),
+        consensus_num: refresh_contract_parameters.min_data_points(),
+        oracle_pool_nft_id: parameters.token_ids.pool_nft_token_id.clone(),
+        oracle_pool_participant_token_id: parameters.token_ids.oracle_token_id.clone(),
     }))
 }
 
 /// Basic information about node the oracle core is using
-async fn node_info() -> impl IntoResponse {
-    Json(json!({
-        "node_url": "http://".to_string() + &get_node_ip() + ":" + &get_node_port(),
-    }))
+#[utoipa::path(
+    get,
+    path = "/nodeInfo",
+    tag = "legacy",
+    responses((status = 200, body = NodeInfoResponse))
+)]
+async fn node_info() -> Json<NodeInfoResponse> {
+    Json(NodeInfoResponse {
+        node_url: "http://".to_string() + &get_node_ip() + ":" + &get_node_port(),
+    })
 }
 
 /// Status of the oracle pool
-async fn pool_status() -> impl IntoResponse {
-    let op = OraclePool::new().unwrap();
+#[utoipa::path(
+    get,
+    path = "/poolStatus",
+    tag = "legacy",
+    responses(
+        (status = 200, body = PoolStatusResponse),
+        (status = 503, description = "Not read by the main loop yet", body = ErrorResponse)
+    )
+)]
+async fn pool_status(
+    Extension(snapshot): Extension<SharedApiSnapshot>,
+) -> Result<Json<PoolStatusResponse>, ApiError> {
+    let pool_state = snapshot
+        .read()
+        .unwrap()
+        .pool_state
+        .clone()
+        .ok_or(ApiError::NotAvailable("pool state"))?;
 
     // Current stage of the oracle pool box
-    let current_stage = match op.check_oracle_pool_stage() {
-        PoolState::LiveEpoch(_) => "Live Epoch",
-        PoolState::NeedsBootstrap => "Needs bootstrap",
-    };
-
     let mut latest_datapoint = 0;
     let mut current_epoch_id = "".to_string();
-    if let Ok(l) = op.get_live_epoch_state() {
-        latest_datapoint = l.latest_pool_datapoint;
-        current_epoch_id = l.pool_box_epoch_id.to_string();
-    }
-    Json(json!({
-            "current_pool_stage": current_stage,
-            "latest_datapoint": latest_datapoint,
-            "current_epoch_id" : current_epoch_id,
+    let current_stage = match pool_state {
+        PoolState::LiveEpoch(l) => {
+            latest_datapoint = l.latest_pool_datapoint;
+            current_epoch_id = l.pool_box_epoch_id.to_string();
+            "Live Epoch"
+        }
+        PoolState::NeedsBootstrap => "Needs bootstrap",
+    };
+    Ok(Json(PoolStatusResponse {
+        current_pool_stage: current_stage.to_string(),
+        latest_datapoint,
+        current_epoch_id,
     }))
 }
 
 /// Block height of the Ergo blockchain
-async fn block_height() -> impl IntoResponse {
-    let current_height =
-        current_block_height().expect("Please ensure that the Ergo node is running.");
-    format!("{}", current_height)
+#[utoipa::path(
+    get,
+    path = "/blockHeight",
+    tag = "legacy",
+    responses(
+        (status = 200, content_type = "text/plain", body = String),
+        (status = 503, description = "Not read by the main loop yet", body = ErrorResponse)
+    )
+)]
+async fn block_height(
+    Extension(snapshot): Extension<SharedApiSnapshot>,
+) -> Result<String, ApiError> {
+    let current_height = snapshot
+        .read()
+        .unwrap()
+        .height
+        .ok_or(ApiError::NotAvailable("block height"))?;
+    Ok(format!("{}", current_height))
 }
 
 /// Whether the Core requires the Connector to repost a new Datapoint
-async fn require_datapoint_repost(repost_receiver: Receiver<bool>) -> impl IntoResponse {
+#[utoipa::path(
+    get,
+    path = "/requireDatapointRepost",
+    tag = "legacy",
+    responses((status = 200, description = "\"true\" or \"false\"", content_type = "text/plain", body = String))
+)]
+async fn require_datapoint_repost(repost_receiver: Receiver<bool>) -> String {
     let mut response_text = "false".to_string();
     if let Ok(b) = repost_receiver.try_recv() {
         response_text = b.to_string();
@@ -119,27 +376,276 @@ async fn require_datapoint_repost(repost_receiver: Receiver<bool>) -> impl IntoR
     response_text
 }
 
-pub async fn start_rest_server(repost_receiver: Receiver<bool>) {
-    let app = Router::new()
-        .route("/", get(root))
-        .route("/oracleInfo", get(oracle_info))
-        .route("/oracleStatus", get(oracle_status))
-        .route("/poolInfo", get(pool_info))
-        .route("/nodeInfo", get(node_info))
-        .route("/poolStatus", get(pool_status))
-        .route("/blockHeight", get(block_height))
-        .route(
+/// Prometheus metrics
+#[utoipa::path(
+    get,
+    path = "/metrics",
+    tag = "metrics",
+    responses((status = 200, content_type = "text/plain", body = String))
+)]
+async fn metrics() -> impl IntoResponse {
+    (
+        [(header::CONTENT_TYPE, prometheus::TEXT_FORMAT)],
+        crate::metrics::encode(),
+    )
+}
+
+/// The address and box of one of the oracle identities, `oracle_address` by default
+#[utoipa::path(
+    get,
+    path = "/api/v1/oracle",
+    tag = "v1",
+    params(OracleQuery),
+    responses(
+        (status = 200, body = v1::OracleResponse),
+        (status = 404, description = "Unknown oracle address", body = ErrorResponse),
+        (status = 503, description = "Not read by the main loop yet", body = ErrorResponse)
+    )
+)]
+async fn oracle_v1(
+    Extension(snapshot): Extension<SharedApiSnapshot>,
+    Query(query): Query<OracleQuery>,
+) -> Result<Json<v1::OracleResponse>, ApiError> {
+    let snapshot = snapshot.read().unwrap();
+    Ok(Json(snapshot.oracle(query.address.as_deref())?.clone()))
+}
+
+/// The addresses and boxes of all the oracle identities, `oracle_address` first
+#[utoipa::path(
+    get,
+    path = "/api/v1/oracles",
+    tag = "v1",
+    responses(
+        (status = 200, body = [v1::OracleResponse]),
+        (status = 503, description = "Not read by the main loop yet", body = ErrorResponse)
+    )
+)]
+async fn oracles_v1(
+    Extension(snapshot): Extension<SharedApiSnapshot>,
+) -> Result<Json<Vec<v1::OracleResponse>>, ApiError> {
+    let oracles = snapshot.read().unwrap().oracles.clone();
+    Ok(Json(oracles.ok_or(ApiError::NotAvailable("oracles"))?))
+}
+
+/// Token ids, the pool box and the refresh box
+#[utoipa::path(
+    get,
+    path = "/api/v1/pool",
+    tag = "v1",
+    responses(
+        (status = 200, body = v1::PoolResponse),
+        (status = 503, description = "Not read by the main loop yet", body = ErrorResponse)
+    )
+)]
+async fn pool_v1(
+    Extension(snapshot): Extension<SharedApiSnapshot>,
+) -> Result<Json<v1::PoolResponse>, ApiError> {
+    let pool = snapshot.read().unwrap().pool.clone();
+    Ok(Json(pool.ok_or(ApiError::NotAvailable("pool"))?))
+}
+
+/// The current epoch and the oracle boxes posted in it
+#[utoipa::path(
+    get,
+    path = "/api/v1/epoch",
+    tag = "v1",
+    responses(
+        (status = 200, body = v1::EpochResponse),
+        (status = 503, description = "Not read by the main loop yet", body = ErrorResponse)
+    )
+)]
+async fn epoch_v1(
+    Extension(snapshot): Extension<SharedApiSnapshot>,
+) -> Result<Json<v1::EpochResponse>, ApiError> {
+    let epoch = snapshot.read().unwrap().epoch.clone();
+    Ok(Json(epoch.ok_or(ApiError::NotAvailable("epoch"))?))
+}
+
+/// The update box and the cast ballots
+#[utoipa::path(
+    get,
+    path = "/api/v1/update",
+    tag = "v1",
+    responses(
+        (status = 200, body = v1::UpdateResponse),
+        (status = 503, description = "Not read by the main loop yet", body = ErrorResponse)
+    )
+)]
+async fn update_v1(
+    Extension(snapshot): Extension<SharedApiSnapshot>,
+) -> Result<Json<v1::UpdateResponse>, ApiError> {
+    let update = snapshot.read().unwrap().update.clone();
+    Ok(Json(update.ok_or(ApiError::NotAvailable("update"))?))
+}
+
+/// Number of actions returned by `GET /api/v1/history` by default
+pub const DEFAULT_HISTORY_LIMIT: u32 = 50;
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct HistoryQuery {
+    /// Number of actions to return, 50 if not set
+    limit: Option<u32>,
+}
+
+/// Actions built by the main loop and the status of their txs, newest first
+#[utoipa::path(
+    get,
+    path = "/api/v1/history",
+    tag = "v1",
+    params(HistoryQuery),
+    responses(
+        (status = 200, body = [ActionRecord]),
+        (status = 500, description = "Failed to read the action history", body = ErrorResponse)
+    )
+)]
+async fn history_v1(
+    Extension(history): Extension<Arc<ActionHistory>>,
+    Query(query): Query<HistoryQuery>,
+) -> Result<Json<Vec<ActionRecord>>, ApiError> {
+    let limit = query.limit.unwrap_or(DEFAULT_HISTORY_LIMIT);
+    Ok(Json(history.recent(limit)?))
+}
+
+/// Every route of the API. All of them must be described in `openapi::ApiDoc`.
+fn routes(repost_receiver: Receiver<bool>) -> Vec<(&'static str, Method, MethodRouter)> {
+    vec![
+        ("/", Method::GET, get(root)),
+        ("/oracleInfo", Method::GET, get(oracle_info)),
+        ("/oracleStatus", Method::GET, get(oracle_status)),
+        ("/poolInfo", Method::GET, get(pool_info)),
+        ("/nodeInfo", Method::GET, get(node_info)),
+        ("/poolStatus", Method::GET, get(pool_status)),
+        ("/blockHeight", Method::GET, get(block_height)),
+        (
             "/requireDatapointRepost",
+            Method::GET,
             get(|| require_datapoint_repost(repost_receiver)),
-        )
+        ),
+        ("/metrics", Method::GET, get(metrics)),
+        ("/health", Method::GET, get(health::health)),
+        ("/ready", Method::GET, get(health::ready)),
+        ("/api/v1/oracle", Method::GET, get(oracle_v1)),
+        ("/api/v1/oracles", Method::GET, get(oracles_v1)),
+        ("/api/v1/pool", Method::GET, get(pool_v1)),
+        ("/api/v1/epoch", Method::GET, get(epoch_v1)),
+        ("/api/v1/update", Method::GET, get(update_v1)),
+        ("/api/v1/history", Method::GET, get(history_v1)),
+        ("/api/v1/events", Method::GET, get(events::events)),
+        (
+            "/api/v1/openapi.json",
+            Method::GET,
+            get(openapi::openapi_json),
+        ),
+        (
+            "/api/v1/actions/publish-datapoint",
+            Method::POST,
+            post(commands::publish_datapoint),
+        ),
+        (
+            "/api/v1/actions/refresh",
+            Method::POST,
+            post(commands::refresh),
+        ),
+        ("/api/v1/actions/pause", Method::POST, post(commands::pause)),
+        (
+            "/api/v1/actions/resume",
+            Method::POST,
+            post(commands::resume),
+        ),
+        (
+            "/api/v1/actions/extract-reward-tokens",
+            Method::POST,
+            post(commands::extract_reward_tokens),
+        ),
+    ]
+}
+
+/// Runs the REST API server on its own thread and runtime, so that it doesn't block the main loop
+pub fn start_rest_server_thread(
+    repost_receiver: Receiver<bool>,
+    snapshot: SharedApiSnapshot,
+    command_sender: ApiCommandSender,
+    event_sender: PoolEventSender,
+    history: Arc<ActionHistory>,
+) -> std::io::Result<thread::JoinHandle<()>> {
+    thread::Builder::new()
+        .name("rest-api".to_string())
+        .spawn(move || {
+            let result = tokio::runtime::Runtime::new()
+                .map_err(anyhow::Error::from)
+                .and_then(|rt| {
+                    rt.block_on(start_rest_server(
+                        repost_receiver,
+                        snapshot,
+                        command_sender,
+                        event_sender,
+                        history,
+                    ))
+                });
+            if let Err(e) = result {
+                log::error!("REST API server error: {}", e);
+            }
+        })
+}
+
+pub async fn start_rest_server(
+    repost_receiver: Receiver<bool>,
+    snapshot: SharedApiSnapshot,
+    command_sender: ApiCommandSender,
+    event_sender: PoolEventSender,
+    history: Arc<ActionHistory>,
+) -> Result<(), anyhow::Error> {
+    let app = routes(repost_receiver)
+        .into_iter()
+        .fold(Router::new(), |router, (path, _, method_router)| {
+            router.route(path, method_router)
+        })
+        .layer(Extension(snapshot))
+        .layer(Extension(command_sender))
+        .layer(Extension(event_sender))
+        .layer(Extension(history))
         .layer(
             CorsLayer::new()
-                .allow_origin(tower_http::cors::Any)
-                .allow_methods([axum::http::Method::GET]),
+                .allow_origin(allowed_origins()?)
+                .allow_methods([Method::GET, Method::POST])
+                .allow_headers([
+                    header::AUTHORIZATION,
+                    header::CONTENT_TYPE,
+                    HeaderName::from_static(commands::API_KEY_HEADER),
+                ]),
         );
-    let addr = SocketAddr::from(([0, 0, 0, 0], get_core_api_port().parse().unwrap()));
-    axum::Server::bind(&addr)
-        .serve(app.into_make_service())
-        .await
-        .unwrap();
+    let addr = SocketAddr::new(get_core_api_bind_address(), get_core_api_port().parse()?);
+    match &ORACLE_CONFIG.core_api_tls {
+        Some(tls) => {
+            let tls_config = RustlsConfig::from_pem_file(&tls.cert_path, &tls.key_path).await?;
+            log::info!("REST API listening on https://{}", addr);
+            axum_server::bind_rustls(addr, tls_config)
+                .serve(app.into_make_service())
+                .await?;
+        }
+        None => {
+            let server = axum::Server::try_bind(&addr)?;
+            log::info!("REST API listening on http://{}", addr);
+            server.serve(app.into_make_service()).await?;
+        }
+    }
+    Ok(())
+}
+
+/// `core_api_allowed_origins` from the config file, any origin if not set
+fn allowed_origins() -> Result<AllowOrigin, anyhow::Error> {
+    match &ORACLE_CONFIG.core_api_allowed_origins {
+        Some(origins) => {
+            let origins = origins
+                .iter()
+                .map(|origin| {
+                    HeaderValue::from_str(origin)
+                        .map_err(|_| anyhow::anyhow!("invalid allowed origin: {}", origin))
+                })
+                .collect::<Result<Vec<_>, _>>()?;
+            Ok(origins.into())
+        }
+        None => Ok(Any.into()),
+    }
 }
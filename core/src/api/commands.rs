@@ -0,0 +1,258 @@
+//! Authenticated write endpoints (`POST /api/v1/actions/*`). The handlers send a command to the
+//! main loop and wait for its result.
+
+use axum::extract::Query;
+use axum::http::{header, HeaderMap};
+use axum::{Extension, Json};
+use crossbeam::channel::Sender;
+use serde::{Deserialize, Serialize};
+use tokio::sync::oneshot;
+use utoipa::ToSchema;
+
+use crate::oracle_config::ORACLE_CONFIG;
+
+use super::{ApiError, ErrorResponse, OracleQuery};
+
+pub const API_KEY_HEADER: &str = "x-api-key";
+
+/// A command for the main loop. `oracle_address` selects the oracle identity running the
+/// command, the identity of `oracle_address` in the config if not set.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ApiCommand {
+    /// Publish a datapoint now (republishing in the current epoch if already published)
+    PublishDatapoint {
+        oracle_address: Option<String>,
+    },
+    /// Try to refresh the pool box now
+    Refresh {
+        oracle_address: Option<String>,
+    },
+    /// Stop running the main loop iterations until resumed
+    Pause,
+    Resume,
+    ExtractRewardTokens {
+        oracle_address: Option<String>,
+        rewards_address: String,
+    },
+}
+
+/// A command with the channel to send the outcome (or the error message) back to the handler
+#[derive(Debug)]
+pub struct ApiCommandRequest {
+    pub command: ApiCommand,
+    pub reply: oneshot::Sender<Result<String, String>>,
+}
+
+pub type ApiCommandSender = Sender<ApiCommandRequest>;
+
+/// Outcome of a command run by the main loop
+#[derive(Debug, Serialize, ToSchema)]
+pub struct CommandResponse {
+    pub result: String,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct ExtractRewardTokensRequest {
+    pub rewards_address: String,
+}
+
+/// Accepts either `Authorization: Bearer <token>` or `X-Api-Key: <token>`
+pub fn check_auth(headers: &HeaderMap, auth_token: Option<&str>) -> Result<(), ApiError> {
+    let auth_token = auth_token.ok_or(ApiError::WriteEndpointsDisabled)?;
+    let bearer = headers
+        .get(header::AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "));
+    let api_key = headers.get(API_KEY_HEADER).and_then(|v| v.to_str().ok());
+    match bearer.or(api_key) {
+        Some(token) if constant_time_eq(token.as_bytes(), auth_token.as_bytes()) => Ok(()),
+        _ => Err(ApiError::Unauthorized),
+    }
+}
+
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+async fn send_command(
+    headers: &HeaderMap,
+    command_sender: &ApiCommandSender,
+    command: ApiCommand,
+) -> Result<Json<CommandResponse>, ApiError> {
+    check_auth(headers, ORACLE_CONFIG.core_api_auth_token.as_deref())?;
+    let (reply, reply_receiver) = oneshot::channel();
+    command_sender
+        .send(ApiCommandRequest { command, reply })
+        .map_err(|_| ApiError::MainLoopUnavailable)?;
+    let result = reply_receiver
+        .await
+        .map_err(|_| ApiError::MainLoopUnavailable)?
+        .map_err(ApiError::CommandFailed)?;
+    Ok(Json(CommandResponse { result }))
+}
+
+/// Publish a datapoint now (republish if already published in the current epoch)
+#[utoipa::path(
+    post,
+    path = "/api/v1/actions/publish-datapoint",
+    tag = "actions",
+    params(OracleQuery),
+    responses(
+        (status = 200, body = CommandResponse),
+        (status = 401, description = "Invalid or missing auth token", body = ErrorResponse),
+        (status = 403, description = "Write endpoints are disabled", body = ErrorResponse),
+        (status = 500, description = "The command failed", body = ErrorResponse),
+        (status = 503, description = "The main loop is not running", body = ErrorResponse)
+    ),
+    security(("bearer_auth" = []), ("api_key" = []))
+)]
+pub async fn publish_datapoint(
+    headers: HeaderMap,
+    Extension(command_sender): Extension<ApiCommandSender>,
+    Query(query): Query<OracleQuery>,
+) -> Result<Json<CommandResponse>, ApiError> {
+    let command = ApiCommand::PublishDatapoint {
+        oracle_address: query.address,
+    };
+    send_command(&headers, &command_sender, command).await
+}
+
+/// Try to refresh the pool box now
+#[utoipa::path(
+    post,
+    path = "/api/v1/actions/refresh",
+    tag = "actions",
+    params(OracleQuery),
+    responses(
+        (status = 200, body = CommandResponse),
+        (status = 401, description = "Invalid or missing auth token", body = ErrorResponse),
+        (status = 403, description = "Write endpoints are disabled", body = ErrorResponse),
+        (status = 500, description = "The command failed", body = ErrorResponse),
+        (status = 503, description = "The main loop is not running", body = ErrorResponse)
+    ),
+    security(("bearer_auth" = []), ("api_key" = []))
+)]
+pub async fn refresh(
+    headers: HeaderMap,
+    Extension(command_sender): Extension<ApiCommandSender>,
+    Query(query): Query<OracleQuery>,
+) -> Result<Json<CommandResponse>, ApiError> {
+    let command = ApiCommand::Refresh {
+        oracle_address: query.address,
+    };
+    send_command(&headers, &command_sender, command).await
+}
+
+/// Pause the main loop
+#[utoipa::path(
+    post,
+    path = "/api/v1/actions/pause",
+    tag = "actions",
+    responses(
+        (status = 200, body = CommandResponse),
+        (status = 401, description = "Invalid or missing auth token", body = ErrorResponse),
+        (status = 403, description = "Write endpoints are disabled", body = ErrorResponse),
+        (status = 500, description = "The command failed", body = ErrorResponse),
+        (status = 503, description = "The main loop is not running", body = ErrorResponse)
+    ),
+    security(("bearer_auth" = []), ("api_key" = []))
+)]
+pub async fn pause(
+    headers: HeaderMap,
+    Extension(command_sender): Extension<ApiCommandSender>,
+) -> Result<Json<CommandResponse>, ApiError> {
+    send_command(&headers, &command_sender, ApiCommand::Pause).await
+}
+
+/// Resume the main loop
+#[utoipa::path(
+    post,
+    path = "/api/v1/actions/resume",
+    tag = "actions",
+    responses(
+        (status = 200, body = CommandResponse),
+        (status = 401, description = "Invalid or missing auth token", body = ErrorResponse),
+        (status = 403, description = "Write endpoints are disabled", body = ErrorResponse),
+        (status = 500, description = "The command failed", body = ErrorResponse),
+        (status = 503, description = "The main loop is not running", body = ErrorResponse)
+    ),
+    security(("bearer_auth" = []), ("api_key" = []))
+)]
+pub async fn resume(
+    headers: HeaderMap,
+    Extension(command_sender): Extension<ApiCommandSender>,
+) -> Result<Json<CommandResponse>, ApiError> {
+    send_command(&headers, &command_sender, ApiCommand::Resume).await
+}
+
+/// Send the reward tokens accumulated in the oracle box to `rewards_address`
+#[utoipa::path(
+    post,
+    path = "/api/v1/actions/extract-reward-tokens",
+    tag = "actions",
+    params(OracleQuery),
+    request_body = ExtractRewardTokensRequest,
+    responses(
+        (status = 200, body = CommandResponse),
+        (status = 401, description = "Invalid or missing auth token", body = ErrorResponse),
+        (status = 403, description = "Write endpoints are disabled", body = ErrorResponse),
+        (status = 500, description = "The command failed", body = ErrorResponse),
+        (status = 503, description = "The main loop is not running", body = ErrorResponse)
+    ),
+    security(("bearer_auth" = []), ("api_key" = []))
+)]
+pub async fn extract_reward_tokens(
+    headers: HeaderMap,
+    Extension(command_sender): Extension<ApiCommandSender>,
+    Query(query): Query<OracleQuery>,
+    Json(request): Json<ExtractRewardTokensRequest>,
+) -> Result<Json<CommandResponse>, ApiError> {
+    send_command(
+        &headers,
+        &command_sender,
+        ApiCommand::ExtractRewardTokens {
+            oracle_address: query.address,
+            rewards_address: request.rewards_address,
+        },
+    )
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::http::HeaderValue;
+
+    #[test]
+    fn test_check_auth() {
+        let mut headers = HeaderMap::new();
+        assert!(matches!(
+            check_auth(&headers, None),
+            Err(ApiError::WriteEndpointsDisabled)
+        ));
+        assert!(matches!(
+            check_auth(&headers, Some("secret")),
+            Err(ApiError::Unauthorized)
+        ));
+        headers.insert(
+            header::AUTHORIZATION,
+            HeaderValue::from_static("Bearer wrong"),
+        );
+        assert!(matches!(
+            check_auth(&headers, Some("secret")),
+            Err(ApiError::Unauthorized)
+        ));
+        headers.insert(
+            header::AUTHORIZATION,
+            HeaderValue::from_static("Bearer secret"),
+        );
+        assert!(check_auth(&headers, Some("secret")).is_ok());
+        let mut headers = HeaderMap::new();
+        headers.insert(API_KEY_HEADER, HeaderValue::from_static("secret"));
+        assert!(check_auth(&headers, Some("secret")).is_ok());
+        assert!(matches!(
+            check_auth(&headers, None),
+            Err(ApiError::WriteEndpointsDisabled)
+        ));
+    }
+}
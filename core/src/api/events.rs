@@ -0,0 +1,267 @@
+//! Pool events streamed to the clients of `GET /api/v1/events` (Server-Sent Events)
+
+use std::convert::Infallible;
+
+use axum::response::sse::{Event, KeepAlive, Sse};
+use axum::Extension;
+use ergo_lib::ergotree_ir::chain::ergo_box::BoxId;
+use serde::Serialize;
+use tokio::sync::broadcast;
+use tokio_stream::wrappers::BroadcastStream;
+use tokio_stream::{Stream, StreamExt};
+use utoipa::ToSchema;
+
+use crate::oracle_config::CastBallotBoxVoteParameters;
+
+use super::ApiSnapshot;
+
+/// Number of events kept for the clients that are slow to read them
+pub const EVENT_CHANNEL_CAPACITY: usize = 256;
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum PoolEvent {
+    /// The pool box epoch counter changed
+    NewEpoch {
+        epoch_counter: u32,
+        pool_box_creation_height: u32,
+    },
+    NewPoolRate {
+        rate: i64,
+        epoch_counter: u32,
+    },
+    /// An oracle box posted in the current epoch by an oracle not run by this oracle core
+    OracleBoxPosted {
+        #[schema(value_type = String)]
+        box_id: BoxId,
+        address: String,
+        rate: Option<u64>,
+        epoch_counter: Option<u32>,
+    },
+    /// A transaction submitted by this oracle
+    ActionSubmitted {
+        action: String,
+        tx_id: String,
+    },
+    BallotCast {
+        #[schema(value_type = String)]
+        box_id: BoxId,
+        owner_address: String,
+        vote: CastBallotBoxVoteParameters,
+    },
+    UpdateBoxChanged {
+        #[schema(value_type = String)]
+        box_id: BoxId,
+        creation_height: u32,
+    },
+}
+
+impl PoolEvent {
+    /// The SSE event name
+    pub fn name(&self) -> &'static str {
+        match self {
+            PoolEvent::NewEpoch { .. } => "new_epoch",
+            PoolEvent::NewPoolRate { .. } => "new_pool_rate",
+            PoolEvent::OracleBoxPosted { .. } => "oracle_box_posted",
+            PoolEvent::ActionSubmitted { .. } => "action_submitted",
+            PoolEvent::BallotCast { .. } => "ballot_cast",
+            PoolEvent::UpdateBoxChanged { .. } => "update_box_changed",
+        }
+    }
+}
+
+pub type PoolEventSender = broadcast::Sender<PoolEvent>;
+
+/// Sends the event to the connected clients, if any
+pub fn publish(sender: &PoolEventSender, event: PoolEvent) {
+    log::debug!("Pool event: {:?}", event);
+    // Fails only when there are no clients
+    let _ = sender.send(event);
+}
+
+/// Events observed between two consecutive snapshots of the main loop. Nothing is reported for
+/// the parts that were not available in the previous snapshot (e.g. on startup).
+pub fn snapshot_events(prev: &ApiSnapshot, next: &ApiSnapshot) -> Vec<PoolEvent> {
+    let mut events = Vec::new();
+    if let (Some(prev_pool), Some(pool)) = (&prev.pool, &next.pool) {
+        let epoch_counter = pool.pool_box.epoch_counter;
+        if epoch_counter != prev_pool.pool_box.epoch_counter {
+            events.push(PoolEvent::NewEpoch {
+                epoch_counter,
+                pool_box_creation_height: pool.pool_box.creation_height,
+            });
+        }
+        if pool.pool_box.rate != prev_pool.pool_box.rate {
+            events.push(PoolEvent::NewPoolRate {
+                rate: pool.pool_box.rate,
+                epoch_counter,
+            });
+        }
+    }
+    if let (Some(prev_epoch), Some(epoch)) = (&prev.epoch, &next.epoch) {
+        let own_addresses: Vec<&str> = next
+            .oracles
+            .iter()
+            .flatten()
+            .map(|o| o.oracle_address.as_str())
+            .collect();
+        events.extend(
+            epoch
+                .oracle_boxes
+                .iter()
+                .filter(|b| !own_addresses.contains(&b.address.as_str()))
+                .filter(|b| !prev_epoch.oracle_boxes.iter().any(|p| p.box_id == b.box_id))
+                .map(|b| PoolEvent::OracleBoxPosted {
+                    box_id: b.box_id.clone(),
+                    address: b.address.clone(),
+                    rate: b.rate,
+                    epoch_counter: b.epoch_counter,
+                }),
+        );
+    }
+    if let (Some(prev_update), Some(update)) = (&prev.update, &next.update) {
+        if update.update_box.box_id != prev_update.update_box.box_id {
+            events.push(PoolEvent::UpdateBoxChanged {
+                box_id: update.update_box.box_id.clone(),
+                creation_height: update.update_box.creation_height,
+            });
+        }
+        events.extend(
+            update
+                .ballots
+                .iter()
+                .filter(|b| !prev_update.ballots.iter().any(|p| p.box_id == b.box_id))
+                .map(|b| PoolEvent::BallotCast {
+                    box_id: b.box_id.clone(),
+                    owner_address: b.owner_address.clone(),
+                    vote: b.vote.clone(),
+                }),
+        );
+    }
+    events
+}
+
+/// Stream of the pool events, the data of each event is a JSON encoded `PoolEvent`
+#[utoipa::path(
+    get,
+    path = "/api/v1/events",
+    tag = "v1",
+    responses((status = 200, content_type = "text/event-stream", body = PoolEvent))
+)]
+pub async fn events(
+    Extension(sender): Extension<PoolEventSender>,
+) -> Sse<impl Stream<Item = Result<Event, Infallible>>> {
+    // Events missed by a lagging client are skipped
+    let stream = BroadcastStream::new(sender.subscribe()).filter_map(|event| match event {
+        Ok(event) => Event::default()
+            .event(event.name())
+            .json_data(&event)
+            .ok()
+            .map(Ok),
+        Err(_) => None,
+    });
+    Sse::new(stream).keep_alive(KeepAlive::default())
+}
+
+#[cfg(test)]
+mod tests {
+    use ergo_lib::ergo_chain_types::Digest32;
+
+    use super::*;
+    use crate::api::v1::{
+        EpochResponse, OracleBoxResponse, OracleResponse, PoolBoxResponse, PoolResponse,
+        RefreshBoxResponse,
+    };
+    use crate::pool_commands::test_utils::generate_token_ids;
+
+    fn box_id(n: u8) -> BoxId {
+        BoxId::from(Digest32::from([n; 32]))
+    }
+
+    fn pool(epoch_counter: u32, rate: i64) -> PoolResponse {
+        PoolResponse {
+            token_ids: generate_token_ids(),
+            pool_box: PoolBoxResponse {
+                box_id: box_id(epoch_counter as u8),
+                epoch_counter,
+                rate,
+                creation_height: 100 + epoch_counter,
+                reward_tokens: 100,
+            },
+            refresh_box: RefreshBoxResponse {
+                box_id: box_id(0),
+                creation_height: 1,
+                epoch_length: 30,
+                buffer: 4,
+                max_deviation_percent: 5,
+                min_data_points: 4,
+            },
+        }
+    }
+
+    fn oracle_box(n: u8, address: &str) -> OracleBoxResponse {
+        OracleBoxResponse {
+            box_id: box_id(n),
+            public_key: String::new(),
+            address: address.to_string(),
+            rate: Some(1000),
+            epoch_counter: Some(1),
+            creation_height: 120,
+            reward_tokens: 1,
+        }
+    }
+
+    fn epoch(oracle_boxes: Vec<OracleBoxResponse>) -> EpochResponse {
+        EpochResponse {
+            current_height: 125,
+            epoch_counter: 1,
+            pool_box_creation_height: 101,
+            next_refresh_possible_at_height: 132,
+            min_data_points: 4,
+            oracle_boxes,
+        }
+    }
+
+    #[test]
+    fn test_snapshot_events() {
+        let prev = ApiSnapshot {
+            pool: Some(pool(1, 1000)),
+            epoch: Some(epoch(vec![oracle_box(10, "other")])),
+            ..ApiSnapshot::default()
+        };
+        // Nothing is reported on startup
+        assert!(snapshot_events(&ApiSnapshot::default(), &prev).is_empty());
+        assert!(snapshot_events(&prev, &prev).is_empty());
+
+        let next = ApiSnapshot {
+            pool: Some(pool(2, 1100)),
+            epoch: Some(epoch(vec![
+                oracle_box(10, "other"),
+                oracle_box(11, "another"),
+                oracle_box(12, "own"),
+                oracle_box(13, "own additional"),
+            ])),
+            oracles: Some(vec![
+                OracleResponse {
+                    oracle_address: "own".to_string(),
+                    oracle_box: None,
+                },
+                OracleResponse {
+                    oracle_address: "own additional".to_string(),
+                    oracle_box: None,
+                },
+            ]),
+            ..ApiSnapshot::default()
+        };
+        let events = snapshot_events(&prev, &next);
+        let names: Vec<&str> = events.iter().map(PoolEvent::name).collect();
+        assert_eq!(
+            names,
+            vec!["new_epoch", "new_pool_rate", "oracle_box_posted"]
+        );
+        assert!(matches!(
+            &events[2],
+            PoolEvent::OracleBoxPosted { address, .. } if address == "another"
+        ));
+    }
+}
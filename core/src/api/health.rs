@@ -0,0 +1,259 @@
+//! Liveness (`GET /health`) and readiness (`GET /ready`) endpoints
+
+use std::time::{Duration, SystemTime};
+
+use axum::http::StatusCode;
+use axum::{Extension, Json};
+use serde::Serialize;
+use utoipa::ToSchema;
+
+use crate::local_wallet::local_wallet;
+use crate::metrics;
+use crate::node_interface::{
+    get_wallet_status, node_sync_status, registered_scan_ids, NodeSyncStatus, MAX_NODE_SYNC_LAG,
+};
+use crate::oracle_state::OraclePool;
+
+use super::SharedApiSnapshot;
+
+/// The main loop is considered stuck if it didn't run the checks for this long
+pub const MAX_CHECKS_AGE: Duration = Duration::from_secs(300);
+
+/// Checks run by the main loop on every iteration, even when paused
+#[derive(Debug, Clone)]
+pub struct HealthChecks {
+    pub checked_at: SystemTime,
+    pub paused: bool,
+    pub node_sync: Result<NodeSyncStatus, String>,
+    pub wallet_unlocked: Result<bool, String>,
+    /// Names of the scans that are not registered in the node
+    pub missing_scans: Result<Vec<String>, String>,
+    pub datapoint_source_up: Option<bool>,
+    pub last_action_at: Option<SystemTime>,
+}
+
+impl HealthChecks {
+    pub fn run(op: &OraclePool, paused: bool) -> Self {
+        let missing_scans = registered_scan_ids()
+            .map(|registered| {
+                op.scans()
+                    .into_iter()
+                    .filter(|scan| !registered.contains(scan.id()))
+                    .map(|scan| scan.name().to_string())
+                    .collect()
+            })
+            .map_err(|e| e.to_string());
+        HealthChecks {
+            checked_at: SystemTime::now(),
+            paused,
+            node_sync: node_sync_status().map_err(|e| e.to_string()),
+            // The local wallet has no lock, its keys are loaded at startup
+            wallet_unlocked: match local_wallet() {
+                Some(_) => Ok(true),
+                None => get_wallet_status()
+                    .map(|status| status.unlocked)
+                    .map_err(|e| e.to_string()),
+            },
+            missing_scans,
+            datapoint_source_up: metrics::datapoint_source_up(),
+            last_action_at: metrics::last_action_time(),
+        }
+    }
+}
+
+/// `GET /health` and `GET /ready`
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct HealthResponse {
+    /// The main loop is running its checks
+    pub healthy: bool,
+    /// Healthy, and the node, the wallet, the scans and the datapoint source are usable
+    pub ready: bool,
+    /// Why the oracle is not healthy or not ready
+    pub problems: Vec<String>,
+    pub secs_since_last_check: Option<u64>,
+    pub paused: bool,
+    pub node_height: Option<u32>,
+    pub node_sync_lag: Option<u32>,
+    pub wallet_unlocked: Option<bool>,
+    pub missing_scans: Vec<String>,
+    /// Whether the last datapoint fetch succeeded, `null` if there was none yet
+    pub datapoint_source_up: Option<bool>,
+    /// `null` if there was no transaction submitted since the start
+    pub secs_since_last_action: Option<u64>,
+}
+
+impl HealthResponse {
+    pub fn new(checks: Option<&HealthChecks>, now: SystemTime) -> Self {
+        let checks = match checks {
+            Some(checks) => checks,
+            None => {
+                return HealthResponse {
+                    healthy: false,
+                    ready: false,
+                    problems: vec!["main loop has not run the checks yet".to_string()],
+                    secs_since_last_check: None,
+                    paused: false,
+                    node_height: None,
+                    node_sync_lag: None,
+                    wallet_unlocked: None,
+                    missing_scans: Vec::new(),
+                    datapoint_source_up: None,
+                    secs_since_last_action: None,
+                }
+            }
+        };
+        let secs_since = |time: SystemTime| {
+            now.duration_since(time)
+                .unwrap_or(Duration::from_secs(0))
+                .as_secs()
+        };
+        let mut problems = Vec::new();
+        let secs_since_last_check = secs_since(checks.checked_at);
+        let healthy = secs_since_last_check <= MAX_CHECKS_AGE.as_secs();
+        if !healthy {
+            problems.push(format!(
+                "main loop has not run the checks for {} seconds",
+                secs_since_last_check
+            ));
+        }
+        let mut not_ready = |problem: String| problems.push(problem);
+        if checks.paused {
+            not_ready("main loop is paused".to_string());
+        }
+        match &checks.node_sync {
+            Ok(sync) if sync.lag() > MAX_NODE_SYNC_LAG => not_ready(format!(
+                "node is {} blocks behind the best header",
+                sync.lag()
+            )),
+            Ok(_) => (),
+            Err(e) => not_ready(format!("node is not reachable: {}", e)),
+        }
+        match &checks.wallet_unlocked {
+            Ok(true) => (),
+            Ok(false) => not_ready("node wallet is locked".to_string()),
+            Err(e) => not_ready(format!("failed to get the wallet status: {}", e)),
+        }
+        match &checks.missing_scans {
+            Ok(missing) if !missing.is_empty() => not_ready(format!(
+                "scans not registered in the node: {}",
+                missing.join(", ")
+            )),
+            Ok(_) => (),
+            Err(e) => not_ready(format!("failed to get the node scans: {}", e)),
+        }
+        if checks.datapoint_source_up == Some(false) {
+            not_ready("last datapoint fetch failed".to_string());
+        }
+        HealthResponse {
+            healthy,
+            ready: problems.is_empty(),
+            problems,
+            secs_since_last_check: Some(secs_since_last_check),
+            paused: checks.paused,
+            node_height: checks.node_sync.as_ref().ok().map(|sync| sync.full_height),
+            node_sync_lag: checks.node_sync.as_ref().ok().map(NodeSyncStatus::lag),
+            wallet_unlocked: checks.wallet_unlocked.as_ref().ok().copied(),
+            missing_scans: checks.missing_scans.clone().unwrap_or_default(),
+            datapoint_source_up: checks.datapoint_source_up,
+            secs_since_last_action: checks.last_action_at.map(secs_since),
+        }
+    }
+}
+
+fn health_response(snapshot: &SharedApiSnapshot) -> HealthResponse {
+    HealthResponse::new(snapshot.read().unwrap().health.as_ref(), SystemTime::now())
+}
+
+fn status_code(ok: bool) -> StatusCode {
+    if ok {
+        StatusCode::OK
+    } else {
+        StatusCode::SERVICE_UNAVAILABLE
+    }
+}
+
+/// Whether the main loop is running
+#[utoipa::path(
+    get,
+    path = "/health",
+    tag = "health",
+    responses(
+        (status = 200, body = HealthResponse),
+        (status = 503, description = "The main loop is stuck or not started", body = HealthResponse)
+    )
+)]
+pub async fn health(
+    Extension(snapshot): Extension<SharedApiSnapshot>,
+) -> (StatusCode, Json<HealthResponse>) {
+    let response = health_response(&snapshot);
+    (status_code(response.healthy), Json(response))
+}
+
+/// Whether the oracle is able to publish datapoints and refresh the pool
+#[utoipa::path(
+    get,
+    path = "/ready",
+    tag = "health",
+    responses(
+        (status = 200, body = HealthResponse),
+        (status = 503, description = "Not ready, see `problems`", body = HealthResponse)
+    )
+)]
+pub async fn ready(
+    Extension(snapshot): Extension<SharedApiSnapshot>,
+) -> (StatusCode, Json<HealthResponse>) {
+    let response = health_response(&snapshot);
+    (status_code(response.ready), Json(response))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn checks(now: SystemTime) -> HealthChecks {
+        HealthChecks {
+            checked_at: now - Duration::from_secs(10),
+            paused: false,
+            node_sync: Ok(NodeSyncStatus {
+                headers_height: 1001,
+                full_height: 1000,
+            }),
+            wallet_unlocked: Ok(true),
+            missing_scans: Ok(Vec::new()),
+            datapoint_source_up: None,
+            last_action_at: Some(now - Duration::from_secs(60)),
+        }
+    }
+
+    #[test]
+    fn test_health_response() {
+        let now = SystemTime::now();
+        let response = HealthResponse::new(None, now);
+        assert!(!response.healthy && !response.ready);
+
+        let response = HealthResponse::new(Some(&checks(now)), now);
+        assert!(
+            response.healthy && response.ready,
+            "{:?}",
+            response.problems
+        );
+        assert_eq!(response.node_sync_lag, Some(1));
+        assert_eq!(response.secs_since_last_action, Some(60));
+
+        let locked = HealthChecks {
+            wallet_unlocked: Ok(false),
+            missing_scans: Ok(vec!["Pool Box Scan".to_string()]),
+            ..checks(now)
+        };
+        let response = HealthResponse::new(Some(&locked), now);
+        assert!(response.healthy && !response.ready);
+        assert_eq!(response.problems.len(), 2);
+
+        let stuck = HealthChecks {
+            checked_at: now - MAX_CHECKS_AGE - Duration::from_secs(1),
+            ..checks(now)
+        };
+        let response = HealthResponse::new(Some(&stuck), now);
+        assert!(!response.healthy && !response.ready);
+    }
+}
@@ -0,0 +1,154 @@
+//! OpenAPI document of the REST API, served at `GET /api/v1/openapi.json`
+
+use axum::Json;
+use utoipa::openapi::security::{ApiKey, ApiKeyValue, HttpAuthScheme, HttpBuilder, SecurityScheme};
+use utoipa::{Modify, OpenApi};
+
+use super::commands::{self, API_KEY_HEADER};
+use super::{events, health, v1};
+
+#[derive(OpenApi)]
+#[openapi(
+    paths(
+        super::root,
+        super::oracle_info,
+        super::oracle_status,
+        super::pool_info,
+        super::node_info,
+        super::pool_status,
+        super::block_height,
+        super::require_datapoint_repost,
+        super::metrics,
+        health::health,
+        health::ready,
+        super::oracle_v1,
+        super::oracles_v1,
+        super::pool_v1,
+        super::epoch_v1,
+        super::update_v1,
+        super::history_v1,
+        events::events,
+        openapi_json,
+        commands::publish_datapoint,
+        commands::refresh,
+        commands::pause,
+        commands::resume,
+        commands::extract_reward_tokens,
+    ),
+    components(schemas(
+        super::ErrorResponse,
+        super::OracleInfoResponse,
+        super::OracleStatusResponse,
+        super::PoolInfoResponse,
+        super::NodeInfoResponse,
+        super::PoolStatusResponse,
+        v1::OracleBoxResponse,
+        v1::OracleResponse,
+        v1::PoolBoxResponse,
+        v1::RefreshBoxResponse,
+        v1::PoolResponse,
+        v1::EpochResponse,
+        v1::UpdateBoxResponse,
+        v1::BallotResponse,
+        v1::UpdateResponse,
+        crate::oracle_config::TokenIds,
+        crate::oracle_config::CastBallotBoxVoteParameters,
+        crate::action_history::ActionRecord,
+        crate::action_history::ActionStatus,
+        events::PoolEvent,
+        health::HealthResponse,
+        commands::CommandResponse,
+        commands::ExtractRewardTokensRequest,
+    )),
+    modifiers(&SecurityAddon)
+)]
+pub struct ApiDoc;
+
+/// Auth of the write endpoints, see `commands::check_auth`
+struct SecurityAddon;
+
+impl Modify for SecurityAddon {
+    fn modify(&self, openapi: &mut utoipa::openapi::OpenApi) {
+        if let Some(components) = openapi.components.as_mut() {
+            components.add_security_scheme(
+                "bearer_auth",
+                SecurityScheme::Http(HttpBuilder::new().scheme(HttpAuthScheme::Bearer).build()),
+            );
+            components.add_security_scheme(
+                "api_key",
+                SecurityScheme::ApiKey(ApiKey::Header(ApiKeyValue::new(API_KEY_HEADER))),
+            );
+        }
+    }
+}
+
+/// This document
+#[utoipa::path(
+    get,
+    path = "/api/v1/openapi.json",
+    tag = "v1",
+    responses((status = 200, description = "OpenAPI document"))
+)]
+pub async fn openapi_json() -> Json<utoipa::openapi::OpenApi> {
+    Json(ApiDoc::openapi())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeSet;
+
+    use axum::http::Method;
+    use utoipa::openapi::PathItemType;
+
+    use super::*;
+
+    fn method(path_item_type: &PathItemType) -> Method {
+        match path_item_type {
+            PathItemType::Get => Method::GET,
+            PathItemType::Post => Method::POST,
+            PathItemType::Put => Method::PUT,
+            PathItemType::Delete => Method::DELETE,
+            PathItemType::Options => Method::OPTIONS,
+            PathItemType::Head => Method::HEAD,
+            PathItemType::Patch => Method::PATCH,
+            PathItemType::Trace => Method::TRACE,
+            PathItemType::Connect => Method::CONNECT,
+        }
+    }
+
+    #[test]
+    fn test_openapi_matches_routes() {
+        let (_, repost_receiver) = crossbeam::channel::bounded(1);
+        let routes: BTreeSet<(String, String)> = super::super::routes(repost_receiver)
+            .into_iter()
+            .map(|(path, method, _)| (path.to_string(), method.to_string()))
+            .collect();
+        let documented: BTreeSet<(String, String)> = ApiDoc::openapi()
+            .paths
+            .paths
+            .iter()
+            .flat_map(|(path, path_item)| {
+                path_item
+                    .operations
+                    .keys()
+                    .map(move |op| (path.clone(), method(op).to_string()))
+            })
+            .collect();
+        assert_eq!(routes, documented);
+    }
+
+    #[test]
+    fn test_openapi_schemas() {
+        let doc = ApiDoc::openapi();
+        let schemas = &doc.components.as_ref().unwrap().schemas;
+        for name in [
+            "OracleResponse",
+            "PoolResponse",
+            "EpochResponse",
+            "PoolEvent",
+        ] {
+            assert!(schemas.contains_key(name), "missing schema {}", name);
+        }
+        assert!(doc.to_json().is_ok());
+    }
+}
@@ -0,0 +1,255 @@
+//! Typed responses of the versioned (`/api/v1`) REST API
+
+use ergo_lib::ergotree_ir::chain::address::{Address, NetworkAddress};
+use ergo_lib::ergotree_ir::chain::ergo_box::BoxId;
+use ergo_lib::ergotree_ir::sigma_protocol::sigma_boolean::ProveDlog;
+use serde::Serialize;
+use utoipa::ToSchema;
+
+use crate::box_kind::{
+    BallotBox, OracleBox, OracleBoxWrapper, PoolBox, PostedOracleBox, RefreshBox,
+};
+use crate::oracle_config::{CastBallotBoxVoteParameters, TokenIds, ORACLE_CONFIG};
+use crate::oracle_state::{OracleIdentity, OraclePool};
+
+use super::ApiError;
+
+/// An oracle box, either posted (with a datapoint) or collected by a refresh
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct OracleBoxResponse {
+    #[schema(value_type = String)]
+    pub box_id: BoxId,
+    pub public_key: String,
+    pub address: String,
+    /// `None` for a collected box
+    pub rate: Option<u64>,
+    /// `None` for a collected box
+    pub epoch_counter: Option<u32>,
+    pub creation_height: u32,
+    pub reward_tokens: u64,
+}
+
+impl OracleBoxResponse {
+    fn new(oracle_box: &OracleBoxWrapper) -> Self {
+        let (rate, epoch_counter) = match oracle_box {
+            OracleBoxWrapper::Posted(posted) => (Some(posted.rate()), Some(posted.epoch_counter())),
+            OracleBoxWrapper::Collected(_) => (None, None),
+        };
+        OracleBoxResponse {
+            box_id: oracle_box.get_box().box_id(),
+            public_key: public_key_string(&oracle_box.public_key()),
+            address: address_string(oracle_box.public_key()),
+            rate,
+            epoch_counter,
+            creation_height: oracle_box.get_box().creation_height,
+            reward_tokens: *oracle_box.reward_token().amount.as_u64(),
+        }
+    }
+
+    fn from_posted(posted: &PostedOracleBox) -> Self {
+        OracleBoxResponse {
+            box_id: posted.get_box().box_id(),
+            public_key: public_key_string(&posted.public_key()),
+            address: address_string(posted.public_key()),
+            rate: Some(posted.rate()),
+            epoch_counter: Some(posted.epoch_counter()),
+            creation_height: posted.get_box().creation_height,
+            reward_tokens: *posted.reward_token().amount.as_u64(),
+        }
+    }
+}
+
+/// `GET /api/v1/oracle`, one of the oracle identities run by this process
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct OracleResponse {
+    pub oracle_address: String,
+    /// This oracle's box, `None` if it hasn't published a datapoint yet
+    pub oracle_box: Option<OracleBoxResponse>,
+}
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct PoolBoxResponse {
+    #[schema(value_type = String)]
+    pub box_id: BoxId,
+    pub epoch_counter: u32,
+    pub rate: i64,
+    pub creation_height: u32,
+    pub reward_tokens: u64,
+}
+
+/// Parameters of the refresh contract of the refresh box on chain
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct RefreshBoxResponse {
+    #[schema(value_type = String)]
+    pub box_id: BoxId,
+    pub creation_height: u32,
+    pub epoch_length: i32,
+    pub buffer: i32,
+    pub max_deviation_percent: i32,
+    pub min_data_points: i32,
+}
+
+/// `GET /api/v1/pool`
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct PoolResponse {
+    pub token_ids: TokenIds,
+    pub pool_box: PoolBoxResponse,
+    pub refresh_box: RefreshBoxResponse,
+}
+
+/// `GET /api/v1/epoch`
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct EpochResponse {
+    pub current_height: u32,
+    pub epoch_counter: u32,
+    pub pool_box_creation_height: u32,
+    pub next_refresh_possible_at_height: u32,
+    pub min_data_points: i32,
+    /// Posted oracle boxes with the current epoch counter
+    pub oracle_boxes: Vec<OracleBoxResponse>,
+}
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct UpdateBoxResponse {
+    #[schema(value_type = String)]
+    pub box_id: BoxId,
+    pub creation_height: u32,
+    pub min_votes: u32,
+}
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct BallotResponse {
+    #[schema(value_type = String)]
+    pub box_id: BoxId,
+    pub owner_address: String,
+    pub ballot_tokens: u64,
+    pub vote: CastBallotBoxVoteParameters,
+}
+
+/// `GET /api/v1/update`
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct UpdateResponse {
+    pub update_box: UpdateBoxResponse,
+    /// Ballot boxes with a cast vote
+    pub ballots: Vec<BallotResponse>,
+}
+
+/// A refresh is possible once the pool box is older than the epoch length
+pub fn next_refresh_possible_at_height(pool_box_creation_height: u32, epoch_length: u32) -> u32 {
+    pool_box_creation_height + epoch_length + 1
+}
+
+pub fn oracle_response(identity: &OracleIdentity) -> Result<OracleResponse, ApiError> {
+    let oracle_box = identity
+        .get_local_datapoint_box_source()
+        .get_local_oracle_datapoint_box()?;
+    Ok(OracleResponse {
+        oracle_address: identity.address.to_base58(),
+        oracle_box: oracle_box.as_ref().map(OracleBoxResponse::new),
+    })
+}
+
+/// `GET /api/v1/oracles`, the oracle identities in the config order (`oracle_address` first)
+pub fn oracle_responses(op: &OraclePool) -> Result<Vec<OracleResponse>, ApiError> {
+    op.identities().iter().map(oracle_response).collect()
+}
+
+pub fn pool_response(op: &OraclePool) -> Result<PoolResponse, ApiError> {
+    let pool_box = op.get_pool_box_source().get_pool_box()?;
+    let refresh_box = op.get_refresh_box_source().get_refresh_box()?;
+    let refresh_contract = refresh_box.contract();
+    Ok(PoolResponse {
+        token_ids: ORACLE_CONFIG.token_ids.clone(),
+        pool_box: PoolBoxResponse {
+            box_id: pool_box.get_box().box_id(),
+            epoch_counter: pool_box.epoch_counter(),
+            rate: pool_box.rate(),
+            creation_height: pool_box.get_box().creation_height,
+            reward_tokens: *pool_box.reward_token().amount.as_u64(),
+        },
+        refresh_box: RefreshBoxResponse {
+            box_id: refresh_box.get_box().box_id(),
+            creation_height: refresh_box.get_box().creation_height,
+            epoch_length: refresh_contract.epoch_length(),
+            buffer: refresh_contract.buffer(),
+            max_deviation_percent: refresh_contract.max_deviation_percent(),
+            min_data_points: refresh_contract.min_data_points(),
+        },
+    })
+}
+
+pub fn epoch_response(op: &OraclePool, current_height: u32) -> Result<EpochResponse, ApiError> {
+    let pool_box = op.get_pool_box_source().get_pool_box()?;
+    let refresh_box = op.get_refresh_box_source().get_refresh_box()?;
+    let epoch_counter = pool_box.epoch_counter();
+    let pool_box_creation_height = pool_box.get_box().creation_height;
+    let oracle_boxes = op
+        .get_datapoint_boxes_source()
+        .get_oracle_datapoint_boxes()?
+        .iter()
+        .filter(|b| b.epoch_counter() == epoch_counter)
+        .map(OracleBoxResponse::from_posted)
+        .collect();
+    Ok(EpochResponse {
+        current_height,
+        epoch_counter,
+        pool_box_creation_height,
+        next_refresh_possible_at_height: next_refresh_possible_at_height(
+            pool_box_creation_height,
+            refresh_box.contract().epoch_length() as u32,
+        ),
+        min_data_points: refresh_box.contract().min_data_points(),
+        oracle_boxes,
+    })
+}
+
+pub fn update_response(op: &OraclePool) -> Result<UpdateResponse, ApiError> {
+    let update_box = op.get_update_box_source().get_update_box()?;
+    let ballots = op
+        .get_ballot_boxes_source()
+        .get_ballot_boxes()?
+        .iter()
+        .map(|ballot_box| BallotResponse {
+            box_id: ballot_box.get_box().box_id(),
+            owner_address: address_string(ballot_box.ballot_token_owner()),
+            ballot_tokens: *ballot_box.ballot_token().amount.as_u64(),
+            vote: ballot_box.vote_parameters().clone(),
+        })
+        .collect();
+    Ok(UpdateResponse {
+        update_box: UpdateBoxResponse {
+            box_id: update_box.get_box().box_id(),
+            creation_height: update_box.get_box().creation_height,
+            min_votes: update_box.min_votes(),
+        },
+        ballots,
+    })
+}
+
+fn public_key_string(public_key: &ProveDlog) -> String {
+    String::from((*public_key.h).clone())
+}
+
+fn address_string(public_key: ProveDlog) -> String {
+    NetworkAddress::new(
+        ORACLE_CONFIG.oracle_address.network(),
+        &Address::P2Pk(public_key),
+    )
+    .to_base58()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_next_refresh_possible_at_height() {
+        // Matches the refresh condition in `state::process`: the pool box must have been created
+        // before `height - epoch_length`
+        let (pool_box_creation_height, epoch_length) = (1000, 30);
+        let height = next_refresh_possible_at_height(pool_box_creation_height, epoch_length);
+        assert_eq!(height, 1031);
+        assert!(pool_box_creation_height < height - epoch_length);
+        assert!(pool_box_creation_height >= height - 1 - epoch_length);
+    }
+}
@@ -3,7 +3,10 @@ use ergo_lib::ergotree_ir::chain::address::NetworkPrefix;
 pub mod bootstrap;
 pub mod extract_reward_tokens;
 pub mod prepare_update;
+pub mod print_action_history;
 pub mod print_reward_tokens;
+pub mod sign_offline;
+pub mod submit_tx;
 pub mod transfer_oracle_token;
 pub mod update_pool;
 pub mod vote_update_pool;
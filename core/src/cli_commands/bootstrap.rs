@@ -42,7 +42,8 @@ use crate::{
             UpdateContract, UpdateContractError, UpdateContractInputs, UpdateContractParameters,
         },
     },
-    datapoint_source::PredefinedDataPointSource,
+    datapoint_source::{ExternalScript, PredefinedDataPointSource},
+    dry_run::{dry_run, DryRunSigner},
     node_interface::{assert_wallet_unlocked, SignTransaction, SubmitTransaction},
     oracle_config::{OracleConfig, TokenIds},
     oracle_config::{OracleConfigError, BASE_FEE},
@@ -73,17 +74,30 @@ pub fn bootstrap(config_file_name: String) -> Result<(), BootstrapError> {
 
     let change_address = AddressEncoder::unchecked_parse_address_from_str(&change_address_str)?;
     let erg_value_per_box = config.oracle_contract_parameters.min_storage_rent;
+    let dry_run_signer = dry_run().map(|dry_run| DryRunSigner {
+        dry_run,
+        network_prefix: config.oracle_address.network(),
+    });
+    let (tx_signer, submit_tx): (&dyn SignTransaction, &dyn SubmitTransaction) =
+        match &dry_run_signer {
+            Some(signer) => (signer, signer),
+            None => (&node, &node),
+        };
     let input = BootstrapInput {
         config,
         wallet: &node as &dyn WalletDataSource,
-        tx_signer: &node as &dyn SignTransaction,
-        submit_tx: &node as &dyn SubmitTransaction,
+        tx_signer,
+        submit_tx,
         tx_fee: *BASE_FEE,
         erg_value_per_box,
         change_address,
         height: node.current_block_height()? as u32,
     };
     let oracle_config = perform_bootstrap_chained_transaction(input)?;
+    if dry_run_signer.is_some() {
+        info!("Bootstrap dry run complete, no config file written");
+        return Ok(());
+    }
     info!("Bootstrap chain-transaction complete");
     let s = serde_yaml::to_string(&oracle_config)?;
     let mut file = std::fs::File::create(crate::oracle_config::DEFAULT_CONFIG_FILE_NAME)?;
@@ -541,7 +555,7 @@ pub struct BootstrapConfig {
     pub node_api_key: String,
     pub core_api_port: u16,
     pub data_point_source: Option<PredefinedDataPointSource>,
-    pub data_point_source_custom_script: Option<String>,
+    pub data_point_source_custom_script: Option<ExternalScript>,
     pub oracle_address: NetworkAddress,
     pub base_fee: u64,
 }
@@ -2,14 +2,11 @@ use std::convert::TryInto;
 
 use derive_more::From;
 use ergo_lib::{
-    chain::{
-        ergo_box::box_builder::{ErgoBoxCandidateBuilder, ErgoBoxCandidateBuilderError},
-        transaction::unsigned::UnsignedTransaction,
-    },
+    chain::ergo_box::box_builder::{ErgoBoxCandidateBuilder, ErgoBoxCandidateBuilderError},
     ergotree_interpreter::sigma_protocol::prover::ContextExtension,
     ergotree_ir::{
         chain::{
-            address::{Address, AddressEncoder, AddressEncoderError},
+            address::{Address, AddressEncoder, AddressEncoderError, NetworkPrefix},
             token::Token,
         },
         serialization::SigmaParsingError,
@@ -23,11 +20,14 @@ use ergo_node_interface::node_interface::NodeError;
 use thiserror::Error;
 
 use crate::{
+    actions::ExtractRewardTokensAction,
     box_kind::{
         make_collected_oracle_box_candidate, make_oracle_box_candidate, OracleBox, OracleBoxWrapper,
     },
     cli_commands::ergo_explorer_transaction_link,
-    node_interface::{current_block_height, get_wallet_status, sign_and_submit_transaction},
+    dry_run::{dry_run, DryRunError},
+    node_interface::{current_block_height, sign_and_submit_transaction},
+    offline_signing::{export_path, export_unsigned_tx, OfflineSigningError},
     oracle_config::BASE_FEE,
     oracle_state::{LocalDatapointBoxSource, StageError},
     wallet::{WalletDataError, WalletDataSource},
@@ -61,6 +61,10 @@ pub enum ExtractRewardTokensActionError {
     Io(std::io::Error),
     #[error("WalletData error: {0}")]
     WalletData(WalletDataError),
+    #[error("{0}")]
+    DryRun(DryRunError),
+    #[error("{0}")]
+    OfflineSigning(OfflineSigningError),
 }
 
 pub fn extract_reward_tokens(
@@ -68,32 +72,28 @@ pub fn extract_reward_tokens(
     local_datapoint_box_source: &dyn LocalDatapointBoxSource,
     rewards_destination_str: String,
 ) -> Result<(), ExtractRewardTokensActionError> {
-    let rewards_destination =
-        AddressEncoder::unchecked_parse_network_address_from_str(&rewards_destination_str)?;
-    let network_prefix = rewards_destination.network();
-
-    let change_address_str = get_wallet_status()?
-        .change_address
-        .ok_or(ExtractRewardTokensActionError::NoChangeAddressSetInNode)?;
-
-    let change_address =
-        AddressEncoder::new(network_prefix).parse_address_from_str(&change_address_str)?;
-    let (unsigned_tx, num_reward_tokens) = build_extract_reward_tokens_tx(
-        local_datapoint_box_source,
+    let (action, network_prefix) = prepare_extract_reward_tokens_action(
         wallet,
-        rewards_destination.address(),
-        current_block_height()? as u32,
-        change_address,
+        local_datapoint_box_source,
+        &rewards_destination_str,
     )?;
+    if let Some(dry_run) = dry_run() {
+        dry_run.write_unsigned_tx("Extract reward tokens", &action.tx, network_prefix)?;
+        return Ok(());
+    }
+    if let Some(path) = export_path() {
+        export_unsigned_tx(path, "Extract reward tokens", &action.tx, network_prefix)?;
+        return Ok(());
+    }
 
     println!(
         "YOU WILL BE TRANSFERRING {} REWARD TOKENS TO {}. TYPE 'YES' TO INITIATE THE TRANSACTION.",
-        num_reward_tokens, rewards_destination_str
+        action.num_reward_tokens, rewards_destination_str
     );
     let mut input = String::new();
     std::io::stdin().read_line(&mut input)?;
     if input.trim() == "YES" {
-        let tx_id_str = sign_and_submit_transaction(&unsigned_tx)?;
+        let tx_id_str = sign_and_submit_transaction(&action.tx)?;
         println!(
             "Transaction made. Check status here: {}",
             ergo_explorer_transaction_link(tx_id_str, network_prefix)
@@ -104,13 +104,52 @@ pub fn extract_reward_tokens(
     Ok(())
 }
 
+/// Builds the action without asking for a confirmation, for the REST API. It is submitted like
+/// the pool actions.
+pub fn build_extract_reward_tokens_action(
+    wallet: &dyn WalletDataSource,
+    local_datapoint_box_source: &dyn LocalDatapointBoxSource,
+    rewards_destination_str: &str,
+) -> Result<ExtractRewardTokensAction, ExtractRewardTokensActionError> {
+    prepare_extract_reward_tokens_action(
+        wallet,
+        local_datapoint_box_source,
+        rewards_destination_str,
+    )
+    .map(|(action, _)| action)
+}
+
+fn prepare_extract_reward_tokens_action(
+    wallet: &dyn WalletDataSource,
+    local_datapoint_box_source: &dyn LocalDatapointBoxSource,
+    rewards_destination_str: &str,
+) -> Result<(ExtractRewardTokensAction, NetworkPrefix), ExtractRewardTokensActionError> {
+    let rewards_destination =
+        AddressEncoder::unchecked_parse_network_address_from_str(rewards_destination_str)?;
+    let network_prefix = rewards_destination.network();
+
+    let change_address_str = crate::wallet::change_address()?
+        .ok_or(ExtractRewardTokensActionError::NoChangeAddressSetInNode)?;
+
+    let change_address =
+        AddressEncoder::new(network_prefix).parse_address_from_str(&change_address_str)?;
+    let action = build_extract_reward_tokens_tx(
+        local_datapoint_box_source,
+        wallet,
+        rewards_destination.address(),
+        current_block_height()? as u32,
+        change_address,
+    )?;
+    Ok((action, network_prefix))
+}
+
 fn build_extract_reward_tokens_tx(
     local_datapoint_box_source: &dyn LocalDatapointBoxSource,
     wallet: &dyn WalletDataSource,
     rewards_destination: Address,
     height: u32,
     change_address: Address,
-) -> Result<(UnsignedTransaction, u64), ExtractRewardTokensActionError> {
+) -> Result<ExtractRewardTokensAction, ExtractRewardTokensActionError> {
     let in_oracle_box = local_datapoint_box_source
         .get_local_oracle_datapoint_box()?
         .ok_or(ExtractRewardTokensActionError::NoLocalDatapointBox)?;
@@ -127,9 +166,9 @@ fn build_extract_reward_tokens_tx(
             token_id: in_oracle_box.reward_token().token_id.clone(),
             amount: 1.try_into().unwrap(),
         };
-        let oracle_box_candidate =
+        let (oracle_box_candidate, datapoint) =
             if let OracleBoxWrapper::Posted(ref posted_oracle_box) = in_oracle_box {
-                make_oracle_box_candidate(
+                let candidate = make_oracle_box_candidate(
                     posted_oracle_box.contract(),
                     posted_oracle_box.public_key(),
                     posted_oracle_box.rate() as i64,
@@ -138,16 +177,18 @@ fn build_extract_reward_tokens_tx(
                     single_reward_token,
                     posted_oracle_box.get_box().value,
                     height,
-                )?
+                )?;
+                (candidate, posted_oracle_box.rate() as i64)
             } else {
-                make_collected_oracle_box_candidate(
+                let candidate = make_collected_oracle_box_candidate(
                     in_oracle_box.contract(),
                     in_oracle_box.public_key(),
                     in_oracle_box.oracle_token(),
                     single_reward_token,
                     in_oracle_box.get_box().value,
                     height,
-                )?
+                )?;
+                (candidate, 0)
             };
 
         // Build box to hold extracted tokens
@@ -188,7 +229,12 @@ fn build_extract_reward_tokens_tx(
         };
         tx_builder.set_context_extension(in_oracle_box.get_box().box_id(), ctx_ext);
         let tx = tx_builder.build()?;
-        Ok((tx, num_reward_tokens - 1))
+        Ok(ExtractRewardTokensAction {
+            tx,
+            datapoint,
+            num_reward_tokens: num_reward_tokens - 1,
+            fee: *BASE_FEE,
+        })
     } else {
         Err(ExtractRewardTokensActionError::IncorrectDestinationAddress)
     }
@@ -254,7 +300,7 @@ mod tests {
         let wallet_mock = WalletDataMock {
             unspent_boxes: vec![wallet_unspent_box],
         };
-        let (tx, num_reward_tokens) = build_extract_reward_tokens_tx(
+        let action = build_extract_reward_tokens_tx(
             &local_datapoint_box_source,
             &wallet_mock,
             change_address.clone(),
@@ -263,7 +309,9 @@ mod tests {
         )
         .unwrap();
 
-        assert_eq!(num_reward_tokens, num_reward_tokens_in_box - 1);
+        assert_eq!(action.num_reward_tokens, num_reward_tokens_in_box - 1);
+        assert_eq!(action.datapoint, 200);
+        let tx = action.tx;
         let mut possible_input_boxes = vec![local_datapoint_box_source
             .get_local_oracle_datapoint_box()
             .unwrap()
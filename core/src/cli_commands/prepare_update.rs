@@ -42,6 +42,7 @@ use crate::{
             UpdateContract, UpdateContractError, UpdateContractInputs, UpdateContractParameters,
         },
     },
+    dry_run::{dry_run, DryRunSigner},
     node_interface::{new_node_interface, SignTransaction, SubmitTransaction},
     oracle_config::{OracleConfig, BASE_FEE, ORACLE_CONFIG},
     serde::{OracleConfigSerde, SerdeConversionError, UpdateBootstrapConfigSerde},
@@ -79,11 +80,20 @@ pub fn prepare_update(config_file_name: String) -> Result<(), PrepareUpdateError
             .ok_or(PrepareUpdateError::NoChangeAddressSetInNode)?,
     )?;
     let config = UpdateBootstrapConfig::try_from(config_serde)?;
+    let dry_run_signer = dry_run().map(|dry_run| DryRunSigner {
+        dry_run,
+        network_prefix: ORACLE_CONFIG.oracle_address.network(),
+    });
+    let (tx_signer, submit_tx): (&dyn SignTransaction, &dyn SubmitTransaction) =
+        match &dry_run_signer {
+            Some(signer) => (signer, signer),
+            None => (&node_interface, &node_interface),
+        };
     let update_bootstrap_input = PrepareUpdateInput {
         config: config.clone(),
         wallet: &node_interface,
-        tx_signer: &node_interface,
-        submit_tx: &node_interface,
+        tx_signer,
+        submit_tx,
         tx_fee: *BASE_FEE,
         erg_value_per_box: *BASE_FEE,
         change_address,
@@ -96,6 +106,10 @@ pub fn prepare_update(config_file_name: String) -> Result<(), PrepareUpdateError
     };
 
     let new_config = perform_update_chained_transaction(update_bootstrap_input)?;
+    if dry_run_signer.is_some() {
+        info!("Update dry run complete, no config file written");
+        return Ok(());
+    }
 
     info!("Update chain-transaction complete");
     info!("Writing new config file to oracle_config_updated.yaml");
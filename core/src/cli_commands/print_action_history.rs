@@ -0,0 +1,35 @@
+use crate::action_history::{ActionHistory, ActionHistoryError};
+use crate::oracle_config::get_action_history_path;
+
+/// Prints the last `limit` actions recorded by the main loop, newest first
+pub fn print_action_history(limit: u32) -> Result<(), ActionHistoryError> {
+    let history = ActionHistory::open(&get_action_history_path())?;
+    let records = history.recent(limit)?;
+    if records.is_empty() {
+        println!("No actions recorded");
+        return Ok(());
+    }
+    println!(
+        "{:>6} {:>8} {:<30} {:<52} {:>20} {:>10} {:<18} {:<64} error",
+        "id", "height", "action", "oracle", "datapoint", "fee", "status", "tx id"
+    );
+    for record in records {
+        let status = match record.confirmation_height {
+            Some(height) => format!("{} ({})", record.status.as_str(), height),
+            None => record.status.as_str().to_string(),
+        };
+        println!(
+            "{:>6} {:>8} {:<30} {:<52} {:>20} {:>10} {:<18} {:<64} {}",
+            record.id,
+            record.height,
+            record.action,
+            record.oracle_address,
+            record.datapoint,
+            record.fee,
+            status,
+            record.tx_id.unwrap_or_default(),
+            record.error.unwrap_or_default()
+        );
+    }
+    Ok(())
+}
@@ -0,0 +1,37 @@
+use std::path::Path;
+
+use ergo_lib::wallet::Wallet;
+
+use crate::local_wallet::{load_secrets, prompt_password};
+use crate::offline_signing::{OfflineSigningError, UnsignedTxBundle};
+
+/// Signs a bundle exported with `--export-unsigned` with a local wallet, without any node. The
+/// signed tx is written to `signed_tx_file`, to be submitted with the `submit` command.
+pub fn sign_offline(
+    bundle_file: &Path,
+    secret_file: &Path,
+    signed_tx_file: &Path,
+) -> Result<(), OfflineSigningError> {
+    let bundle: UnsignedTxBundle = serde_json::from_str(&std::fs::read_to_string(bundle_file)?)?;
+    println!("{}", bundle.summary()?);
+    let password = prompt_password(
+        "Keystore or mnemonic password (leave empty if none or if the secret file has a secret key): ",
+    )?;
+    let wallet = Wallet::from_secrets(load_secrets(secret_file, &password)?);
+
+    println!("YOU WILL BE SIGNING THE TRANSACTION ABOVE. TYPE 'YES' TO SIGN IT.");
+    let mut input = String::new();
+    std::io::stdin().read_line(&mut input)?;
+    if input.trim() == "YES" {
+        let tx = bundle.sign(&wallet)?;
+        std::fs::write(signed_tx_file, serde_json::to_string_pretty(&tx)?)?;
+        println!(
+            "Signed tx {} written to {}, submit it with the submit command",
+            String::from(tx.id()),
+            signed_tx_file.display()
+        );
+    } else {
+        println!("Aborting the signing.")
+    }
+    Ok(())
+}
@@ -0,0 +1,19 @@
+use std::path::Path;
+
+use ergo_lib::chain::transaction::Transaction;
+
+use crate::{
+    cli_commands::ergo_explorer_transaction_link, node_interface::submit_transaction,
+    offline_signing::OfflineSigningError, oracle_config::ORACLE_CONFIG,
+};
+
+/// Submits a tx signed with the `sign-offline` command
+pub fn submit_tx(signed_tx_file: &Path) -> Result<(), OfflineSigningError> {
+    let tx: Transaction = serde_json::from_str(&std::fs::read_to_string(signed_tx_file)?)?;
+    let tx_id_str = submit_transaction(&tx)?;
+    println!(
+        "Transaction submitted. Check status here: {}",
+        ergo_explorer_transaction_link(tx_id_str, ORACLE_CONFIG.oracle_address.network())
+    );
+    Ok(())
+}
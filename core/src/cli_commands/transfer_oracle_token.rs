@@ -24,7 +24,9 @@ use crate::{
         make_collected_oracle_box_candidate, make_oracle_box_candidate, OracleBox, OracleBoxWrapper,
     },
     cli_commands::ergo_explorer_transaction_link,
-    node_interface::{current_block_height, get_wallet_status, sign_and_submit_transaction},
+    dry_run::{dry_run, DryRunError},
+    node_interface::{current_block_height, sign_and_submit_transaction},
+    offline_signing::{export_path, export_unsigned_tx, OfflineSigningError},
     oracle_config::BASE_FEE,
     oracle_state::{LocalDatapointBoxSource, StageError},
     wallet::{WalletDataError, WalletDataSource},
@@ -58,6 +60,10 @@ pub enum TransferOracleTokenActionError {
     Io(std::io::Error),
     #[error("WalletData error: {0}")]
     WalletData(WalletDataError),
+    #[error("{0}")]
+    DryRun(DryRunError),
+    #[error("{0}")]
+    OfflineSigning(OfflineSigningError),
 }
 
 pub fn transfer_oracle_token(
@@ -68,8 +74,7 @@ pub fn transfer_oracle_token(
     let rewards_destination =
         AddressEncoder::unchecked_parse_network_address_from_str(&rewards_destination_str)?;
 
-    let change_address_str = get_wallet_status()?
-        .change_address
+    let change_address_str = crate::wallet::change_address()?
         .ok_or(TransferOracleTokenActionError::NoChangeAddressSetInNode)?;
 
     let (change_address, network_prefix) = {
@@ -83,6 +88,14 @@ pub fn transfer_oracle_token(
         current_block_height()? as u32,
         change_address,
     )?;
+    if let Some(dry_run) = dry_run() {
+        dry_run.write_unsigned_tx("Transfer oracle token", &unsigned_tx, network_prefix)?;
+        return Ok(());
+    }
+    if let Some(path) = export_path() {
+        export_unsigned_tx(path, "Transfer oracle token", &unsigned_tx, network_prefix)?;
+        return Ok(());
+    }
 
     println!(
         "YOU WILL BE TRANSFERRING YOUR ORACLE TOKEN TO {}. TYPE 'YES' TO INITIATE THE TRANSACTION.",
@@ -26,7 +26,9 @@ use crate::{
     box_kind::{make_pool_box_candidate, BallotBox, PoolBox, PoolBoxWrapper, VoteBallotBoxWrapper},
     cli_commands::ergo_explorer_transaction_link,
     contracts::pool::PoolContract,
-    node_interface::{current_block_height, get_wallet_status, sign_and_submit_transaction},
+    dry_run::{dry_run, DryRunError},
+    node_interface::{current_block_height, sign_and_submit_transaction},
+    offline_signing::{export_path, export_unsigned_tx, OfflineSigningError},
     oracle_config::{CastBallotBoxVoteParameters, OracleConfig, BASE_FEE, ORACLE_CONFIG},
     oracle_state::{OraclePool, PoolBoxSource, StageError, UpdateBoxSource, VoteBallotBoxesSource},
     wallet::{WalletDataError, WalletDataSource},
@@ -66,6 +68,10 @@ pub enum UpdatePoolError {
     NoUsableWalletBoxes,
     #[error("WalletData error: {0}")]
     WalletData(WalletDataError),
+    #[error("Update pool: {0}")]
+    DryRun(DryRunError),
+    #[error("Update pool: {0}")]
+    OfflineSigning(OfflineSigningError),
 }
 
 pub fn update_pool(
@@ -77,9 +83,8 @@ pub fn update_pool(
     let s = std::fs::read_to_string("oracle_config_updated.yaml")?;
     let new_oracle_config: OracleConfig = serde_yaml::from_str(&s)?;
     let wallet = crate::wallet::WalletData {};
-    let change_address_str = get_wallet_status()?
-        .change_address
-        .ok_or(UpdatePoolError::NoChangeAddressSetInNode)?;
+    let change_address_str =
+        crate::wallet::change_address()?.ok_or(UpdatePoolError::NoChangeAddressSetInNode)?;
 
     let (change_address, network_prefix) = {
         let a = AddressEncoder::unchecked_parse_network_address_from_str(&change_address_str)?;
@@ -121,6 +126,14 @@ pub fn update_pool(
         change_address,
     )?;
 
+    if let Some(dry_run) = dry_run() {
+        dry_run.write_unsigned_tx("Update pool", &tx.spending_tx, network_prefix)?;
+        return Ok(());
+    }
+    if let Some(path) = export_path() {
+        export_unsigned_tx(path, "Update pool", &tx.spending_tx, network_prefix)?;
+        return Ok(());
+    }
     let tx_id_str = sign_and_submit_transaction(&tx.spending_tx)?;
     println!(
         "Update pool box transaction submitted: view here, {}",
@@ -24,7 +24,9 @@ use crate::{
     contracts::ballot::{
         BallotContract, BallotContractError, BallotContractInputs, BallotContractParameters,
     },
-    node_interface::{current_block_height, get_wallet_status, sign_and_submit_transaction},
+    dry_run::{dry_run, DryRunError},
+    node_interface::{current_block_height, sign_and_submit_transaction},
+    offline_signing::{export_path, export_unsigned_tx, OfflineSigningError},
     oracle_config::{TokenIds, BASE_FEE, ORACLE_CONFIG},
     oracle_state::{LocalBallotBoxSource, StageError},
     wallet::{WalletDataError, WalletDataSource},
@@ -58,6 +60,10 @@ pub enum VoteUpdatePoolError {
     BallotContract(BallotContractError),
     #[error("WalletData error: {0}")]
     WalletData(WalletDataError),
+    #[error("Vote update pool: {0}")]
+    DryRun(DryRunError),
+    #[error("Vote update pool: {0}")]
+    OfflineSigning(OfflineSigningError),
 }
 
 pub fn vote_update_pool(
@@ -68,9 +74,8 @@ pub fn vote_update_pool(
     reward_token_amount: u32,
     update_box_creation_height: u32,
 ) -> Result<(), VoteUpdatePoolError> {
-    let change_address_str = get_wallet_status()?
-        .change_address
-        .ok_or(VoteUpdatePoolError::NoChangeAddressSetInNode)?;
+    let change_address_str =
+        crate::wallet::change_address()?.ok_or(VoteUpdatePoolError::NoChangeAddressSetInNode)?;
 
     let change_network_address =
         AddressEncoder::unchecked_parse_network_address_from_str(&change_address_str)?;
@@ -109,6 +114,14 @@ pub fn vote_update_pool(
             change_network_address.address(),
         )?
     };
+    if let Some(dry_run) = dry_run() {
+        dry_run.write_unsigned_tx("Vote update pool", &unsigned_tx, network_prefix)?;
+        return Ok(());
+    }
+    if let Some(path) = export_path() {
+        export_unsigned_tx(path, "Vote update pool", &unsigned_tx, network_prefix)?;
+        return Ok(());
+    }
     println!(
         "YOU WILL BE CASTING A VOTE FOR THE FOLLOWING ITEMS:\
            - Hash of new pool box address: {}\
@@ -1,20 +1,87 @@
 //! Datapoint sources for oracle-core
 mod ada_usd;
+mod aggregator;
+mod backoff;
+mod coincap;
+mod derived;
 mod erg_usd;
 mod erg_xau;
+mod ergodex;
+mod external_script;
+mod http_json;
+mod kraken;
+mod mexc;
+mod prefetch;
+mod record;
+pub mod registry;
+use std::thread;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
 use derive_more::From;
 use thiserror::Error;
 
+use self::backoff::Backoff;
+use self::registry::{DataPointSourceRegistryError, NamedDataPointSourceConfig};
+
+/// A datapoint together with where and when it was observed
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DataPoint {
+    pub value: i64,
+    /// Unix time (in seconds) when the upstream source observed the value, if it reports it
+    pub observed_at: Option<u64>,
+    /// Identifier of the source the value came from
+    pub source: String,
+}
+
+impl DataPoint {
+    /// Time elapsed since the value was observed. `None` if the source didn't report it.
+    pub fn age(&self) -> Option<Duration> {
+        let observed_at = UNIX_EPOCH + Duration::from_secs(self.observed_at?);
+        Some(
+            SystemTime::now()
+                .duration_since(observed_at)
+                .unwrap_or_default(),
+        )
+    }
+}
+
 pub trait DataPointSource: std::fmt::Debug {
     fn get_datapoint(&self) -> Result<i64, DataPointSourceError>;
 
+    /// Datapoint with its metadata. Sources which know when the value was observed should
+    /// override this.
+    fn get_datapoint_with_metadata(&self) -> Result<DataPoint, DataPointSourceError> {
+        Ok(DataPoint {
+            value: self.get_datapoint()?,
+            observed_at: None,
+            source: format!("{:?}", self),
+        })
+    }
+
     fn get_datapoint_retry(&self, retries: u8) -> Result<i64, DataPointSourceError> {
+        self.get_datapoint_with_metadata_retry(retries)
+            .map(|datapoint| datapoint.value)
+    }
+
+    fn get_datapoint_with_metadata_retry(
+        &self,
+        retries: u8,
+    ) -> Result<DataPoint, DataPointSourceError> {
+        let mut backoff = Backoff::default();
         let mut last_error = None;
-        for _ in 0..retries {
-            match self.get_datapoint() {
+        for attempt in 1..=retries {
+            match self.get_datapoint_with_metadata() {
                 Ok(datapoint) => return Ok(datapoint),
                 Err(err) => {
-                    log::warn!("Failed to get datapoint from source: {}, retrying ...", err);
+                    if attempt < retries {
+                        let delay = backoff.next_delay(&err);
+                        log::warn!(
+                            "Failed to get datapoint from source: {}, retrying in {:?} ...",
+                            err,
+                            delay
+                        );
+                        thread::sleep(delay);
+                    }
                     last_error = Some(err)
                 }
             }
@@ -33,43 +100,41 @@ pub enum DataPointSourceError {
     JsonParse(json::Error),
     #[error("Missing JSON field")]
     JsonMissingField,
-}
-
-#[derive(Debug, From, Error)]
-pub enum ExternalScriptError {
-    #[error("external script child process error: {0}")]
-    ChildProcess(std::io::Error),
-    #[error("String from bytes error: {0}")]
-    StringFromBytes(std::string::FromUtf8Error),
-    #[error("Parse i64 from string error: {0}")]
-    ParseInt(std::num::ParseIntError),
-}
-
-#[derive(Debug, Clone)]
-pub struct ExternalScript(String);
-
-impl ExternalScript {
-    pub fn new(script_name: String) -> Self {
-        ExternalScript(script_name)
-    }
-}
-
-impl DataPointSource for ExternalScript {
-    fn get_datapoint(&self) -> Result<i64, DataPointSourceError> {
-        let script_output = std::process::Command::new(&self.0)
-            .output()
-            .map_err(ExternalScriptError::from)?;
-        let datapoint_str =
-            String::from_utf8(script_output.stdout).map_err(ExternalScriptError::from)?;
-        datapoint_str
-            .parse()
-            .map_err(|e| DataPointSourceError::from(ExternalScriptError::from(e)))
-    }
+    #[error("Invalid JSON path: {0}")]
+    #[from(ignore)]
+    InvalidJsonPath(String),
+    #[error("Unexpected API response: {0}")]
+    #[from(ignore)]
+    UnexpectedResponse(String),
+    #[error("Rate limited by the API, retry after {retry_after:?}")]
+    #[from(ignore)]
+    RateLimited { retry_after: Option<Duration> },
+    #[error("Replayed error: {0}")]
+    #[from(ignore)]
+    Replayed(String),
+    #[error("No more recorded datapoints to replay")]
+    ReplayExhausted,
+    #[error("Not enough sources agree on the datapoint: found {found}, required {required}")]
+    NotEnoughAgreeingSources { found: usize, required: usize },
+    #[error("Arithmetic overflow while deriving the datapoint")]
+    ArithmeticOverflow,
+    #[error("Division by zero while deriving the datapoint")]
+    DivisionByZero,
 }
 
 pub use ada_usd::NanoAdaUsd;
+pub use aggregator::AggregateDataPointSourceConfig;
+pub use coincap::CoinCapNanoErgUsd;
+pub use derived::DerivedDataPointSourceConfig;
 pub use erg_usd::NanoErgUsd;
 pub use erg_xau::NanoErgXau;
+pub use ergodex::ErgoDexNanoErgSigUsd;
+pub use external_script::{ExternalScript, ExternalScriptError};
+pub use http_json::HttpJsonDataPointSource;
+pub use kraken::KrakenNanoErgUsd;
+pub use mexc::MexcNanoErgUsd;
+pub use prefetch::PrefetchingDataPointSource;
+pub use record::{RecordingDataPointSource, ReplayDataPointSource};
 
 #[derive(serde::Serialize, serde::Deserialize, Debug, Copy, Clone)]
 #[allow(clippy::enum_variant_names)]
@@ -77,14 +142,66 @@ pub enum PredefinedDataPointSource {
     NanoErgUsd,
     NanoErgXau,
     NanoAdaUsd,
+    CoinCapNanoErgUsd,
+    KrakenNanoErgUsd,
+    MexcNanoErgUsd,
+    ErgoDexNanoErgSigUsd,
 }
 
-impl DataPointSource for PredefinedDataPointSource {
-    fn get_datapoint(&self) -> Result<i64, DataPointSourceError> {
+impl PredefinedDataPointSource {
+    pub const ALL: [PredefinedDataPointSource; 7] = [
+        PredefinedDataPointSource::NanoErgUsd,
+        PredefinedDataPointSource::NanoErgXau,
+        PredefinedDataPointSource::NanoAdaUsd,
+        PredefinedDataPointSource::CoinCapNanoErgUsd,
+        PredefinedDataPointSource::KrakenNanoErgUsd,
+        PredefinedDataPointSource::MexcNanoErgUsd,
+        PredefinedDataPointSource::ErgoDexNanoErgSigUsd,
+    ];
+
+    fn source(&self) -> &'static dyn DataPointSource {
         match self {
-            PredefinedDataPointSource::NanoAdaUsd => NanoAdaUsd.get_datapoint(),
-            PredefinedDataPointSource::NanoErgUsd => NanoErgUsd.get_datapoint(),
-            PredefinedDataPointSource::NanoErgXau => NanoErgXau.get_datapoint(),
+            PredefinedDataPointSource::NanoAdaUsd => &NanoAdaUsd,
+            PredefinedDataPointSource::NanoErgUsd => &NanoErgUsd,
+            PredefinedDataPointSource::NanoErgXau => &NanoErgXau,
+            PredefinedDataPointSource::CoinCapNanoErgUsd => &CoinCapNanoErgUsd,
+            PredefinedDataPointSource::KrakenNanoErgUsd => &KrakenNanoErgUsd,
+            PredefinedDataPointSource::MexcNanoErgUsd => &MexcNanoErgUsd,
+            PredefinedDataPointSource::ErgoDexNanoErgSigUsd => &ErgoDexNanoErgSigUsd,
         }
     }
 }
+
+impl DataPointSource for PredefinedDataPointSource {
+    fn get_datapoint(&self) -> Result<i64, DataPointSourceError> {
+        self.source().get_datapoint()
+    }
+
+    fn get_datapoint_with_metadata(&self) -> Result<DataPoint, DataPointSourceError> {
+        self.source().get_datapoint_with_metadata()
+    }
+}
+
+/// Config file description of a single datapoint source
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone)]
+pub enum DataPointSourceConfig {
+    Predefined(PredefinedDataPointSource),
+    ExternalScript(ExternalScript),
+    HttpJson(HttpJsonDataPointSource),
+    Named(NamedDataPointSourceConfig),
+    Derived(Box<DerivedDataPointSourceConfig>),
+}
+
+impl DataPointSourceConfig {
+    pub fn build(
+        &self,
+    ) -> Result<Box<dyn DataPointSource + Send + Sync>, DataPointSourceRegistryError> {
+        Ok(match self {
+            DataPointSourceConfig::Predefined(source) => Box::new(*source),
+            DataPointSourceConfig::ExternalScript(script) => Box::new(script.clone()),
+            DataPointSourceConfig::HttpJson(source) => Box::new(source.clone()),
+            DataPointSourceConfig::Named(named) => named.build()?,
+            DataPointSourceConfig::Derived(derived) => Box::new(derived.build()?),
+        })
+    }
+}
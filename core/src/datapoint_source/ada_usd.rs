@@ -1,12 +1,19 @@
 //! Obtains the lovelace per 1 USD rate.
 
-use super::{DataPointSource, DataPointSourceError};
+use json::JsonValue;
+
+use super::http_json::{extract_f64, fetch_json, invert_price};
+use super::{DataPoint, DataPointSource, DataPointSourceError};
 
 #[derive(Debug, Clone)]
 pub struct NanoAdaUsd;
 
 impl DataPointSource for NanoAdaUsd {
     fn get_datapoint(&self) -> Result<i64, DataPointSourceError> {
+        get_nanoada_usd_price().map(|datapoint| datapoint.value)
+    }
+
+    fn get_datapoint_with_metadata(&self) -> Result<DataPoint, DataPointSourceError> {
         get_nanoada_usd_price()
     }
 }
@@ -15,7 +22,7 @@ impl DataPointSource for NanoAdaUsd {
 static LOVELACE_CONVERSION: f64 = 1000000.0;
 
 static CG_RATE_URL: &str =
-    "https://api.coingecko.com/api/v3/simple/price?ids=cardano&vs_currencies=USD";
+    "https://api.coingecko.com/api/v3/simple/price?ids=cardano&vs_currencies=USD&include_last_updated_at=true";
 
 /// Get the Ada/USD price from the Lovelaces per 1 USD datapoint price
 pub fn generate_current_price(datapoint: u64) -> f64 {
@@ -24,15 +31,18 @@ pub fn generate_current_price(datapoint: u64) -> f64 {
 
 /// Acquires the price of Ada in USD from CoinGecko, convert it
 /// into Lovelaces per 1 USD, and return it.
-fn get_nanoada_usd_price() -> Result<i64, DataPointSourceError> {
-    let resp = reqwest::blocking::Client::new().get(CG_RATE_URL).send()?;
-    let price_json = json::parse(&resp.text()?)?;
-    if let Some(p) = price_json["cardano"]["usd"].as_f64() {
-        let lovelace_price = (1.0 / p) * LOVELACE_CONVERSION;
-        Ok(lovelace_price as i64)
-    } else {
-        Err(DataPointSourceError::JsonMissingField)
-    }
+fn get_nanoada_usd_price() -> Result<DataPoint, DataPointSourceError> {
+    let price_json = fetch_json(CG_RATE_URL)?;
+    price_from_response(&price_json)
+}
+
+fn price_from_response(price_json: &JsonValue) -> Result<DataPoint, DataPointSourceError> {
+    let p = extract_f64(price_json, "cardano.usd")?;
+    Ok(DataPoint {
+        value: invert_price(p, LOVELACE_CONVERSION)?,
+        observed_at: price_json["cardano"]["last_updated_at"].as_u64(),
+        source: "CoinGecko ADA/USD".to_string(),
+    })
 }
 
 #[cfg(test)]
@@ -44,4 +54,12 @@ mod tests {
         let n = NanoAdaUsd {};
         assert!(n.get_datapoint().unwrap() > 0);
     }
+
+    #[test]
+    fn test_ada_usd_price_from_fixture() {
+        let price_json = json::parse(include_str!("fixtures/coingecko_ada_usd.json")).unwrap();
+        let datapoint = price_from_response(&price_json).unwrap();
+        assert_eq!(datapoint.value, 4000000);
+        assert_eq!(datapoint.observed_at, Some(1666000000));
+    }
 }
@@ -0,0 +1,360 @@
+//! Combines several datapoint sources into one, dropping failed sources and outliers
+
+use serde::{Deserialize, Serialize};
+
+use super::registry::DataPointSourceRegistryError;
+use super::{DataPoint, DataPointSource, DataPointSourceConfig, DataPointSourceError};
+
+/// How the datapoints which survived the outlier rejection are combined into the final value
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum AggregationMethod {
+    Median,
+    /// Mean weighted by the `weight` of each source
+    WeightedMean,
+    /// Mean after dropping `trim_percent` percent of the lowest and of the highest datapoints
+    TrimmedMean {
+        trim_percent: u32,
+    },
+}
+
+/// Config file description of a single source of an aggregate datapoint source
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WeightedDataPointSourceConfig {
+    pub source: DataPointSourceConfig,
+    #[serde(default = "default_weight")]
+    pub weight: u32,
+}
+
+fn default_weight() -> u32 {
+    1
+}
+
+/// Config file description of an aggregate datapoint source
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AggregateDataPointSourceConfig {
+    pub sources: Vec<WeightedDataPointSourceConfig>,
+    pub method: AggregationMethod,
+    /// Minimum number of sources that must provide a datapoint within the deviation range
+    pub min_sources: usize,
+    /// Maximum deviation (in percent) from the median of all fetched datapoints. Datapoints
+    /// outside of this range are dropped as outliers. Defaults to the `max_deviation_percent` of
+    /// the pool's refresh contract.
+    pub max_deviation_percent: Option<u32>,
+}
+
+impl AggregateDataPointSourceConfig {
+    pub fn build(
+        &self,
+        default_max_deviation_percent: u32,
+    ) -> Result<AggregateDataPointSource, DataPointSourceRegistryError> {
+        Ok(AggregateDataPointSource {
+            sources: self
+                .sources
+                .iter()
+                .map(|s| Ok((s.source.build()?, s.weight)))
+                .collect::<Result<_, DataPointSourceRegistryError>>()?,
+            method: self.method,
+            min_sources: self.min_sources,
+            max_deviation_percent: self
+                .max_deviation_percent
+                .unwrap_or(default_max_deviation_percent),
+        })
+    }
+}
+
+/// Queries every source, drops the failed ones and the outliers and aggregates the rest
+#[derive(Debug)]
+pub struct AggregateDataPointSource {
+    sources: Vec<(Box<dyn DataPointSource + Send + Sync>, u32)>,
+    method: AggregationMethod,
+    min_sources: usize,
+    max_deviation_percent: u32,
+}
+
+impl AggregateDataPointSource {
+    pub fn new(
+        sources: Vec<(Box<dyn DataPointSource + Send + Sync>, u32)>,
+        method: AggregationMethod,
+        min_sources: usize,
+        max_deviation_percent: u32,
+    ) -> Self {
+        AggregateDataPointSource {
+            sources,
+            method,
+            min_sources,
+            max_deviation_percent,
+        }
+    }
+}
+
+impl DataPointSource for AggregateDataPointSource {
+    fn get_datapoint(&self) -> Result<i64, DataPointSourceError> {
+        self.get_datapoint_with_metadata()
+            .map(|datapoint| datapoint.value)
+    }
+
+    /// The observation time of the aggregate is the oldest one among the agreeing sources
+    fn get_datapoint_with_metadata(&self) -> Result<DataPoint, DataPointSourceError> {
+        let datapoints: Vec<(DataPoint, u32)> = self
+            .sources
+            .iter()
+            .filter_map(
+                |(source, weight)| match source.get_datapoint_with_metadata() {
+                    Ok(datapoint) => Some((datapoint, *weight)),
+                    Err(e) => {
+                        log::warn!("Aggregate datapoint source: {:?} failed: {}", source, e);
+                        None
+                    }
+                },
+            )
+            .collect();
+        let agreeing = reject_outliers(datapoints, self.max_deviation_percent);
+        let required = self.min_sources.max(1);
+        if agreeing.len() < required {
+            return Err(DataPointSourceError::NotEnoughAgreeingSources {
+                found: agreeing.len(),
+                required,
+            });
+        }
+        let observed_at = agreeing.iter().filter_map(|(d, _)| d.observed_at).min();
+        let source = agreeing
+            .iter()
+            .map(|(d, _)| d.source.as_str())
+            .collect::<Vec<_>>()
+            .join(", ");
+        let values: Vec<(i64, u32)> = agreeing.iter().map(|(d, w)| (d.value, *w)).collect();
+        Ok(DataPoint {
+            value: aggregate(&values, self.method),
+            observed_at,
+            source: format!("{:?} of [{}]", self.method, source),
+        })
+    }
+}
+
+/// Drops datapoints deviating more than `max_deviation_percent` from the median.
+/// Returns the remaining datapoints sorted by value.
+fn reject_outliers(
+    mut datapoints: Vec<(DataPoint, u32)>,
+    max_deviation_percent: u32,
+) -> Vec<(DataPoint, u32)> {
+    if datapoints.is_empty() {
+        return datapoints;
+    }
+    datapoints.sort_by_key(|(datapoint, _)| datapoint.value);
+    let values: Vec<(i64, u32)> = datapoints.iter().map(|(d, w)| (d.value, *w)).collect();
+    let median = median(&values) as i128;
+    let max_delta = median.abs() * max_deviation_percent as i128 / 100;
+    datapoints
+        .into_iter()
+        .filter(|(datapoint, _)| {
+            let within_range = (datapoint.value as i128 - median).abs() <= max_delta;
+            if !within_range {
+                log::warn!(
+                    "Aggregate datapoint source: dropping outlier {} from {} (median {})",
+                    datapoint.value,
+                    datapoint.source,
+                    median
+                );
+            }
+            within_range
+        })
+        .collect()
+}
+
+/// Median of non-empty datapoints sorted by value
+fn median(sorted: &[(i64, u32)]) -> i64 {
+    let mid = sorted.len() / 2;
+    if sorted.len() % 2 == 0 {
+        ((sorted[mid - 1].0 as i128 + sorted[mid].0 as i128) / 2) as i64
+    } else {
+        sorted[mid].0
+    }
+}
+
+/// Aggregates non-empty datapoints sorted by value
+fn aggregate(sorted: &[(i64, u32)], method: AggregationMethod) -> i64 {
+    match method {
+        AggregationMethod::Median => median(sorted),
+        AggregationMethod::WeightedMean => {
+            let total_weight: i128 = sorted.iter().map(|(_, w)| *w as i128).sum();
+            if total_weight == 0 {
+                return median(sorted);
+            }
+            let weighted_sum: i128 = sorted.iter().map(|(d, w)| *d as i128 * *w as i128).sum();
+            (weighted_sum / total_weight) as i64
+        }
+        AggregationMethod::TrimmedMean { trim_percent } => {
+            // Always keep at least one datapoint
+            let trim = (sorted.len() * trim_percent as usize / 100).min((sorted.len() - 1) / 2);
+            let trimmed = &sorted[trim..sorted.len() - trim];
+            let sum: i128 = trimmed.iter().map(|(d, _)| *d as i128).sum();
+            (sum / trimmed.len() as i128) as i64
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug)]
+    struct MockSource(Option<i64>);
+
+    impl DataPointSource for MockSource {
+        fn get_datapoint(&self) -> Result<i64, DataPointSourceError> {
+            self.0.ok_or(DataPointSourceError::JsonMissingField)
+        }
+    }
+
+    fn make_source(
+        datapoints: &[(Option<i64>, u32)],
+        method: AggregationMethod,
+        min_sources: usize,
+    ) -> AggregateDataPointSource {
+        AggregateDataPointSource::new(
+            datapoints
+                .iter()
+                .map(|(d, w)| {
+                    (
+                        Box::new(MockSource(*d)) as Box<dyn DataPointSource + Send + Sync>,
+                        *w,
+                    )
+                })
+                .collect(),
+            method,
+            min_sources,
+            5,
+        )
+    }
+
+    #[test]
+    fn test_median_drops_failed_and_outliers() {
+        let source = make_source(
+            &[
+                (Some(100), 1),
+                (None, 1),
+                (Some(102), 1),
+                (Some(1000), 1),
+                (Some(98), 1),
+            ],
+            AggregationMethod::Median,
+            3,
+        );
+        assert_eq!(source.get_datapoint().unwrap(), 100);
+    }
+
+    #[test]
+    fn test_oldest_observation_time() {
+        #[derive(Debug)]
+        struct TimedSource(i64, Option<u64>);
+
+        impl DataPointSource for TimedSource {
+            fn get_datapoint(&self) -> Result<i64, DataPointSourceError> {
+                Ok(self.0)
+            }
+
+            fn get_datapoint_with_metadata(&self) -> Result<DataPoint, DataPointSourceError> {
+                Ok(DataPoint {
+                    value: self.0,
+                    observed_at: self.1,
+                    source: "timed".to_string(),
+                })
+            }
+        }
+
+        let source = AggregateDataPointSource::new(
+            vec![
+                (Box::new(TimedSource(100, Some(20))), 1),
+                (Box::new(TimedSource(101, None)), 1),
+                (Box::new(TimedSource(102, Some(30))), 1),
+                // outlier, its observation time must be ignored
+                (Box::new(TimedSource(500, Some(10))), 1),
+            ],
+            AggregationMethod::Median,
+            3,
+            5,
+        );
+        let datapoint = source.get_datapoint_with_metadata().unwrap();
+        assert_eq!(datapoint.value, 101);
+        assert_eq!(datapoint.observed_at, Some(20));
+    }
+
+    #[test]
+    fn test_weighted_mean() {
+        let source = make_source(
+            &[(Some(100), 3), (Some(104), 1)],
+            AggregationMethod::WeightedMean,
+            2,
+        );
+        assert_eq!(source.get_datapoint().unwrap(), 101);
+    }
+
+    #[test]
+    fn test_trimmed_mean() {
+        let source = make_source(
+            &[
+                (Some(100), 1),
+                (Some(101), 1),
+                (Some(102), 1),
+                (Some(103), 1),
+                (Some(99), 1),
+            ],
+            AggregationMethod::TrimmedMean { trim_percent: 20 },
+            3,
+        );
+        assert_eq!(source.get_datapoint().unwrap(), 101);
+    }
+
+    #[test]
+    fn test_config_from_yaml() {
+        let config: AggregateDataPointSourceConfig = serde_yaml::from_str(
+            "
+sources:
+  - source:
+      Predefined: NanoErgUsd
+    weight: 2
+  - source:
+      ExternalScript: ./erg_usd.sh
+method:
+  TrimmedMean:
+    trim_percent: 10
+min_sources: 2
+max_deviation_percent: ~
+",
+        )
+        .unwrap();
+        assert_eq!(config.sources.len(), 2);
+        assert_eq!(config.sources[0].weight, 2);
+        assert_eq!(config.sources[1].weight, 1);
+        assert_eq!(
+            config.method,
+            AggregationMethod::TrimmedMean { trim_percent: 10 }
+        );
+        assert_eq!(config.build(5).unwrap().max_deviation_percent, 5);
+    }
+
+    #[test]
+    fn test_not_enough_agreeing_sources() {
+        let source = make_source(
+            &[(Some(100), 1), (Some(102), 1), (None, 1), (Some(200), 1)],
+            AggregationMethod::Median,
+            3,
+        );
+        assert!(matches!(
+            source.get_datapoint(),
+            Err(DataPointSourceError::NotEnoughAgreeingSources {
+                found: 2,
+                required: 3
+            })
+        ));
+        // At least one source is always required
+        let source = make_source(&[(None, 1), (None, 1)], AggregationMethod::Median, 0);
+        assert!(matches!(
+            source.get_datapoint(),
+            Err(DataPointSourceError::NotEnoughAgreeingSources {
+                found: 0,
+                required: 1
+            })
+        ));
+    }
+}
@@ -0,0 +1,87 @@
+//! Exponential backoff with jitter for retrying failed datapoint fetches
+
+use std::time::Duration;
+
+use rand::Rng;
+
+use super::DataPointSourceError;
+
+#[derive(Debug, Clone)]
+pub(crate) struct Backoff {
+    initial: Duration,
+    max: Duration,
+    attempt: u32,
+}
+
+impl Default for Backoff {
+    fn default() -> Self {
+        Backoff::new(Duration::from_secs(1), Duration::from_secs(30))
+    }
+}
+
+impl Backoff {
+    pub(crate) fn new(initial: Duration, max: Duration) -> Self {
+        Backoff {
+            initial,
+            max,
+            attempt: 0,
+        }
+    }
+
+    /// Delay before the next attempt after `err`. Rate limited requests wait for as long as the
+    /// API asks to, otherwise the delay doubles on every attempt (up to `max`) and a random
+    /// jitter of up to a half of it is subtracted, so that retries of several sources and
+    /// oracles don't line up. Replayed errors are retried right away, the next attempt just
+    /// replays the next record.
+    pub(crate) fn next_delay(&mut self, err: &DataPointSourceError) -> Duration {
+        if matches!(
+            err,
+            DataPointSourceError::Replayed(_) | DataPointSourceError::ReplayExhausted
+        ) {
+            return Duration::ZERO;
+        }
+        if let DataPointSourceError::RateLimited {
+            retry_after: Some(retry_after),
+        } = err
+        {
+            self.attempt = self.attempt.saturating_add(1);
+            return *retry_after;
+        }
+        let delay = self
+            .initial
+            .checked_mul(2u32.saturating_pow(self.attempt))
+            .map_or(self.max, |delay| delay.min(self.max));
+        self.attempt = self.attempt.saturating_add(1);
+        let jitter = rand::thread_rng().gen_range(0.0..0.5);
+        delay.mul_f64(1.0 - jitter)
+    }
+
+    pub(crate) fn reset(&mut self) {
+        self.attempt = 0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_backoff_delays() {
+        let mut backoff = Backoff::new(Duration::from_secs(1), Duration::from_secs(5));
+        let err = DataPointSourceError::JsonMissingField;
+        let expected_max = [1, 2, 4, 5, 5];
+        for max in expected_max.iter() {
+            let delay = backoff.next_delay(&err);
+            assert!(delay <= Duration::from_secs(*max), "{:?}", delay);
+            assert!(delay > Duration::from_secs(*max) / 2, "{:?}", delay);
+        }
+        let rate_limited = DataPointSourceError::RateLimited {
+            retry_after: Some(Duration::from_secs(60)),
+        };
+        assert_eq!(backoff.next_delay(&rate_limited), Duration::from_secs(60));
+        backoff.reset();
+        assert!(backoff.next_delay(&err) <= Duration::from_secs(1));
+        let replayed = DataPointSourceError::Replayed("Missing JSON field".to_string());
+        assert_eq!(backoff.next_delay(&replayed), Duration::ZERO);
+    }
+}
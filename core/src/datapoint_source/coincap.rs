@@ -0,0 +1,44 @@
+//! Obtains the nanoErg per 1 USD rate from CoinCap
+
+use json::JsonValue;
+
+use super::erg_usd::NANO_ERG_CONVERSION;
+use super::http_json::{extract_f64, fetch_json, invert_price};
+use super::{DataPointSource, DataPointSourceError};
+
+#[derive(Debug, Clone)]
+pub struct CoinCapNanoErgUsd;
+
+impl DataPointSource for CoinCapNanoErgUsd {
+    fn get_datapoint(&self) -> Result<i64, DataPointSourceError> {
+        let price_json = fetch_json(COINCAP_ERGO_URL)?;
+        nanoerg_usd_from_response(&price_json)
+    }
+}
+
+static COINCAP_ERGO_URL: &str = "https://api.coincap.io/v2/assets/ergo";
+
+fn nanoerg_usd_from_response(price_json: &JsonValue) -> Result<i64, DataPointSourceError> {
+    let p = extract_f64(price_json, "data.priceUsd")?;
+    invert_price(p, NANO_ERG_CONVERSION)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_nanoerg_usd_from_fixture() {
+        let price_json = json::parse(include_str!("fixtures/coincap_ergo.json")).unwrap();
+        assert_eq!(nanoerg_usd_from_response(&price_json).unwrap(), 800000000);
+    }
+
+    #[test]
+    fn test_missing_price() {
+        let price_json = json::parse(r#"{"error": "ergo not found"}"#).unwrap();
+        assert!(matches!(
+            nanoerg_usd_from_response(&price_json),
+            Err(DataPointSourceError::JsonMissingField)
+        ));
+    }
+}
@@ -0,0 +1,283 @@
+//! Datapoint sources derived from other sources: inverse, cross rates and rescaling.
+//!
+//! Datapoints are integers with an implied number of decimals, e.g. a USD price with 2 decimals
+//! is in cents. Every operand declares its decimals and the result is given with the configured
+//! number of decimals. All the arithmetic is overflow-checked integer math, the result is
+//! truncated towards zero.
+
+use std::convert::TryFrom;
+
+use serde::{Deserialize, Serialize};
+
+use super::registry::DataPointSourceRegistryError;
+use super::{DataPoint, DataPointSource, DataPointSourceConfig, DataPointSourceError};
+
+/// Config file description of an operand of a derived source
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DerivedOperandConfig {
+    pub source: DataPointSourceConfig,
+    #[serde(default)]
+    pub decimals: u32,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum DerivedOperationConfig {
+    /// `1 / source`, e.g. ERG per 1 USD from nanoErg per 1 USD
+    Invert(DerivedOperandConfig),
+    /// `left * right`, e.g. nanoErg per 1 XAU from nanoErg per 1 USD and USD per 1 XAU
+    Multiply(DerivedOperandConfig, DerivedOperandConfig),
+    /// `numerator / denominator`
+    Divide(DerivedOperandConfig, DerivedOperandConfig),
+    /// The source value with a different number of decimals
+    Rescale(DerivedOperandConfig),
+}
+
+/// Config file description of a derived datapoint source
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DerivedDataPointSourceConfig {
+    pub operation: DerivedOperationConfig,
+    /// Number of decimals of the resulting datapoint
+    #[serde(default)]
+    pub decimals: u32,
+}
+
+impl DerivedOperandConfig {
+    fn build(&self) -> Result<DerivedOperand, DataPointSourceRegistryError> {
+        Ok(DerivedOperand {
+            source: self.source.build()?,
+            decimals: self.decimals,
+        })
+    }
+}
+
+impl DerivedDataPointSourceConfig {
+    pub fn build(&self) -> Result<DerivedDataPointSource, DataPointSourceRegistryError> {
+        let operation = match &self.operation {
+            DerivedOperationConfig::Invert(source) => DerivedOperation::Invert(source.build()?),
+            DerivedOperationConfig::Multiply(left, right) => {
+                DerivedOperation::Multiply(left.build()?, right.build()?)
+            }
+            DerivedOperationConfig::Divide(numerator, denominator) => {
+                DerivedOperation::Divide(numerator.build()?, denominator.build()?)
+            }
+            DerivedOperationConfig::Rescale(source) => DerivedOperation::Rescale(source.build()?),
+        };
+        Ok(DerivedDataPointSource {
+            operation,
+            decimals: self.decimals,
+        })
+    }
+}
+
+#[derive(Debug)]
+pub struct DerivedOperand {
+    pub source: Box<dyn DataPointSource + Send + Sync>,
+    pub decimals: u32,
+}
+
+#[derive(Debug)]
+pub enum DerivedOperation {
+    Invert(DerivedOperand),
+    Multiply(DerivedOperand, DerivedOperand),
+    Divide(DerivedOperand, DerivedOperand),
+    Rescale(DerivedOperand),
+}
+
+#[derive(Debug)]
+pub struct DerivedDataPointSource {
+    operation: DerivedOperation,
+    decimals: u32,
+}
+
+impl DerivedDataPointSource {
+    pub fn new(operation: DerivedOperation, decimals: u32) -> Self {
+        DerivedDataPointSource {
+            operation,
+            decimals,
+        }
+    }
+}
+
+impl DataPointSource for DerivedDataPointSource {
+    fn get_datapoint(&self) -> Result<i64, DataPointSourceError> {
+        self.get_datapoint_with_metadata()
+            .map(|datapoint| datapoint.value)
+    }
+
+    /// The observation time is the oldest one among the operands
+    fn get_datapoint_with_metadata(&self) -> Result<DataPoint, DataPointSourceError> {
+        let out = self.decimals;
+        let (value, operands) = match &self.operation {
+            DerivedOperation::Invert(a) => {
+                let x = a.source.get_datapoint_with_metadata()?;
+                // 10^(da + out) / a
+                let value = mul_div(1, pow10(a.decimals.saturating_add(out))?, x.value as i128)?;
+                (value, vec![x])
+            }
+            DerivedOperation::Multiply(a, b) => {
+                let x = a.source.get_datapoint_with_metadata()?;
+                let y = b.source.get_datapoint_with_metadata()?;
+                // a * b * 10^out / 10^(da + db)
+                let product = mul_div(x.value as i128, y.value as i128, 1)?;
+                let value = mul_div(
+                    product,
+                    pow10(out)?,
+                    pow10(a.decimals.saturating_add(b.decimals))?,
+                )?;
+                (value, vec![x, y])
+            }
+            DerivedOperation::Divide(a, b) => {
+                let x = a.source.get_datapoint_with_metadata()?;
+                let y = b.source.get_datapoint_with_metadata()?;
+                // a * 10^(db + out) / (b * 10^da)
+                let denominator = mul_div(y.value as i128, pow10(a.decimals)?, 1)?;
+                let value = mul_div(
+                    x.value as i128,
+                    pow10(b.decimals.saturating_add(out))?,
+                    denominator,
+                )?;
+                (value, vec![x, y])
+            }
+            DerivedOperation::Rescale(a) => {
+                let x = a.source.get_datapoint_with_metadata()?;
+                // a * 10^out / 10^da
+                let value = mul_div(x.value as i128, pow10(out)?, pow10(a.decimals)?)?;
+                (value, vec![x])
+            }
+        };
+        Ok(DataPoint {
+            value: i64::try_from(value).map_err(|_| DataPointSourceError::ArithmeticOverflow)?,
+            observed_at: operands.iter().filter_map(|d| d.observed_at).min(),
+            source: format!(
+                "{}({})",
+                self.operation.name(),
+                operands
+                    .iter()
+                    .map(|d| d.source.as_str())
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
+        })
+    }
+}
+
+impl DerivedOperation {
+    fn name(&self) -> &'static str {
+        match self {
+            DerivedOperation::Invert(_) => "Invert",
+            DerivedOperation::Multiply(_, _) => "Multiply",
+            DerivedOperation::Divide(_, _) => "Divide",
+            DerivedOperation::Rescale(_) => "Rescale",
+        }
+    }
+}
+
+fn pow10(exp: u32) -> Result<i128, DataPointSourceError> {
+    10i128
+        .checked_pow(exp)
+        .ok_or(DataPointSourceError::ArithmeticOverflow)
+}
+
+/// `a * b / c`
+fn mul_div(a: i128, b: i128, c: i128) -> Result<i128, DataPointSourceError> {
+    if c == 0 {
+        return Err(DataPointSourceError::DivisionByZero);
+    }
+    a.checked_mul(b)
+        .ok_or(DataPointSourceError::ArithmeticOverflow)?
+        .checked_div(c)
+        .ok_or(DataPointSourceError::ArithmeticOverflow)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug)]
+    struct ConstantSource(i64);
+
+    impl DataPointSource for ConstantSource {
+        fn get_datapoint(&self) -> Result<i64, DataPointSourceError> {
+            Ok(self.0)
+        }
+    }
+
+    fn operand(value: i64, decimals: u32) -> DerivedOperand {
+        DerivedOperand {
+            source: Box::new(ConstantSource(value)),
+            decimals,
+        }
+    }
+
+    #[test]
+    fn test_invert() {
+        // 800000000 nanoErg per 1 USD -> 1.25 USD per 1 ERG, with 2 decimals
+        let source =
+            DerivedDataPointSource::new(DerivedOperation::Invert(operand(800000000, 9)), 2);
+        assert_eq!(source.get_datapoint().unwrap(), 125);
+    }
+
+    #[test]
+    fn test_cross_rate() {
+        // 800000000 nanoErg per 1 USD * 1650.25 USD per 1 XAU = 1320200000000 nanoErg per 1 XAU
+        let source = DerivedDataPointSource::new(
+            DerivedOperation::Multiply(operand(800000000, 0), operand(165025, 2)),
+            0,
+        );
+        assert_eq!(source.get_datapoint().unwrap(), 1320200000000);
+        // 1320200000000 nanoErg per 1 XAU / 800000000 nanoErg per 1 USD = 1650.25 USD per 1 XAU
+        let source = DerivedDataPointSource::new(
+            DerivedOperation::Divide(operand(1320200000000, 0), operand(800000000, 0)),
+            2,
+        );
+        assert_eq!(source.get_datapoint().unwrap(), 165025);
+    }
+
+    #[test]
+    fn test_rescale() {
+        let source = DerivedDataPointSource::new(DerivedOperation::Rescale(operand(123456, 4)), 2);
+        assert_eq!(source.get_datapoint().unwrap(), 1234);
+        let source = DerivedDataPointSource::new(DerivedOperation::Rescale(operand(12, 0)), 3);
+        assert_eq!(source.get_datapoint().unwrap(), 12000);
+    }
+
+    #[test]
+    fn test_overflow_and_division_by_zero() {
+        let source = DerivedDataPointSource::new(
+            DerivedOperation::Multiply(operand(i64::MAX, 0), operand(i64::MAX, 0)),
+            0,
+        );
+        assert!(matches!(
+            source.get_datapoint(),
+            Err(DataPointSourceError::ArithmeticOverflow)
+        ));
+        let source = DerivedDataPointSource::new(DerivedOperation::Invert(operand(0, 0)), 0);
+        assert!(matches!(
+            source.get_datapoint(),
+            Err(DataPointSourceError::DivisionByZero)
+        ));
+        let source = DerivedDataPointSource::new(DerivedOperation::Rescale(operand(1, 0)), 40);
+        assert!(matches!(
+            source.get_datapoint(),
+            Err(DataPointSourceError::ArithmeticOverflow)
+        ));
+    }
+
+    #[test]
+    fn test_config_from_yaml() {
+        let config: DerivedDataPointSourceConfig = serde_yaml::from_str(
+            "
+operation:
+  Multiply:
+    - source:
+        Predefined: NanoErgUsd
+    - source:
+        ExternalScript: ./xau_usd.sh
+      decimals: 2
+",
+        )
+        .unwrap();
+        assert_eq!(config.decimals, 0);
+        assert!(config.build().is_ok());
+    }
+}
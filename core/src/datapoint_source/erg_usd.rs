@@ -1,34 +1,44 @@
 //! Obtains the nanoErg per 1 USD rate
 
-use super::{DataPointSource, DataPointSourceError};
+use json::JsonValue;
+
+use super::http_json::{extract_f64, fetch_json, invert_price};
+use super::{DataPoint, DataPointSource, DataPointSourceError};
 
 #[derive(Debug, Clone)]
 pub struct NanoErgUsd;
 
 impl DataPointSource for NanoErgUsd {
     fn get_datapoint(&self) -> Result<i64, DataPointSourceError> {
+        get_nanoerg_usd_price().map(|datapoint| datapoint.value)
+    }
+
+    fn get_datapoint_with_metadata(&self) -> Result<DataPoint, DataPointSourceError> {
         get_nanoerg_usd_price()
     }
 }
 
 // Number of nanoErgs in a single Erg
-static NANO_ERG_CONVERSION: f64 = 1000000000.0;
+pub(crate) static NANO_ERG_CONVERSION: f64 = 1000000000.0;
 
 static CG_RATE_URL: &str =
-    "https://api.coingecko.com/api/v3/simple/price?ids=ergo&vs_currencies=USD";
+    "https://api.coingecko.com/api/v3/simple/price?ids=ergo&vs_currencies=USD&include_last_updated_at=true";
 
 /// Acquires the price of Ergs in USD from CoinGecko, convert it
 /// into nanoErgs per 1 USD, and return it.
-fn get_nanoerg_usd_price() -> Result<i64, DataPointSourceError> {
-    let resp = reqwest::blocking::Client::new().get(CG_RATE_URL).send()?;
-    let price_json = json::parse(&resp.text()?)?;
-    if let Some(p) = price_json["ergo"]["usd"].as_f64() {
-        // Convert from price Erg/USD to nanoErgs per 1 USD
-        let nanoerg_price = (1.0 / p) * NANO_ERG_CONVERSION;
-        Ok(nanoerg_price as i64)
-    } else {
-        Err(DataPointSourceError::JsonMissingField)
-    }
+fn get_nanoerg_usd_price() -> Result<DataPoint, DataPointSourceError> {
+    let price_json = fetch_json(CG_RATE_URL)?;
+    price_from_response(&price_json)
+}
+
+fn price_from_response(price_json: &JsonValue) -> Result<DataPoint, DataPointSourceError> {
+    let p = extract_f64(price_json, "ergo.usd")?;
+    // Convert from price Erg/USD to nanoErgs per 1 USD
+    Ok(DataPoint {
+        value: invert_price(p, NANO_ERG_CONVERSION)?,
+        observed_at: price_json["ergo"]["last_updated_at"].as_u64(),
+        source: "CoinGecko ERG/USD".to_string(),
+    })
 }
 
 #[cfg(test)]
@@ -40,4 +50,12 @@ mod tests {
         let n = NanoErgUsd {};
         assert!(n.get_datapoint().unwrap() > 0);
     }
+
+    #[test]
+    fn test_erg_usd_price_from_fixture() {
+        let price_json = json::parse(include_str!("fixtures/coingecko_erg_usd.json")).unwrap();
+        let datapoint = price_from_response(&price_json).unwrap();
+        assert_eq!(datapoint.value, 800000000);
+        assert_eq!(datapoint.observed_at, Some(1666000000));
+    }
 }
@@ -1,12 +1,19 @@
 //! Obtains the nanoErg per 1 XAU (troy ounce of gold) rate
 
-use super::{DataPointSource, DataPointSourceError};
+use json::JsonValue;
+
+use super::http_json::{extract_f64, fetch_json, invert_price};
+use super::{DataPoint, DataPointSource, DataPointSourceError};
 
 #[derive(Debug, Clone)]
 pub struct NanoErgXau;
 
 impl DataPointSource for NanoErgXau {
     fn get_datapoint(&self) -> Result<i64, DataPointSourceError> {
+        get_nanoerg_xau_price().map(|datapoint| datapoint.value)
+    }
+
+    fn get_datapoint_with_metadata(&self) -> Result<DataPoint, DataPointSourceError> {
         get_nanoerg_xau_price()
     }
 }
@@ -15,20 +22,23 @@ impl DataPointSource for NanoErgXau {
 static NANO_ERG_CONVERSION: f64 = 1000000000.0;
 
 static CG_RATE_URL: &str =
-    "https://api.coingecko.com/api/v3/simple/price?ids=ergo&vs_currencies=XAU";
+    "https://api.coingecko.com/api/v3/simple/price?ids=ergo&vs_currencies=XAU&include_last_updated_at=true";
 
 /// Acquires the price of Ergs in XAU from CoinGecko, convert it into nanoErgs per 1 XAU (troy ounce
 /// of gold), and return it.
-fn get_nanoerg_xau_price() -> Result<i64, DataPointSourceError> {
-    let resp = reqwest::blocking::Client::new().get(CG_RATE_URL).send()?;
-    let price_json = json::parse(&resp.text()?)?;
-    if let Some(p) = price_json["ergo"]["xau"].as_f64() {
-        // Convert from price Erg/XAU to nanoErgs per 1 XAU
-        let nanoerg_price = (1.0 / p) * NANO_ERG_CONVERSION;
-        Ok(nanoerg_price as i64)
-    } else {
-        Err(DataPointSourceError::JsonMissingField)
-    }
+fn get_nanoerg_xau_price() -> Result<DataPoint, DataPointSourceError> {
+    let price_json = fetch_json(CG_RATE_URL)?;
+    price_from_response(&price_json)
+}
+
+fn price_from_response(price_json: &JsonValue) -> Result<DataPoint, DataPointSourceError> {
+    let p = extract_f64(price_json, "ergo.xau")?;
+    // Convert from price Erg/XAU to nanoErgs per 1 XAU
+    Ok(DataPoint {
+        value: invert_price(p, NANO_ERG_CONVERSION)?,
+        observed_at: price_json["ergo"]["last_updated_at"].as_u64(),
+        source: "CoinGecko ERG/XAU".to_string(),
+    })
 }
 
 #[cfg(test)]
@@ -40,4 +50,12 @@ mod tests {
         let n = NanoErgXau {};
         assert!(n.get_datapoint().unwrap() > 0);
     }
+
+    #[test]
+    fn test_erg_xau_price_from_fixture() {
+        let price_json = json::parse(include_str!("fixtures/coingecko_erg_xau.json")).unwrap();
+        let datapoint = price_from_response(&price_json).unwrap();
+        assert_eq!(datapoint.value, 2000000000000);
+        assert_eq!(datapoint.observed_at, Some(1666000000));
+    }
 }
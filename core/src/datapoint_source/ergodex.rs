@@ -0,0 +1,124 @@
+//! Obtains rates from the reserves of Ergo DEX (ERG to token) AMM pools, read via the explorer API
+
+use std::convert::TryFrom;
+
+use json::JsonValue;
+
+use super::http_json::{extract_u64, fetch_json, lookup};
+use super::{DataPointSource, DataPointSourceError};
+
+/// nanoErg per 1 SigUSD rate from the Ergo DEX ERG/SigUSD pool
+#[derive(Debug, Clone)]
+pub struct ErgoDexNanoErgSigUsd;
+
+impl DataPointSource for ErgoDexNanoErgSigUsd {
+    fn get_datapoint(&self) -> Result<i64, DataPointSourceError> {
+        ERG_SIGUSD_POOL.get_datapoint()
+    }
+}
+
+static ERG_SIGUSD_POOL: ErgoDexPool = ErgoDexPool {
+    pool_nft_id: "9916d75132593c8b07fe18bd8d583bda1652eed7565cf41a4738ddd90fc992ec",
+    token_id: "03faf2cb329f2e90d6d23b58d91bbb6c046aa143261cc21f52fbe2824bfcbf04",
+    token_decimals: 2,
+};
+
+static EXPLORER_UNSPENT_BY_TOKEN_ID_URL: &str =
+    "https://api.ergoplatform.com/api/v1/boxes/unspent/byTokenId/";
+
+/// ERG to token pool, identified by its NFT. Gives the number of nanoErgs per 1 whole token.
+#[derive(Debug, Clone)]
+struct ErgoDexPool {
+    pool_nft_id: &'static str,
+    token_id: &'static str,
+    token_decimals: u32,
+}
+
+impl DataPointSource for ErgoDexPool {
+    fn get_datapoint(&self) -> Result<i64, DataPointSourceError> {
+        let boxes_json = fetch_json(&format!(
+            "{}{}",
+            EXPLORER_UNSPENT_BY_TOKEN_ID_URL, self.pool_nft_id
+        ))?;
+        self.rate_from_response(&boxes_json)
+    }
+}
+
+impl ErgoDexPool {
+    fn rate_from_response(&self, boxes_json: &JsonValue) -> Result<i64, DataPointSourceError> {
+        // Pool box tokens are [pool NFT, LP token, Y token]
+        let pool_nft_id = lookup(boxes_json, "items[0].assets[0].tokenId")?;
+        let token_id = lookup(boxes_json, "items[0].assets[2].tokenId")?;
+        if pool_nft_id.as_str() != Some(self.pool_nft_id)
+            || token_id.as_str() != Some(self.token_id)
+        {
+            return Err(DataPointSourceError::UnexpectedResponse(format!(
+                "unexpected pool box tokens {}, {}",
+                pool_nft_id, token_id
+            )));
+        }
+        let nanoerg_reserve = extract_u64(boxes_json, "items[0].value")? as i128;
+        let token_reserve = extract_u64(boxes_json, "items[0].assets[2].amount")? as i128;
+        if token_reserve == 0 {
+            return Err(DataPointSourceError::UnexpectedResponse(
+                "empty pool token reserve".to_string(),
+            ));
+        }
+        let rate = 10i128
+            .checked_pow(self.token_decimals)
+            .and_then(|scale| nanoerg_reserve.checked_mul(scale))
+            .ok_or(DataPointSourceError::ArithmeticOverflow)?
+            / token_reserve;
+        i64::try_from(rate).map_err(|_| DataPointSourceError::ArithmeticOverflow)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_erg_sigusd_rate_from_fixture() {
+        let boxes_json =
+            json::parse(include_str!("fixtures/ergodex_erg_sigusd_pool.json")).unwrap();
+        assert_eq!(
+            ERG_SIGUSD_POOL.rate_from_response(&boxes_json).unwrap(),
+            800000000
+        );
+    }
+
+    #[test]
+    fn test_wrong_pool() {
+        let boxes_json =
+            json::parse(include_str!("fixtures/ergodex_erg_sigusd_pool.json")).unwrap();
+        let pool = ErgoDexPool {
+            pool_nft_id: ERG_SIGUSD_POOL.pool_nft_id,
+            token_id: "0cd8c9f416e5b1ca9f986a7f10a84191dfb85941619e49e53c0dc30ebf83324b",
+            token_decimals: 0,
+        };
+        assert!(matches!(
+            pool.rate_from_response(&boxes_json),
+            Err(DataPointSourceError::UnexpectedResponse(_))
+        ));
+    }
+
+    #[test]
+    fn test_rate_overflow() {
+        let mut boxes_json =
+            json::parse(include_str!("fixtures/ergodex_erg_sigusd_pool.json")).unwrap();
+        boxes_json["items"][0]["value"] = 1_000_000_000_000_000_000u64.into();
+        boxes_json["items"][0]["assets"][2]["amount"] = 1.into();
+        assert!(matches!(
+            ERG_SIGUSD_POOL.rate_from_response(&boxes_json),
+            Err(DataPointSourceError::ArithmeticOverflow)
+        ));
+        let pool = ErgoDexPool {
+            token_decimals: 40,
+            ..ERG_SIGUSD_POOL.clone()
+        };
+        assert!(matches!(
+            pool.rate_from_response(&boxes_json),
+            Err(DataPointSourceError::ArithmeticOverflow)
+        ));
+    }
+}
@@ -0,0 +1,318 @@
+//! Datapoint source which runs an external script (or any executable) and reads the datapoint
+//! from its stdout
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::io::Read;
+use std::process::{Command, Stdio};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use derive_more::From;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use super::http_json::RedactedValues;
+use super::{DataPoint, DataPointSource, DataPointSourceError};
+
+#[derive(Debug, From, Error)]
+pub enum ExternalScriptError {
+    #[error("external script child process error: {0}")]
+    ChildProcess(std::io::Error),
+    #[error("String from bytes error: {0}")]
+    StringFromBytes(std::string::FromUtf8Error),
+    #[error("Parse i64 from string error: {0}")]
+    ParseInt(std::num::ParseIntError),
+    #[error("JSON output parse error: {0}")]
+    JsonOutput(serde_json::Error),
+    #[error("external script exited with {status}, stderr: {stderr}")]
+    #[from(ignore)]
+    NonZeroExitCode {
+        status: std::process::ExitStatus,
+        stderr: String,
+    },
+    #[error("external script did not finish in {0} seconds and was killed")]
+    #[from(ignore)]
+    Timeout(u64),
+}
+
+/// Format of the script's stdout
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ExternalScriptOutputFormat {
+    /// A single integer
+    Plain,
+    /// A JSON object, see [`ExternalScriptOutput`]
+    Json,
+}
+
+impl Default for ExternalScriptOutputFormat {
+    fn default() -> Self {
+        ExternalScriptOutputFormat::Plain
+    }
+}
+
+/// Datapoint with optional metadata, as printed by a script with the `Json` output format, e.g.
+/// `{"value": 123456789, "timestamp": 1666000000}`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ExternalScriptOutput {
+    pub value: i64,
+    /// Unix time (in seconds) when the datapoint was observed by the script's source
+    #[serde(default)]
+    pub timestamp: Option<u64>,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(from = "ExternalScriptSerde")]
+pub struct ExternalScript {
+    pub command: String,
+    pub args: Vec<String>,
+    /// Environment variables set in addition to the ones inherited from oracle-core
+    pub env: BTreeMap<String, String>,
+    /// The script is killed if it doesn't exit in this time
+    pub timeout_secs: u64,
+    pub output_format: ExternalScriptOutputFormat,
+}
+
+/// The values of `env` (e.g. API keys of the script) are not shown
+impl fmt::Debug for ExternalScript {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ExternalScript")
+            .field("command", &self.command)
+            .field("args", &self.args)
+            .field("env", &RedactedValues(&self.env))
+            .field("timeout_secs", &self.timeout_secs)
+            .field("output_format", &self.output_format)
+            .finish()
+    }
+}
+
+fn default_timeout_secs() -> u64 {
+    60
+}
+
+/// A script can be set either by just its path, or with all the options
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum ExternalScriptSerde {
+    Path(String),
+    Full {
+        command: String,
+        #[serde(default)]
+        args: Vec<String>,
+        #[serde(default)]
+        env: BTreeMap<String, String>,
+        #[serde(default = "default_timeout_secs")]
+        timeout_secs: u64,
+        #[serde(default)]
+        output_format: ExternalScriptOutputFormat,
+    },
+}
+
+impl From<ExternalScriptSerde> for ExternalScript {
+    fn from(s: ExternalScriptSerde) -> Self {
+        match s {
+            ExternalScriptSerde::Path(script_name) => ExternalScript::new(script_name),
+            ExternalScriptSerde::Full {
+                command,
+                args,
+                env,
+                timeout_secs,
+                output_format,
+            } => ExternalScript {
+                command,
+                args,
+                env,
+                timeout_secs,
+                output_format,
+            },
+        }
+    }
+}
+
+impl ExternalScript {
+    pub fn new(script_name: String) -> Self {
+        ExternalScript {
+            command: script_name,
+            args: Vec::new(),
+            env: BTreeMap::new(),
+            timeout_secs: default_timeout_secs(),
+            output_format: ExternalScriptOutputFormat::default(),
+        }
+    }
+
+    /// Runs the script and parses its output
+    pub fn run(&self) -> Result<ExternalScriptOutput, ExternalScriptError> {
+        let stdout = self.run_to_completion()?;
+        let stdout = stdout.trim();
+        match self.output_format {
+            ExternalScriptOutputFormat::Plain => Ok(ExternalScriptOutput {
+                value: stdout.parse()?,
+                timestamp: None,
+            }),
+            ExternalScriptOutputFormat::Json => Ok(serde_json::from_str(stdout)?),
+        }
+    }
+
+    /// Runs the script, killing it on timeout. Returns the stdout of a successful run.
+    fn run_to_completion(&self) -> Result<String, ExternalScriptError> {
+        let mut child = Command::new(&self.command)
+            .args(&self.args)
+            .envs(&self.env)
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()?;
+        // Read the pipes in separate threads so that a chatty child doesn't block on a full pipe
+        let stdout_reader = read_to_end_in_thread(child.stdout.take());
+        let stderr_reader = read_to_end_in_thread(child.stderr.take());
+        let deadline = Instant::now() + Duration::from_secs(self.timeout_secs);
+        let status = loop {
+            if let Some(status) = child.try_wait()? {
+                break status;
+            }
+            if Instant::now() >= deadline {
+                child.kill()?;
+                child.wait()?;
+                return Err(ExternalScriptError::Timeout(self.timeout_secs));
+            }
+            thread::sleep(Duration::from_millis(20));
+        };
+        let stdout = stdout_reader.join().unwrap_or_default();
+        let stderr = stderr_reader.join().unwrap_or_default();
+        if !status.success() {
+            return Err(ExternalScriptError::NonZeroExitCode {
+                status,
+                stderr: String::from_utf8_lossy(&stderr).trim().to_string(),
+            });
+        }
+        Ok(String::from_utf8(stdout)?)
+    }
+}
+
+fn read_to_end_in_thread<R: Read + Send + 'static>(pipe: Option<R>) -> thread::JoinHandle<Vec<u8>> {
+    thread::spawn(move || {
+        let mut buf = Vec::new();
+        if let Some(mut pipe) = pipe {
+            // A read error just truncates the captured output
+            let _ = pipe.read_to_end(&mut buf);
+        }
+        buf
+    })
+}
+
+impl DataPointSource for ExternalScript {
+    fn get_datapoint(&self) -> Result<i64, DataPointSourceError> {
+        Ok(self.run()?.value)
+    }
+
+    fn get_datapoint_with_metadata(&self) -> Result<DataPoint, DataPointSourceError> {
+        let output = self.run()?;
+        Ok(DataPoint {
+            value: output.value,
+            observed_at: output.timestamp,
+            source: self.command.clone(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sh(script: &str) -> ExternalScript {
+        ExternalScript {
+            command: "sh".to_string(),
+            args: vec!["-c".to_string(), script.to_string()],
+            env: BTreeMap::new(),
+            timeout_secs: 5,
+            output_format: ExternalScriptOutputFormat::Plain,
+        }
+    }
+
+    #[test]
+    fn test_trailing_newline() {
+        assert_eq!(sh("echo 42").get_datapoint().unwrap(), 42);
+    }
+
+    #[test]
+    fn test_args_and_env() {
+        let mut script = sh("echo $((DATAPOINT + $0))");
+        script.args.push("2".to_string());
+        script.env.insert("DATAPOINT".to_string(), "40".to_string());
+        assert_eq!(script.get_datapoint().unwrap(), 42);
+    }
+
+    #[test]
+    fn test_debug_redacts_env() {
+        let mut script = sh("echo 42");
+        script
+            .env
+            .insert("API_KEY".to_string(), "secret".to_string());
+        let debug = format!("{:?}", script);
+        assert!(debug.contains("API_KEY"));
+        assert!(!debug.contains("secret"));
+    }
+
+    #[test]
+    fn test_non_zero_exit_code() {
+        match sh("echo 42; echo 'no rate' >&2; exit 3").run() {
+            Err(ExternalScriptError::NonZeroExitCode { status, stderr }) => {
+                assert_eq!(status.code(), Some(3));
+                assert_eq!(stderr, "no rate");
+            }
+            r => panic!("unexpected result {:?}", r),
+        }
+    }
+
+    #[test]
+    fn test_timeout() {
+        let mut script = sh("sleep 10; echo 42");
+        script.timeout_secs = 1;
+        let started = Instant::now();
+        assert!(matches!(script.run(), Err(ExternalScriptError::Timeout(1))));
+        assert!(started.elapsed() < Duration::from_secs(5));
+    }
+
+    #[test]
+    fn test_json_output() {
+        let mut script = sh(r#"echo '{"value": 42, "timestamp": 1666000000}'"#);
+        script.output_format = ExternalScriptOutputFormat::Json;
+        assert_eq!(
+            script.run().unwrap(),
+            ExternalScriptOutput {
+                value: 42,
+                timestamp: Some(1666000000)
+            }
+        );
+        assert_eq!(
+            script.get_datapoint_with_metadata().unwrap(),
+            DataPoint {
+                value: 42,
+                observed_at: Some(1666000000),
+                source: "sh".to_string(),
+            }
+        );
+    }
+
+    #[test]
+    fn test_config_from_yaml() {
+        let script: ExternalScript = serde_yaml::from_str("./erg_usd.sh").unwrap();
+        assert_eq!(script.command, "./erg_usd.sh");
+        assert_eq!(script.timeout_secs, 60);
+        let script: ExternalScript = serde_yaml::from_str(
+            "
+command: ./erg_usd.sh
+args: [--pair, ERGUSD]
+env:
+  API_KEY: secret
+timeout_secs: 10
+output_format: Json
+",
+        )
+        .unwrap();
+        assert_eq!(script.args, vec!["--pair", "ERGUSD"]);
+        assert_eq!(script.env["API_KEY"], "secret");
+        assert_eq!(script.timeout_secs, 10);
+        assert_eq!(script.output_format, ExternalScriptOutputFormat::Json);
+    }
+}
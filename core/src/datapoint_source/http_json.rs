@@ -0,0 +1,291 @@
+//! Shared HTTP fetching and JSON path extraction used by the datapoint source adapters, and a
+//! datapoint source fully defined in the config file
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::time::Duration;
+
+use json::JsonValue;
+use serde::{Deserialize, Serialize};
+
+use super::{DataPointSource, DataPointSourceError};
+
+lazy_static! {
+    /// Client shared by all the sources, so that connections are reused between fetches
+    static ref HTTP_CLIENT: reqwest::blocking::Client = reqwest::blocking::Client::builder()
+        .timeout(Duration::from_secs(30))
+        .build()
+        .expect("failed to build HTTP client");
+}
+
+/// Fetches `url` and parses the response body as JSON
+pub(crate) fn fetch_json(url: &str) -> Result<JsonValue, DataPointSourceError> {
+    send_json_request(HTTP_CLIENT.get(url))
+}
+
+fn send_json_request(
+    request: reqwest::blocking::RequestBuilder,
+) -> Result<JsonValue, DataPointSourceError> {
+    let resp = request.send()?;
+    if resp.status() == reqwest::StatusCode::TOO_MANY_REQUESTS {
+        let retry_after = resp
+            .headers()
+            .get(reqwest::header::RETRY_AFTER)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.trim().parse().ok())
+            .map(Duration::from_secs);
+        return Err(DataPointSourceError::RateLimited { retry_after });
+    }
+    let resp = resp.error_for_status()?;
+    Ok(json::parse(&resp.text()?)?)
+}
+
+/// Datapoint source defined in the config file: a number at `json_path` in the JSON response of
+/// a GET request to `url`
+#[derive(Clone, Serialize, Deserialize)]
+pub struct HttpJsonDataPointSource {
+    pub url: String,
+    #[serde(default)]
+    pub headers: BTreeMap<String, String>,
+    /// Path to the number in the response, e.g. `data.priceUsd` or `result.ERGUSD.c[0]`
+    pub json_path: String,
+    /// Use `1 / value` instead of the value, e.g. to get nanoErgs per 1 USD from an ERG/USD price
+    #[serde(default)]
+    pub invert: bool,
+    /// Factor the (inverted) value is multiplied by, e.g. 1000000000 for nanoErgs
+    #[serde(default = "default_scale")]
+    pub scale: f64,
+    #[serde(default = "default_timeout_secs")]
+    pub timeout_secs: u64,
+}
+
+/// `Debug` of a map without its values, e.g. the headers holding API keys. `Debug` of the sources
+/// ends up in the logs and in the recorded datapoints.
+pub(crate) struct RedactedValues<'a>(pub &'a BTreeMap<String, String>);
+
+impl<'a> fmt::Debug for RedactedValues<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_map()
+            .entries(self.0.keys().map(|key| (key, "<redacted>")))
+            .finish()
+    }
+}
+
+impl fmt::Debug for HttpJsonDataPointSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("HttpJsonDataPointSource")
+            .field("url", &self.url)
+            .field("headers", &RedactedValues(&self.headers))
+            .field("json_path", &self.json_path)
+            .field("invert", &self.invert)
+            .field("scale", &self.scale)
+            .field("timeout_secs", &self.timeout_secs)
+            .finish()
+    }
+}
+
+fn default_scale() -> f64 {
+    1.0
+}
+
+fn default_timeout_secs() -> u64 {
+    30
+}
+
+impl DataPointSource for HttpJsonDataPointSource {
+    fn get_datapoint(&self) -> Result<i64, DataPointSourceError> {
+        let request = self.headers.iter().fold(
+            HTTP_CLIENT
+                .get(&self.url)
+                .timeout(Duration::from_secs(self.timeout_secs)),
+            |request, (name, value)| request.header(name, value),
+        );
+        let json = send_json_request(request)?;
+        self.datapoint_from_response(&json)
+    }
+}
+
+impl HttpJsonDataPointSource {
+    fn datapoint_from_response(&self, json: &JsonValue) -> Result<i64, DataPointSourceError> {
+        let value = extract_f64(json, &self.json_path)?;
+        if self.invert {
+            return invert_price(value, self.scale);
+        }
+        let datapoint = value * self.scale;
+        if !datapoint.is_finite() || datapoint.abs() >= i64::MAX as f64 {
+            return Err(DataPointSourceError::UnexpectedResponse(format!(
+                "datapoint {} is out of range",
+                datapoint
+            )));
+        }
+        Ok(datapoint as i64)
+    }
+}
+
+/// Looks up the value at `path` in `json`. The path is a dot separated list of object keys, each
+/// optionally followed by one or more array indices, e.g. `result.ERGUSD.c[0]` or `items[0].value`
+pub(crate) fn lookup<'a>(
+    json: &'a JsonValue,
+    path: &str,
+) -> Result<&'a JsonValue, DataPointSourceError> {
+    let invalid_path = || DataPointSourceError::InvalidJsonPath(path.to_string());
+    let mut current = json;
+    for segment in path.split('.') {
+        let (key, mut indices) = match segment.find('[') {
+            Some(i) => segment.split_at(i),
+            None => (segment, ""),
+        };
+        if key.is_empty() && indices.is_empty() {
+            return Err(invalid_path());
+        }
+        if !key.is_empty() {
+            current = &current[key];
+        }
+        while !indices.is_empty() {
+            if !indices.starts_with('[') {
+                return Err(invalid_path());
+            }
+            let close = indices.find(']').ok_or_else(invalid_path)?;
+            let index: usize = indices[1..close].parse().map_err(|_| invalid_path())?;
+            current = &current[index];
+            indices = &indices[close + 1..];
+        }
+    }
+    if current.is_null() {
+        Err(DataPointSourceError::JsonMissingField)
+    } else {
+        Ok(current)
+    }
+}
+
+/// Extracts a number at `path` in `json`. Numbers encoded as strings (e.g. `"1.2345"`, as returned
+/// by most exchanges) are accepted as well.
+pub(crate) fn extract_f64(json: &JsonValue, path: &str) -> Result<f64, DataPointSourceError> {
+    let value = lookup(json, path)?;
+    value
+        .as_f64()
+        .or_else(|| value.as_str().and_then(|s| s.trim().parse().ok()))
+        .ok_or(DataPointSourceError::JsonMissingField)
+}
+
+/// Extracts an unsigned integer at `path` in `json`
+pub(crate) fn extract_u64(json: &JsonValue, path: &str) -> Result<u64, DataPointSourceError> {
+    let value = lookup(json, path)?;
+    value
+        .as_u64()
+        .or_else(|| value.as_str().and_then(|s| s.trim().parse().ok()))
+        .ok_or(DataPointSourceError::JsonMissingField)
+}
+
+/// Converts a price of one unit in some currency into the number of `units_conversion` sub-units
+/// (e.g. nanoErgs) per 1 unit of that currency
+pub(crate) fn invert_price(price: f64, units_conversion: f64) -> Result<i64, DataPointSourceError> {
+    if !price.is_finite() || price <= 0.0 {
+        return Err(DataPointSourceError::UnexpectedResponse(format!(
+            "invalid price {}",
+            price
+        )));
+    }
+    Ok(((1.0 / price) * units_conversion) as i64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lookup() {
+        let json = json::parse(r#"{"a": {"b": [1, [2, "3.5"]]}, "c": null}"#).unwrap();
+        assert_eq!(extract_f64(&json, "a.b[0]").unwrap(), 1.0);
+        assert_eq!(extract_f64(&json, "a.b[1][0]").unwrap(), 2.0);
+        assert_eq!(extract_f64(&json, "a.b[1][1]").unwrap(), 3.5);
+        assert_eq!(extract_u64(&json, "a.b[0]").unwrap(), 1);
+        assert!(matches!(
+            extract_f64(&json, "a.b[2]"),
+            Err(DataPointSourceError::JsonMissingField)
+        ));
+        assert!(matches!(
+            extract_f64(&json, "c"),
+            Err(DataPointSourceError::JsonMissingField)
+        ));
+        assert!(matches!(
+            extract_f64(&json, "a"),
+            Err(DataPointSourceError::JsonMissingField)
+        ));
+    }
+
+    #[test]
+    fn test_debug_redacts_headers() {
+        let source = HttpJsonDataPointSource {
+            url: "https://api.example.com/price".to_string(),
+            headers: vec![("Authorization".to_string(), "Bearer secret".to_string())]
+                .into_iter()
+                .collect(),
+            json_path: "price".to_string(),
+            invert: false,
+            scale: 1.0,
+            timeout_secs: 30,
+        };
+        let debug = format!("{:?}", source);
+        assert!(debug.contains("Authorization"));
+        assert!(!debug.contains("secret"));
+    }
+
+    #[test]
+    fn test_invalid_path() {
+        let json = json::parse(r#"{"a": [1]}"#).unwrap();
+        for path in &["", "a..b", "a[x]", "a[0", "a[0]b"] {
+            assert!(
+                matches!(
+                    lookup(&json, path),
+                    Err(DataPointSourceError::InvalidJsonPath(_))
+                ),
+                "{}",
+                path
+            );
+        }
+    }
+
+    #[test]
+    fn test_http_json_source_from_yaml() {
+        let source: HttpJsonDataPointSource = serde_yaml::from_str(
+            "
+url: https://api.kraken.com/0/public/Ticker?pair=ERGUSD
+json_path: result.ERGUSD.c[0]
+invert: true
+scale: 1000000000
+",
+        )
+        .unwrap();
+        assert!(source.headers.is_empty());
+        assert_eq!(source.timeout_secs, 30);
+        let ticker_json = json::parse(include_str!("fixtures/kraken_ergusd.json")).unwrap();
+        assert_eq!(
+            source.datapoint_from_response(&ticker_json).unwrap(),
+            800000000
+        );
+    }
+
+    #[test]
+    fn test_http_json_source_scale() {
+        let source = HttpJsonDataPointSource {
+            url: "http://localhost".to_string(),
+            headers: BTreeMap::new(),
+            json_path: "ergo.usd".to_string(),
+            invert: false,
+            scale: 100.0,
+            timeout_secs: 1,
+        };
+        let price_json = json::parse(include_str!("fixtures/coingecko_erg_usd.json")).unwrap();
+        assert_eq!(source.datapoint_from_response(&price_json).unwrap(), 125);
+        let huge_json = json::parse(r#"{"ergo": {"usd": 1e30}}"#).unwrap();
+        assert!(source.datapoint_from_response(&huge_json).is_err());
+    }
+
+    #[test]
+    fn test_invert_price() {
+        assert_eq!(invert_price(2.0, 1000000000.0).unwrap(), 500000000);
+        assert!(invert_price(0.0, 1000000000.0).is_err());
+        assert!(invert_price(-1.0, 1000000000.0).is_err());
+    }
+}
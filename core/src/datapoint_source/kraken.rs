@@ -0,0 +1,59 @@
+//! Obtains the nanoErg per 1 USD rate from Kraken
+
+use json::JsonValue;
+
+use super::erg_usd::NANO_ERG_CONVERSION;
+use super::http_json::{extract_f64, fetch_json, invert_price};
+use super::{DataPointSource, DataPointSourceError};
+
+#[derive(Debug, Clone)]
+pub struct KrakenNanoErgUsd;
+
+impl DataPointSource for KrakenNanoErgUsd {
+    fn get_datapoint(&self) -> Result<i64, DataPointSourceError> {
+        let ticker_json = fetch_json(KRAKEN_ERGUSD_URL)?;
+        nanoerg_usd_from_response(&ticker_json)
+    }
+}
+
+static KRAKEN_ERGUSD_URL: &str = "https://api.kraken.com/0/public/Ticker?pair=ERGUSD";
+
+/// Kraken reports failures in the `error` array of an otherwise successful response
+fn check_errors(ticker_json: &JsonValue) -> Result<(), DataPointSourceError> {
+    let errors: Vec<String> = ticker_json["error"]
+        .members()
+        .map(|e| e.to_string())
+        .collect();
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(DataPointSourceError::UnexpectedResponse(errors.join(", ")))
+    }
+}
+
+fn nanoerg_usd_from_response(ticker_json: &JsonValue) -> Result<i64, DataPointSourceError> {
+    check_errors(ticker_json)?;
+    // `c` is the last trade closed, as [price, lot volume]
+    let p = extract_f64(ticker_json, "result.ERGUSD.c[0]")?;
+    invert_price(p, NANO_ERG_CONVERSION)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_nanoerg_usd_from_fixture() {
+        let ticker_json = json::parse(include_str!("fixtures/kraken_ergusd.json")).unwrap();
+        assert_eq!(nanoerg_usd_from_response(&ticker_json).unwrap(), 800000000);
+    }
+
+    #[test]
+    fn test_api_error() {
+        let ticker_json = json::parse(include_str!("fixtures/kraken_error.json")).unwrap();
+        assert!(matches!(
+            nanoerg_usd_from_response(&ticker_json),
+            Err(DataPointSourceError::UnexpectedResponse(e)) if e == "EQuery:Unknown asset pair"
+        ));
+    }
+}
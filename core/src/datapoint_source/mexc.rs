@@ -0,0 +1,49 @@
+//! Obtains the nanoErg per 1 USD rate from MEXC, which implements the Binance-style spot ticker
+//! API (`/api/v3/ticker/price?symbol=...`)
+
+use json::JsonValue;
+
+use super::erg_usd::NANO_ERG_CONVERSION;
+use super::http_json::{extract_f64, fetch_json, invert_price};
+use super::{DataPointSource, DataPointSourceError};
+
+/// nanoErg per 1 USD rate from the MEXC ERG/USDT spot ticker
+#[derive(Debug, Clone)]
+pub struct MexcNanoErgUsd;
+
+impl DataPointSource for MexcNanoErgUsd {
+    fn get_datapoint(&self) -> Result<i64, DataPointSourceError> {
+        let ticker_json = fetch_json(MEXC_ERGUSDT_URL)?;
+        let p = ticker_price(&ticker_json, "ERGUSDT")?;
+        invert_price(p, NANO_ERG_CONVERSION)
+    }
+}
+
+static MEXC_ERGUSDT_URL: &str = "https://api.mexc.com/api/v3/ticker/price?symbol=ERGUSDT";
+
+/// Extracts the price from a Binance-style ticker response, checking that it is for `symbol`
+fn ticker_price(ticker_json: &JsonValue, symbol: &str) -> Result<f64, DataPointSourceError> {
+    match ticker_json["symbol"].as_str() {
+        Some(s) if s == symbol => extract_f64(ticker_json, "price"),
+        Some(s) => Err(DataPointSourceError::UnexpectedResponse(format!(
+            "expected ticker for {}, got {}",
+            symbol, s
+        ))),
+        None => Err(DataPointSourceError::JsonMissingField),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ticker_price_from_fixture() {
+        let ticker_json = json::parse(include_str!("fixtures/mexc_ergusdt.json")).unwrap();
+        assert_eq!(ticker_price(&ticker_json, "ERGUSDT").unwrap(), 1.25);
+        assert!(matches!(
+            ticker_price(&ticker_json, "ERGBTC"),
+            Err(DataPointSourceError::UnexpectedResponse(_))
+        ));
+    }
+}
@@ -0,0 +1,122 @@
+//! Fetches datapoints in a background thread so that a recent value is ready when it's needed
+
+use std::sync::{Arc, Mutex, Weak};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use super::backoff::Backoff;
+use super::{DataPoint, DataPointSource, DataPointSourceError};
+
+type LatestDataPoint = Mutex<Option<(DataPoint, Instant)>>;
+
+/// Wraps a datapoint source, refreshing its datapoint every `interval` in a background thread.
+/// Failed fetches are retried with a backoff. Serves the prefetched datapoint if it was fetched
+/// less than two intervals ago, otherwise falls back to fetching it directly.
+#[derive(Debug)]
+pub struct PrefetchingDataPointSource {
+    source: Arc<dyn DataPointSource + Send + Sync>,
+    latest: Arc<LatestDataPoint>,
+    max_cache_age: Duration,
+}
+
+impl PrefetchingDataPointSource {
+    pub fn new(source: Box<dyn DataPointSource + Send + Sync>, interval: Duration) -> Self {
+        let source: Arc<dyn DataPointSource + Send + Sync> = Arc::from(source);
+        let latest = Arc::new(Mutex::new(None));
+        let thread_source = source.clone();
+        let thread_latest = Arc::downgrade(&latest);
+        thread::Builder::new()
+            .name("datapoint-prefetch".to_string())
+            .spawn(move || prefetch_loop(&*thread_source, thread_latest, interval))
+            .expect("failed to spawn the datapoint prefetch thread");
+        PrefetchingDataPointSource {
+            source,
+            latest,
+            max_cache_age: interval * 2,
+        }
+    }
+}
+
+/// Runs until the `PrefetchingDataPointSource` owning `latest` is dropped
+fn prefetch_loop(source: &dyn DataPointSource, latest: Weak<LatestDataPoint>, interval: Duration) {
+    let mut backoff = Backoff::new(Duration::from_secs(1), interval);
+    loop {
+        let result = source.get_datapoint_with_metadata();
+        let latest = match latest.upgrade() {
+            Some(latest) => latest,
+            None => return,
+        };
+        let delay = match result {
+            Ok(datapoint) => {
+                log::debug!("Prefetched datapoint {:?}", datapoint);
+                *latest.lock().unwrap() = Some((datapoint, Instant::now()));
+                backoff.reset();
+                interval
+            }
+            Err(e) => {
+                let delay = backoff.next_delay(&e);
+                log::warn!(
+                    "Failed to prefetch datapoint from {:?}: {}, retrying in {:?}",
+                    source,
+                    e,
+                    delay
+                );
+                delay
+            }
+        };
+        drop(latest);
+        thread::sleep(delay);
+    }
+}
+
+impl DataPointSource for PrefetchingDataPointSource {
+    fn get_datapoint(&self) -> Result<i64, DataPointSourceError> {
+        self.get_datapoint_with_metadata()
+            .map(|datapoint| datapoint.value)
+    }
+
+    fn get_datapoint_with_metadata(&self) -> Result<DataPoint, DataPointSourceError> {
+        if let Some((datapoint, fetched_at)) = self.latest.lock().unwrap().clone() {
+            if fetched_at.elapsed() <= self.max_cache_age {
+                return Ok(datapoint);
+            }
+        }
+        let datapoint = self.source.get_datapoint_with_metadata()?;
+        *self.latest.lock().unwrap() = Some((datapoint.clone(), Instant::now()));
+        Ok(datapoint)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicI64, Ordering};
+
+    use super::*;
+
+    #[derive(Debug)]
+    struct CountingSource(Arc<AtomicI64>);
+
+    impl DataPointSource for CountingSource {
+        fn get_datapoint(&self) -> Result<i64, DataPointSourceError> {
+            Ok(self.0.fetch_add(1, Ordering::SeqCst) + 1)
+        }
+    }
+
+    #[test]
+    fn test_serves_prefetched_datapoint() {
+        let fetches = Arc::new(AtomicI64::new(0));
+        let source = PrefetchingDataPointSource::new(
+            Box::new(CountingSource(fetches.clone())),
+            Duration::from_secs(60),
+        );
+        // wait for the background fetch to be stored, not just made
+        let started = Instant::now();
+        while source.latest.lock().unwrap().is_none() && started.elapsed() < Duration::from_secs(5)
+        {
+            thread::sleep(Duration::from_millis(10));
+        }
+        assert_eq!(source.get_datapoint().unwrap(), 1);
+        assert_eq!(source.get_datapoint().unwrap(), 1);
+        assert_eq!(fetches.load(Ordering::SeqCst), 1);
+    }
+}
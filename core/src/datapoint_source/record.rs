@@ -0,0 +1,202 @@
+//! Recording of fetched datapoints to a file and replaying them, e.g. to check how the pool would
+//! have behaved during historical market moves
+
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+
+use super::{DataPoint, DataPointSource, DataPointSourceError};
+
+/// A single fetch result, stored as one JSON line in a recording file
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DataPointRecord {
+    /// Unix time (in seconds) of the fetch
+    pub recorded_at: u64,
+    pub value: Option<i64>,
+    pub observed_at: Option<u64>,
+    pub source: Option<String>,
+    pub error: Option<String>,
+}
+
+impl DataPointRecord {
+    fn new(result: &Result<DataPoint, DataPointSourceError>) -> Self {
+        let recorded_at = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        match result {
+            Ok(datapoint) => DataPointRecord {
+                recorded_at,
+                value: Some(datapoint.value),
+                observed_at: datapoint.observed_at,
+                source: Some(datapoint.source.clone()),
+                error: None,
+            },
+            Err(e) => DataPointRecord {
+                recorded_at,
+                value: None,
+                observed_at: None,
+                source: None,
+                error: Some(e.to_string()),
+            },
+        }
+    }
+
+    fn to_result(&self) -> Result<DataPoint, DataPointSourceError> {
+        match (self.value, &self.error) {
+            (Some(value), None) => Ok(DataPoint {
+                value,
+                observed_at: self.observed_at,
+                source: self.source.clone().unwrap_or_default(),
+            }),
+            (_, Some(error)) => Err(DataPointSourceError::Replayed(error.clone())),
+            (None, None) => Err(DataPointSourceError::Replayed(
+                "record has neither a value nor an error".to_string(),
+            )),
+        }
+    }
+}
+
+/// Passes through the datapoints of the wrapped source, appending every fetch result to a file
+#[derive(Debug)]
+pub struct RecordingDataPointSource {
+    source: Box<dyn DataPointSource + Send + Sync>,
+    path: PathBuf,
+    file: Mutex<File>,
+}
+
+impl RecordingDataPointSource {
+    pub fn new(
+        source: Box<dyn DataPointSource + Send + Sync>,
+        path: &Path,
+    ) -> Result<Self, std::io::Error> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(RecordingDataPointSource {
+            source,
+            path: path.to_path_buf(),
+            file: Mutex::new(file),
+        })
+    }
+
+    fn record(&self, record: &DataPointRecord) -> Result<(), std::io::Error> {
+        let mut line = serde_json::to_string(record)?;
+        line.push('\n');
+        self.file.lock().unwrap().write_all(line.as_bytes())
+    }
+}
+
+impl DataPointSource for RecordingDataPointSource {
+    fn get_datapoint(&self) -> Result<i64, DataPointSourceError> {
+        self.get_datapoint_with_metadata()
+            .map(|datapoint| datapoint.value)
+    }
+
+    fn get_datapoint_with_metadata(&self) -> Result<DataPoint, DataPointSourceError> {
+        let result = self.source.get_datapoint_with_metadata();
+        if let Err(e) = self.record(&DataPointRecord::new(&result)) {
+            // A failed recording must not stop the oracle
+            log::warn!(
+                "Failed to record datapoint to {}: {}",
+                self.path.display(),
+                e
+            );
+        }
+        result
+    }
+}
+
+/// Returns the recorded fetch results one by one, in order
+#[derive(Debug)]
+pub struct ReplayDataPointSource {
+    records: Vec<DataPointRecord>,
+    next: AtomicUsize,
+}
+
+impl ReplayDataPointSource {
+    pub fn new(records: Vec<DataPointRecord>) -> Self {
+        ReplayDataPointSource {
+            records,
+            next: AtomicUsize::new(0),
+        }
+    }
+
+    /// Loads a file written by `RecordingDataPointSource`
+    pub fn load(path: &Path) -> Result<Self, std::io::Error> {
+        let records = BufReader::new(File::open(path)?)
+            .lines()
+            .filter(|line| !matches!(line, Ok(l) if l.trim().is_empty()))
+            .map(|line| Ok(serde_json::from_str(&line?)?))
+            .collect::<Result<Vec<DataPointRecord>, std::io::Error>>()?;
+        Ok(ReplayDataPointSource::new(records))
+    }
+}
+
+impl DataPointSource for ReplayDataPointSource {
+    fn get_datapoint(&self) -> Result<i64, DataPointSourceError> {
+        self.get_datapoint_with_metadata()
+            .map(|datapoint| datapoint.value)
+    }
+
+    fn get_datapoint_with_metadata(&self) -> Result<DataPoint, DataPointSourceError> {
+        let index = self.next.fetch_add(1, Ordering::SeqCst);
+        self.records
+            .get(index)
+            .ok_or(DataPointSourceError::ReplayExhausted)?
+            .to_result()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug)]
+    struct SeriesSource(Mutex<Vec<Option<i64>>>);
+
+    impl DataPointSource for SeriesSource {
+        fn get_datapoint(&self) -> Result<i64, DataPointSourceError> {
+            self.0
+                .lock()
+                .unwrap()
+                .remove(0)
+                .ok_or(DataPointSourceError::JsonMissingField)
+        }
+    }
+
+    #[test]
+    fn test_record_and_replay() {
+        let path = std::env::temp_dir().join(format!(
+            "oracle-core-datapoint-record-test-{}.jsonl",
+            std::process::id()
+        ));
+        let _ = std::fs::remove_file(&path);
+        let recording = RecordingDataPointSource::new(
+            Box::new(SeriesSource(Mutex::new(vec![Some(100), None, Some(102)]))),
+            &path,
+        )
+        .unwrap();
+        assert_eq!(recording.get_datapoint().unwrap(), 100);
+        assert!(recording.get_datapoint().is_err());
+        assert_eq!(recording.get_datapoint().unwrap(), 102);
+
+        let replay = ReplayDataPointSource::load(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(replay.get_datapoint().unwrap(), 100);
+        match replay.get_datapoint() {
+            Err(DataPointSourceError::Replayed(e)) => {
+                assert_eq!(e, DataPointSourceError::JsonMissingField.to_string())
+            }
+            r => panic!("unexpected result {:?}", r),
+        }
+        assert_eq!(replay.get_datapoint().unwrap(), 102);
+        assert!(matches!(
+            replay.get_datapoint(),
+            Err(DataPointSourceError::ReplayExhausted)
+        ));
+    }
+}
@@ -0,0 +1,192 @@
+//! Registry of datapoint source constructors by name. Lets a source be added without touching
+//! `PredefinedDataPointSource` and the config serde layer: register a constructor under a name
+//! and refer to it from the config file by that name, with the parameters it expects.
+
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::RwLock;
+
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use super::{
+    DataPointSource, ExternalScript, HttpJsonDataPointSource, PredefinedDataPointSource,
+    ReplayDataPointSource,
+};
+
+/// Name of the source replaying a file written with `data_point_source_record_path`
+pub const REPLAY_SOURCE_NAME: &str = "Replay";
+
+/// Builds a source from its parameters in the config file
+pub type DataPointSourceConstructor = Box<
+    dyn Fn(&serde_yaml::Value) -> Result<Box<dyn DataPointSource + Send + Sync>, serde_yaml::Error>
+        + Send
+        + Sync,
+>;
+
+#[derive(Debug, Error)]
+pub enum DataPointSourceRegistryError {
+    #[error("datapoint source {0} is already registered")]
+    AlreadyRegistered(String),
+    #[error("unknown datapoint source {0}")]
+    UnknownSource(String),
+    #[error("invalid parameters for datapoint source {name}: {error}")]
+    InvalidParams {
+        name: String,
+        error: serde_yaml::Error,
+    },
+}
+
+/// Config file description of a source from the registry
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NamedDataPointSourceConfig {
+    pub name: String,
+    #[serde(default)]
+    pub params: serde_yaml::Value,
+}
+
+impl NamedDataPointSourceConfig {
+    pub fn build(
+        &self,
+    ) -> Result<Box<dyn DataPointSource + Send + Sync>, DataPointSourceRegistryError> {
+        let registry = REGISTRY.read().unwrap();
+        let constructor = registry
+            .get(&self.name)
+            .ok_or_else(|| DataPointSourceRegistryError::UnknownSource(self.name.clone()))?;
+        constructor(&self.params).map_err(|error| DataPointSourceRegistryError::InvalidParams {
+            name: self.name.clone(),
+            error,
+        })
+    }
+}
+
+lazy_static! {
+    static ref REGISTRY: RwLock<HashMap<String, DataPointSourceConstructor>> =
+        RwLock::new(builtin_sources());
+}
+
+/// Registers a source constructor under `name`. Already registered names, including the built-in
+/// ones, can't be replaced.
+pub fn register_data_point_source(
+    name: &str,
+    constructor: DataPointSourceConstructor,
+) -> Result<(), DataPointSourceRegistryError> {
+    let mut registry = REGISTRY.write().unwrap();
+    if registry.contains_key(name) {
+        return Err(DataPointSourceRegistryError::AlreadyRegistered(
+            name.to_string(),
+        ));
+    }
+    registry.insert(name.to_string(), constructor);
+    Ok(())
+}
+
+/// Names of all the registered sources
+pub fn registered_data_point_sources() -> Vec<String> {
+    let mut names: Vec<String> = REGISTRY.read().unwrap().keys().cloned().collect();
+    names.sort();
+    names
+}
+
+fn builtin_sources() -> HashMap<String, DataPointSourceConstructor> {
+    let mut sources: HashMap<String, DataPointSourceConstructor> = HashMap::new();
+    for predefined in PredefinedDataPointSource::ALL.iter() {
+        let predefined = *predefined;
+        sources.insert(
+            format!("{:?}", predefined),
+            Box::new(move |_| Ok(Box::new(predefined))),
+        );
+    }
+    sources.insert(
+        "ExternalScript".to_string(),
+        Box::new(|params| {
+            let script: ExternalScript = serde_yaml::from_value(params.clone())?;
+            Ok(Box::new(script))
+        }),
+    );
+    sources.insert(
+        "HttpJson".to_string(),
+        Box::new(|params| {
+            let source: HttpJsonDataPointSource = serde_yaml::from_value(params.clone())?;
+            Ok(Box::new(source))
+        }),
+    );
+    sources.insert(
+        REPLAY_SOURCE_NAME.to_string(),
+        Box::new(|params| {
+            let params: ReplayParams = serde_yaml::from_value(params.clone())?;
+            let source = ReplayDataPointSource::load(&params.path)
+                .map_err(<serde_yaml::Error as serde::de::Error>::custom)?;
+            Ok(Box::new(source))
+        }),
+    );
+    sources
+}
+
+#[derive(Deserialize)]
+struct ReplayParams {
+    /// File written with `data_point_source_record_path`
+    path: PathBuf,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::DataPointSourceError;
+    use super::*;
+
+    #[derive(Debug)]
+    struct ConstantSource(i64);
+
+    impl DataPointSource for ConstantSource {
+        fn get_datapoint(&self) -> Result<i64, DataPointSourceError> {
+            Ok(self.0)
+        }
+    }
+
+    #[derive(Deserialize)]
+    struct ConstantSourceParams {
+        value: i64,
+    }
+
+    #[test]
+    fn test_register_and_build() {
+        register_data_point_source(
+            "TestConstant",
+            Box::new(|params| {
+                let params: ConstantSourceParams = serde_yaml::from_value(params.clone())?;
+                Ok(Box::new(ConstantSource(params.value)))
+            }),
+        )
+        .unwrap();
+        assert!(matches!(
+            register_data_point_source("TestConstant", Box::new(|_| unreachable!())),
+            Err(DataPointSourceRegistryError::AlreadyRegistered(_))
+        ));
+        assert!(registered_data_point_sources().contains(&"TestConstant".to_string()));
+
+        let config: NamedDataPointSourceConfig =
+            serde_yaml::from_str("name: TestConstant\nparams:\n  value: 42\n").unwrap();
+        assert_eq!(config.build().unwrap().get_datapoint().unwrap(), 42);
+
+        let config: NamedDataPointSourceConfig =
+            serde_yaml::from_str("name: TestConstant\nparams:\n  val: 42\n").unwrap();
+        assert!(matches!(
+            config.build(),
+            Err(DataPointSourceRegistryError::InvalidParams { .. })
+        ));
+    }
+
+    #[test]
+    fn test_builtin_sources() {
+        let config: NamedDataPointSourceConfig = serde_yaml::from_str("name: NanoErgUsd").unwrap();
+        assert!(config.build().is_ok());
+        let config: NamedDataPointSourceConfig =
+            serde_yaml::from_str("name: ExternalScript\nparams: ./erg_usd.sh\n").unwrap();
+        assert!(config.build().is_ok());
+        let config: NamedDataPointSourceConfig = serde_yaml::from_str("name: Unknown").unwrap();
+        assert!(matches!(
+            config.build(),
+            Err(DataPointSourceRegistryError::UnknownSource(_))
+        ));
+    }
+}
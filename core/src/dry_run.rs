@@ -0,0 +1,371 @@
+//! Dry-run mode (`--dry-run`): instead of signing and submitting, the transactions are written as
+//! EIP-12 JSON (to a file or stdout) along with a summary of their inputs, outputs, token
+//! movements and fee
+
+use std::collections::BTreeMap;
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::PathBuf;
+
+use derive_more::From;
+use ergo_lib::chain::transaction::unsigned::UnsignedTransaction;
+use ergo_lib::chain::transaction::{Input, Transaction, TxIoVec};
+use ergo_lib::ergotree_interpreter::sigma_protocol::prover::{ProofBytes, ProverResult};
+use ergo_lib::ergotree_ir::chain::address::{Address, NetworkAddress, NetworkPrefix};
+use ergo_lib::ergotree_ir::chain::ergo_box::ErgoBox;
+use ergo_lib::ergotree_ir::ergo_tree::ErgoTree;
+use ergo_lib::ergotree_ir::serialization::SigmaSerializable;
+use ergo_node_interface::node_interface::NodeError;
+use once_cell::sync::OnceCell;
+use serde_json::{json, Value};
+use thiserror::Error;
+
+use crate::node_interface::{tx_input_boxes, SignTransaction, SubmitTransaction};
+
+/// Set in `main` when the `--dry-run` flag is passed
+pub static DRY_RUN: OnceCell<DryRun> = OnceCell::new();
+
+/// Ergo tree of the miner fee contract, the value of its outputs is the tx fee
+const MINERS_FEE_ERGO_TREE: &str = "1005040004000e36100204a00b08cd0279be667ef9dcbbac55a06295ce870b07029bfcdb2dce28d959f2815b16f81798ea02d192a39a8cc7a701730073011001020402d19683030193a38cc7b2a57300000193c2b2a57301007473027303830108cdeeac93b1a57304";
+
+#[derive(Debug, Error, From)]
+pub enum DryRunError {
+    #[error("dry run: io error {0}")]
+    Io(std::io::Error),
+    #[error("dry run: json error {0}")]
+    Json(serde_json::Error),
+    #[error("dry run: node error {0}")]
+    Node(NodeError),
+}
+
+/// Returns the dry-run settings if the `--dry-run` flag is passed
+pub fn dry_run() -> Option<&'static DryRun> {
+    DRY_RUN.get()
+}
+
+#[derive(Debug)]
+pub struct DryRun {
+    /// File the EIP-12 transactions are appended to (as JSON lines), stdout if not set
+    output: Option<PathBuf>,
+}
+
+impl DryRun {
+    pub fn new(output: Option<PathBuf>) -> Self {
+        DryRun { output }
+    }
+
+    /// Writes a tx whose input and data input boxes are known
+    pub fn write_tx(
+        &self,
+        title: &str,
+        tx: &UnsignedTransaction,
+        inputs: &[ErgoBox],
+        data_inputs: &[ErgoBox],
+        network_prefix: NetworkPrefix,
+    ) -> Result<(), DryRunError> {
+        self.write(
+            title,
+            &serde_json::to_value(tx)?,
+            &boxes_json(inputs)?,
+            &boxes_json(data_inputs)?,
+            network_prefix,
+        )
+    }
+
+    /// Writes a tx spending boxes of the UTXO set (or the mempool), fetched from the node
+    pub fn write_unsigned_tx(
+        &self,
+        title: &str,
+        tx: &UnsignedTransaction,
+        network_prefix: NetworkPrefix,
+    ) -> Result<(), DryRunError> {
+        let (inputs, data_inputs) = tx_input_boxes(tx)?;
+        self.write_tx(title, tx, &inputs, &data_inputs, network_prefix)
+    }
+
+    fn write(
+        &self,
+        title: &str,
+        tx: &Value,
+        inputs: &[Value],
+        data_inputs: &[Value],
+        network_prefix: NetworkPrefix,
+    ) -> Result<(), DryRunError> {
+        let eip12 = eip12_tx(tx, inputs, data_inputs);
+        let title = format!("{} (dry run)", title);
+        println!("{}", tx_summary(&title, &eip12, network_prefix));
+        match &self.output {
+            Some(path) => {
+                let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+                writeln!(file, "{}", serde_json::to_string(&eip12)?)?;
+                println!("Unsigned tx appended to {}", path.display());
+            }
+            None => println!("{}", serde_json::to_string_pretty(&eip12)?),
+        }
+        Ok(())
+    }
+}
+
+/// Stands in for the node in the commands that build chains of txs (bootstrap, prepare-update):
+/// the txs are written instead of being signed and submitted. The returned txs have no proofs,
+/// they are only good to build the next txs of the chain.
+pub struct DryRunSigner {
+    pub dry_run: &'static DryRun,
+    pub network_prefix: NetworkPrefix,
+}
+
+impl SignTransaction for DryRunSigner {
+    fn sign_transaction_with_inputs(
+        &self,
+        unsigned_tx: &UnsignedTransaction,
+        inputs: TxIoVec<ErgoBox>,
+        data_boxes: Option<TxIoVec<ErgoBox>>,
+    ) -> Result<Transaction, NodeError> {
+        let data_boxes = data_boxes.map(|bs| bs.as_vec().clone()).unwrap_or_default();
+        self.dry_run
+            .write_tx(
+                "Chained transaction",
+                unsigned_tx,
+                inputs.as_vec(),
+                &data_boxes,
+                self.network_prefix,
+            )
+            .map_err(|e| NodeError::Other(e.to_string()))?;
+        let inputs = unsigned_tx.inputs.clone().mapped(|input| {
+            Input::new(
+                input.box_id,
+                ProverResult {
+                    proof: ProofBytes::Empty,
+                    extension: input.extension,
+                },
+            )
+        });
+        Transaction::new(
+            inputs,
+            unsigned_tx.data_inputs.clone(),
+            unsigned_tx.output_candidates.clone(),
+        )
+        .map_err(|e| NodeError::Other(e.to_string()))
+    }
+}
+
+impl SubmitTransaction for DryRunSigner {
+    fn submit_transaction(&self, tx: &Transaction) -> Result<String, NodeError> {
+        Ok(tx.id().into())
+    }
+}
+
+/// Same summary as in the dry-run mode, for the txs that are about to be signed
+pub fn unsigned_tx_summary(
+    title: &str,
+    tx: &UnsignedTransaction,
+    inputs: &[ErgoBox],
+    data_inputs: &[ErgoBox],
+    network_prefix: NetworkPrefix,
+) -> Result<String, DryRunError> {
+    let eip12 = eip12_tx(
+        &serde_json::to_value(tx)?,
+        &boxes_json(inputs)?,
+        &boxes_json(data_inputs)?,
+    );
+    Ok(tx_summary(title, &eip12, network_prefix))
+}
+
+fn boxes_json(boxes: &[ErgoBox]) -> Result<Vec<Value>, DryRunError> {
+    boxes
+        .iter()
+        .map(|b| serde_json::to_value(b).map_err(DryRunError::from))
+        .collect()
+}
+
+fn json_array(value: &Value) -> Vec<Value> {
+    value.as_array().cloned().unwrap_or_default()
+}
+
+/// Box in the EIP-12 format: the value and the token amounts are strings
+fn eip12_box(ergo_box: &Value) -> Value {
+    let mut ergo_box = ergo_box.clone();
+    if let Some(value) = ergo_box["value"].as_u64() {
+        ergo_box["value"] = json!(value.to_string());
+    }
+    if let Some(assets) = ergo_box["assets"].as_array_mut() {
+        for asset in assets {
+            if let Some(amount) = asset["amount"].as_u64() {
+                asset["amount"] = json!(amount.to_string());
+            }
+        }
+    }
+    ergo_box
+}
+
+/// EIP-12 unsigned tx: the inputs and data inputs are the full boxes, the inputs with the context
+/// extension of the tx input
+fn eip12_tx(tx: &Value, inputs: &[Value], data_inputs: &[Value]) -> Value {
+    let tx_inputs = json_array(&tx["inputs"]);
+    let inputs: Vec<Value> = inputs
+        .iter()
+        .zip(tx_inputs.iter())
+        .map(|(input_box, tx_input)| {
+            let mut input = eip12_box(input_box);
+            input["extension"] = tx_input
+                .get("extension")
+                .cloned()
+                .unwrap_or_else(|| json!({}));
+            input
+        })
+        .collect();
+    json!({
+        "inputs": inputs,
+        "dataInputs": data_inputs.iter().map(eip12_box).collect::<Vec<_>>(),
+        "outputs": json_array(&tx["outputs"]).iter().map(eip12_box).collect::<Vec<_>>(),
+    })
+}
+
+fn amount(value: &Value) -> u64 {
+    value
+        .as_str()
+        .and_then(|s| s.parse().ok())
+        .or_else(|| value.as_u64())
+        .unwrap_or(0)
+}
+
+fn address(ergo_tree_hex: &str, network_prefix: NetworkPrefix) -> String {
+    if ergo_tree_hex == MINERS_FEE_ERGO_TREE {
+        return "miner fee".to_string();
+    }
+    base16::decode(ergo_tree_hex)
+        .ok()
+        .and_then(|bytes| ErgoTree::sigma_parse_bytes(&bytes).ok())
+        .and_then(|tree| Address::recreate_from_ergo_tree(&tree).ok())
+        .map(|address| NetworkAddress::new(network_prefix, &address).to_base58())
+        .unwrap_or_else(|| format!("ergo tree {}", ergo_tree_hex))
+}
+
+/// Token amounts in and out of a tx
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+struct TokenMovement {
+    input: u64,
+    output: u64,
+}
+
+fn token_movements(eip12_tx: &Value) -> BTreeMap<String, TokenMovement> {
+    let mut movements: BTreeMap<String, TokenMovement> = BTreeMap::new();
+    for (field, is_input) in [("inputs", true), ("outputs", false)] {
+        for ergo_box in json_array(&eip12_tx[field]) {
+            for asset in json_array(&ergo_box["assets"]) {
+                let token_id = asset["tokenId"].as_str().unwrap_or_default().to_string();
+                let movement = movements.entry(token_id).or_default();
+                if is_input {
+                    movement.input += amount(&asset["amount"]);
+                } else {
+                    movement.output += amount(&asset["amount"]);
+                }
+            }
+        }
+    }
+    movements
+}
+
+fn tx_fee(eip12_tx: &Value) -> u64 {
+    json_array(&eip12_tx["outputs"])
+        .iter()
+        .filter(|output| output["ergoTree"].as_str() == Some(MINERS_FEE_ERGO_TREE))
+        .map(|output| amount(&output["value"]))
+        .sum()
+}
+
+/// Human-readable summary of an EIP-12 tx
+fn tx_summary(title: &str, eip12_tx: &Value, network_prefix: NetworkPrefix) -> String {
+    let mut summary = format!("=== {} ===\n", title);
+    let mut write_boxes = |heading: &str, boxes: Vec<Value>| {
+        summary.push_str(&format!("{}:\n", heading));
+        for (i, ergo_box) in boxes.iter().enumerate() {
+            let ergo_tree = ergo_box["ergoTree"].as_str().unwrap_or_default();
+            summary.push_str(&format!(
+                "  #{} {} nanoERG at {}\n",
+                i,
+                amount(&ergo_box["value"]),
+                address(ergo_tree, network_prefix)
+            ));
+            if let Some(box_id) = ergo_box["boxId"].as_str() {
+                summary.push_str(&format!("      box id {}\n", box_id));
+            }
+            for asset in json_array(&ergo_box["assets"]) {
+                summary.push_str(&format!(
+                    "      {} of token {}\n",
+                    amount(&asset["amount"]),
+                    asset["tokenId"].as_str().unwrap_or_default()
+                ));
+            }
+        }
+    };
+    write_boxes("Inputs", json_array(&eip12_tx["inputs"]));
+    write_boxes("Data inputs", json_array(&eip12_tx["dataInputs"]));
+    write_boxes("Outputs", json_array(&eip12_tx["outputs"]));
+    summary.push_str("Token movements:\n");
+    for (token_id, movement) in token_movements(eip12_tx) {
+        let change = if movement.output > movement.input {
+            format!(" (minted {})", movement.output - movement.input)
+        } else if movement.output < movement.input {
+            format!(" (burned {})", movement.input - movement.output)
+        } else {
+            String::new()
+        };
+        summary.push_str(&format!(
+            "  {}: {} in, {} out{}\n",
+            token_id, movement.input, movement.output, change
+        ));
+    }
+    summary.push_str(&format!("Fee: {} nanoERG", tx_fee(eip12_tx)));
+    summary
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_eip12_tx_and_summary() {
+        let tx = json!({
+            "inputs": [{"boxId": "b1", "extension": {"0": "0402"}}],
+            "dataInputs": [],
+            "outputs": [
+                {"value": 9000000, "ergoTree": "00", "assets": [
+                    {"tokenId": "t1", "amount": 3},
+                    {"tokenId": "t2", "amount": 1}
+                ]},
+                {"value": 1000000, "ergoTree": MINERS_FEE_ERGO_TREE, "assets": []}
+            ]
+        });
+        let input = json!({
+            "boxId": "b1",
+            "value": 10000000,
+            "ergoTree": "00",
+            "assets": [{"tokenId": "t1", "amount": 5}]
+        });
+        let eip12 = eip12_tx(&tx, &[input], &[]);
+        assert_eq!(eip12["inputs"][0]["value"], json!("10000000"));
+        assert_eq!(eip12["inputs"][0]["extension"]["0"], json!("0402"));
+        assert_eq!(eip12["outputs"][0]["assets"][0]["amount"], json!("3"));
+
+        let movements = token_movements(&eip12);
+        assert_eq!(
+            movements["t1"],
+            TokenMovement {
+                input: 5,
+                output: 3
+            }
+        );
+        assert_eq!(
+            movements["t2"],
+            TokenMovement {
+                input: 0,
+                output: 1
+            }
+        );
+        assert_eq!(tx_fee(&eip12), 1000000);
+        let summary = tx_summary("Test", &eip12, NetworkPrefix::Mainnet);
+        assert!(summary.contains("t1: 5 in, 3 out (burned 2)"));
+        assert!(summary.contains("1000000 nanoERG at miner fee"));
+    }
+}
@@ -0,0 +1,350 @@
+//! Local wallet (`local_wallet_secret_file` in the config): the txs are signed with keys loaded
+//! from a file instead of the node wallet, and the wallet inputs are the boxes of the oracle
+//! address found with a node scan. The node wallet API is only used to trigger the rescan of the
+//! scans.
+
+use std::convert::TryInto;
+use std::path::Path;
+
+use aes_gcm::aead::consts::U16;
+use aes_gcm::aead::{Aead, KeyInit};
+use aes_gcm::aes::Aes256;
+use aes_gcm::{AesGcm, Nonce};
+use derive_more::From;
+use ergo_lib::chain::ergo_state_context::ErgoStateContext;
+use ergo_lib::chain::transaction::unsigned::UnsignedTransaction;
+use ergo_lib::chain::transaction::{Transaction, TxIoVec};
+use ergo_lib::ergo_chain_types::{Header, PreHeader};
+use ergo_lib::ergotree_ir::chain::ergo_box::ErgoBox;
+use ergo_lib::wallet::derivation_path::{ChildIndexHardened, ChildIndexNormal, DerivationPath};
+use ergo_lib::wallet::ext_secret_key::ExtSecretKey;
+use ergo_lib::wallet::mnemonic::Mnemonic;
+use ergo_lib::wallet::secret_key::SecretKey;
+use ergo_lib::wallet::signing::{TransactionContext, TxSigningError};
+use ergo_lib::wallet::{Wallet, WalletError};
+use ergo_node_interface::node_interface::NodeError;
+use hmac::Hmac;
+use once_cell::sync::OnceCell;
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use thiserror::Error;
+
+use crate::node_interface::{get_scan_boxes, last_block_headers, tx_input_boxes, SignTransaction};
+use crate::oracle_config::ORACLE_CONFIG;
+use crate::scans::{NodeScanIds, Scan, ScanError, LOCAL_WALLET_SCAN_NAME};
+use crate::wallet::{WalletDataError, WalletDataSource};
+
+/// Set in `main` when `local_wallet_secret_file` is set in the config
+pub static LOCAL_WALLET: OnceCell<LocalWallet> = OnceCell::new();
+
+/// Number of block headers in the signing context
+pub const SIGNING_CONTEXT_HEADERS: usize = 10;
+/// Number of EIP-3 addresses (m/44'/429'/0'/0/i) derived from a seed to look for the keys
+/// guarding the inputs
+pub const SEED_ADDRESSES: u32 = 20;
+/// Password of the secret file, read from `local_wallet_password_file` or asked on the terminal
+/// if not set
+pub const WALLET_PASSWORD_ENV_VAR: &str = "ORACLE_WALLET_PASSWORD";
+
+/// AES-GCM with the 16 bytes IV used by the Ergo node keystore
+type Aes256Gcm16 = AesGcm<Aes256, U16>;
+
+#[derive(Debug, Error, From)]
+pub enum LocalWalletError {
+    #[error("local wallet: io error {0}")]
+    Io(std::io::Error),
+    #[error("local wallet: json error {0}")]
+    Json(serde_json::Error),
+    #[error("local wallet: node error {0}")]
+    Node(NodeError),
+    #[error("local wallet: scan error {0}")]
+    Scan(ScanError),
+    #[error("local wallet: tx signing error {0}")]
+    TxSigning(TxSigningError),
+    #[error("local wallet: wallet error {0}")]
+    Wallet(WalletError),
+    #[error("local wallet: keystore error: {0}")]
+    #[from(ignore)]
+    Keystore(String),
+    #[error("local wallet: invalid secret: {0}")]
+    #[from(ignore)]
+    InvalidSecret(String),
+    #[error("local wallet: expected 10 block headers, found {0}")]
+    #[from(ignore)]
+    WrongHeaderCount(usize),
+    #[error("local wallet: no key in the secret file for the address {0}")]
+    #[from(ignore)]
+    NoKeyForAddress(String),
+    #[error(
+        "local wallet: no password, set the {} environment variable or local_wallet_password_file",
+        WALLET_PASSWORD_ENV_VAR
+    )]
+    MissingPassword,
+    #[error("local wallet: {0} not found in scanIDs.json")]
+    #[from(ignore)]
+    MissingScan(&'static str),
+}
+
+/// Returns the local wallet if `local_wallet_secret_file` is set in the config
+pub fn local_wallet() -> Option<&'static LocalWallet> {
+    LOCAL_WALLET.get()
+}
+
+/// Loads the keys of the secret file, checks that they include the keys of the oracle addresses
+/// and sets `LOCAL_WALLET`. The scans must be registered first. The password is only asked on the
+/// terminal if `interactive` (not when running as a daemon).
+pub fn init(secret_file: &Path, interactive: bool) -> Result<(), LocalWalletError> {
+    let secrets = load_secrets(secret_file, &wallet_password(interactive)?)?;
+    for address in ORACLE_CONFIG.oracle_addresses() {
+        if !secrets
+            .iter()
+            .any(|secret| secret.get_address_from_public_image() == address.address())
+        {
+            return Err(LocalWalletError::NoKeyForAddress(address.to_base58()));
+        }
+    }
+    let oracle_address = &ORACLE_CONFIG.oracle_address;
+    let scan = NodeScanIds::load()?.scan(LOCAL_WALLET_SCAN_NAME, LOCAL_WALLET_SCAN_NAME);
+    if scan.id() == "null" {
+        return Err(LocalWalletError::MissingScan(LOCAL_WALLET_SCAN_NAME));
+    }
+    let local_wallet = LocalWallet { secrets, scan };
+    if LOCAL_WALLET.set(local_wallet).is_err() {
+        log::warn!("Local wallet already loaded");
+    }
+    log::info!(
+        "Signing with the local wallet of {}",
+        oracle_address.to_base58()
+    );
+    Ok(())
+}
+
+fn wallet_password(interactive: bool) -> Result<String, LocalWalletError> {
+    if let Ok(password) = std::env::var(WALLET_PASSWORD_ENV_VAR) {
+        return Ok(password);
+    }
+    if let Some(password_file) = &ORACLE_CONFIG.local_wallet_password_file {
+        let password = std::fs::read_to_string(password_file)?;
+        return Ok(password.trim_end_matches(&['\r', '\n'][..]).to_string());
+    }
+    if !interactive {
+        return Err(LocalWalletError::MissingPassword);
+    }
+    prompt_password("Local wallet password (leave empty if none): ")
+}
+
+/// Reads a password from the terminal without echoing it. Fails if there is no terminal.
+pub fn prompt_password(prompt: &str) -> Result<String, LocalWalletError> {
+    Ok(rpassword::prompt_password(prompt)?)
+}
+
+pub struct LocalWallet {
+    secrets: Vec<SecretKey>,
+    /// Boxes of the oracle address
+    scan: Scan,
+}
+
+impl LocalWallet {
+    pub fn sign(
+        &self,
+        unsigned_tx: &UnsignedTransaction,
+        inputs: Vec<ErgoBox>,
+        data_inputs: Vec<ErgoBox>,
+    ) -> Result<Transaction, LocalWalletError> {
+        let mut headers = last_block_headers(SIGNING_CONTEXT_HEADERS as u32)?;
+        headers.reverse();
+        let tx_context = TransactionContext::new(unsigned_tx.clone(), inputs, data_inputs)?;
+        let wallet = Wallet::from_secrets(self.secrets.clone());
+        Ok(wallet.sign_transaction(tx_context, &next_block_state_context(headers)?, None)?)
+    }
+
+    /// Signs a tx spending boxes of the UTXO set (or the mempool), fetched from the node
+    pub fn sign_unsigned_tx(
+        &self,
+        unsigned_tx: &UnsignedTransaction,
+    ) -> Result<Transaction, LocalWalletError> {
+        let (inputs, data_inputs) = tx_input_boxes(unsigned_tx)?;
+        self.sign(unsigned_tx, inputs, data_inputs)
+    }
+}
+
+impl SignTransaction for LocalWallet {
+    fn sign_transaction_with_inputs(
+        &self,
+        unsigned_tx: &UnsignedTransaction,
+        inputs: TxIoVec<ErgoBox>,
+        data_boxes: Option<TxIoVec<ErgoBox>>,
+    ) -> Result<Transaction, NodeError> {
+        self.sign(
+            unsigned_tx,
+            inputs.as_vec().clone(),
+            data_boxes.map(|bs| bs.as_vec().clone()).unwrap_or_default(),
+        )
+        .map_err(|e| NodeError::Other(e.to_string()))
+    }
+}
+
+impl WalletDataSource for LocalWallet {
+    fn get_unspent_wallet_boxes(&self) -> Result<Vec<ErgoBox>, WalletDataError> {
+        get_scan_boxes(self.scan.id()).map_err(Into::into)
+    }
+}
+
+/// Signing context of the block following `headers` (the last block headers, newest first)
+pub fn next_block_state_context(
+    headers: Vec<Header>,
+) -> Result<ErgoStateContext, LocalWalletError> {
+    let headers: [Header; SIGNING_CONTEXT_HEADERS] = headers
+        .try_into()
+        .map_err(|headers: Vec<Header>| LocalWalletError::WrongHeaderCount(headers.len()))?;
+    let last_header = &headers[0];
+    let pre_header = PreHeader {
+        parent_id: last_header.id.clone(),
+        height: last_header.height + 1,
+        ..PreHeader::from(last_header.clone())
+    };
+    Ok(ErgoStateContext::new(pre_header, headers))
+}
+
+/// Loads the keys of a file containing either an Ergo node keystore (the password is the wallet
+/// password), a mnemonic (the password is the mnemonic password) or a base16 encoded secret key.
+/// For a keystore or a mnemonic the keys are the root key and the keys of the first EIP-3
+/// addresses.
+pub fn load_secrets(
+    secret_file: &Path,
+    password: &str,
+) -> Result<Vec<SecretKey>, LocalWalletError> {
+    let secret = std::fs::read_to_string(secret_file)?;
+    let secret = secret.trim();
+    if secret.starts_with('{') {
+        let keystore: Keystore = serde_json::from_str(secret)?;
+        let seed: [u8; 64] =
+            keystore
+                .decrypt_seed(password)?
+                .try_into()
+                .map_err(|seed: Vec<u8>| {
+                    LocalWalletError::Keystore(format!(
+                        "expected a 64 bytes seed, found {}",
+                        seed.len()
+                    ))
+                })?;
+        return seed_secrets(seed);
+    }
+    if let Ok(bytes) = base16::decode(secret) {
+        let bytes: [u8; 32] = bytes.try_into().map_err(|bytes: Vec<u8>| {
+            LocalWalletError::InvalidSecret(format!("expected 32 bytes, found {}", bytes.len()))
+        })?;
+        let secret_key = SecretKey::dlog_from_bytes(&bytes)
+            .ok_or_else(|| LocalWalletError::InvalidSecret("not a valid secret key".to_string()))?;
+        return Ok(vec![secret_key]);
+    }
+    seed_secrets(Mnemonic::to_seed(secret, password))
+}
+
+fn seed_secrets(seed: [u8; 64]) -> Result<Vec<SecretKey>, LocalWalletError> {
+    let invalid = |e: &dyn std::fmt::Display| LocalWalletError::InvalidSecret(e.to_string());
+    let root_key = ExtSecretKey::derive_master(seed).map_err(|e| invalid(&e))?;
+    let mut secrets = vec![root_key.secret_key()];
+    for index in 0..SEED_ADDRESSES {
+        let account = ChildIndexHardened::from_31_bit(0).map_err(|e| invalid(&e))?;
+        let address_index = ChildIndexNormal::normal(index).map_err(|e| invalid(&e))?;
+        let path = DerivationPath::new(account, vec![address_index]);
+        secrets.push(root_key.derive(path).map_err(|e| invalid(&e))?.secret_key());
+    }
+    Ok(secrets)
+}
+
+/// Ergo node keystore (`.ergo/wallet/keystore/*.json`): the wallet seed encrypted with AES-GCM,
+/// with a key derived from the wallet password with PBKDF2
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Keystore {
+    cipher_text: String,
+    salt: String,
+    iv: String,
+    auth_tag: String,
+    cipher_params: KeystoreCipherParams,
+    #[serde(default)]
+    use_pre1627_key_derivation: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct KeystoreCipherParams {
+    prf: String,
+    /// PBKDF2 iterations
+    c: u32,
+    /// Key length in bits
+    dk_len: u32,
+}
+
+impl Keystore {
+    fn decrypt_seed(&self, password: &str) -> Result<Vec<u8>, LocalWalletError> {
+        let unsupported = |what: String| Err(LocalWalletError::Keystore(what));
+        if self.cipher_params.prf != "HmacSHA256" {
+            return unsupported(format!("unsupported prf {}", self.cipher_params.prf));
+        }
+        if self.cipher_params.dk_len != 256 {
+            return unsupported(format!(
+                "unsupported key length {}",
+                self.cipher_params.dk_len
+            ));
+        }
+        if self.use_pre1627_key_derivation {
+            return unsupported("pre-1627 key derivation is not supported".to_string());
+        }
+        let decode = |field: &str, hex: &str| {
+            base16::decode(hex).map_err(|e| LocalWalletError::Keystore(format!("{}: {}", field, e)))
+        };
+        let iv = decode("iv", &self.iv)?;
+        if iv.len() != 16 {
+            return unsupported(format!("expected a 16 bytes iv, found {}", iv.len()));
+        }
+        let mut key = [0u8; 32];
+        pbkdf2::pbkdf2::<Hmac<Sha256>>(
+            password.as_bytes(),
+            &decode("salt", &self.salt)?,
+            self.cipher_params.c,
+            &mut key,
+        );
+        let mut data = decode("cipherText", &self.cipher_text)?;
+        data.append(&mut decode("authTag", &self.auth_tag)?);
+        Aes256Gcm16::new_from_slice(&key)
+            .map_err(|e| LocalWalletError::Keystore(e.to_string()))?
+            .decrypt(Nonce::from_slice(&iv), data.as_slice())
+            .map_err(|_| LocalWalletError::Keystore("wrong password".to_string()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decrypt_keystore() {
+        let seed = [7u8; 64];
+        let salt = [1u8; 32];
+        let iv = [2u8; 16];
+        let mut key = [0u8; 32];
+        pbkdf2::pbkdf2::<Hmac<Sha256>>(b"password", &salt, 1000, &mut key);
+        let mut encrypted = Aes256Gcm16::new_from_slice(&key)
+            .unwrap()
+            .encrypt(Nonce::from_slice(&iv), &seed[..])
+            .unwrap();
+        let auth_tag = encrypted.split_off(seed.len());
+        let keystore: Keystore = serde_json::from_value(serde_json::json!({
+            "cipherText": base16::encode_lower(&encrypted),
+            "salt": base16::encode_lower(&salt),
+            "iv": base16::encode_lower(&iv),
+            "authTag": base16::encode_lower(&auth_tag),
+            "cipherParams": {"prf": "HmacSHA256", "c": 1000, "dkLen": 256},
+            "usePre1627KeyDerivation": false
+        }))
+        .unwrap();
+        assert_eq!(keystore.decrypt_seed("password").unwrap(), seed.to_vec());
+        assert!(matches!(
+            keystore.decrypt_seed("wrong"),
+            Err(LocalWalletError::Keystore(_))
+        ));
+    }
+}
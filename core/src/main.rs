@@ -16,6 +16,7 @@
 #[macro_use]
 extern crate lazy_static;
 
+mod action_history;
 mod actions;
 mod address_util;
 mod api;
@@ -24,8 +25,12 @@ mod cli_commands;
 mod contracts;
 mod datapoint_source;
 mod default_parameters;
+mod dry_run;
+mod local_wallet;
 mod logging;
+mod metrics;
 mod node_interface;
+mod offline_signing;
 mod oracle_config;
 mod oracle_state;
 mod pool_commands;
@@ -37,16 +42,23 @@ mod templates;
 mod tests;
 mod wallet;
 
+use action_history::ActionHistory;
+use action_history::ActionRecord;
 use actions::execute_action;
+use actions::ActionExecError;
 use actions::PoolAction;
 use anyhow::anyhow;
 use anyhow::Context;
 use clap::{Parser, Subcommand};
 use crossbeam::channel::bounded;
+use crossbeam::channel::unbounded;
+use crossbeam::channel::Sender;
+use dry_run::DryRunError;
 use ergo_lib::ergotree_ir::chain::address::Address;
 use ergo_lib::ergotree_ir::chain::address::AddressEncoder;
 use ergo_lib::ergotree_ir::chain::address::NetworkAddress;
 use ergo_lib::ergotree_ir::chain::address::NetworkPrefix;
+use ergo_lib::ergotree_ir::chain::ergo_box::BoxId;
 use ergo_lib::ergotree_ir::chain::token::Token;
 use ergo_lib::ergotree_ir::chain::token::TokenId;
 use log::debug;
@@ -54,23 +66,35 @@ use log::error;
 use log::LevelFilter;
 use node_interface::assert_wallet_unlocked;
 use node_interface::current_block_height;
-use node_interface::get_wallet_status;
 use node_interface::new_node_interface;
+use node_interface::TxId;
 use oracle_config::ORACLE_CONFIG;
 use oracle_state::register_and_save_scans;
+use oracle_state::MissingScansRegistration;
+use oracle_state::OracleIdentity;
 use oracle_state::OraclePool;
 use pool_commands::build_action;
-use pool_commands::publish_datapoint::PublishDatapointActionError::DataPointSource;
+use pool_commands::publish_datapoint::PublishDatapointActionError::{
+    DataPointSource, DatapointRejected,
+};
 use pool_commands::refresh::RefreshActionError;
+use pool_commands::PoolCommand;
 use pool_commands::PoolCommandError;
 use state::process;
 use state::PoolState;
 use std::convert::TryInto;
-use std::thread;
+use std::path::PathBuf;
+use std::sync::Arc;
 use std::time::Duration;
+use std::time::Instant;
+use wallet::ReservingWallet;
 use wallet::WalletData;
+use wallet::WalletDataSource;
 
-use crate::api::start_rest_server;
+use crate::api::commands::{ApiCommand, ApiCommandRequest};
+use crate::api::events::{self, PoolEvent, PoolEventSender};
+use crate::api::health::HealthChecks;
+use crate::api::{start_rest_server_thread, ApiSnapshot, SharedApiSnapshot};
 use crate::default_parameters::print_contract_hashes;
 use crate::oracle_config::MAYBE_ORACLE_CONFIG;
 
@@ -107,6 +131,22 @@ struct Args {
     /// Set path of configuration file to use. Default is ./oracle_config.yaml
     #[clap(short, long)]
     config_file: Option<String>,
+    /// Write the transactions as EIP-12 JSON, with a summary of their inputs, outputs, token
+    /// movements and fee, instead of signing and submitting them. Implies `--read-only` for `run`.
+    #[clap(long, global = true)]
+    dry_run: bool,
+    /// File to append the dry-run transactions to (one JSON per line). Default is stdout
+    #[clap(long, global = true, requires = "dry_run")]
+    dry_run_output: Option<PathBuf>,
+    /// Export the transaction with its input boxes and the last block headers to this file instead
+    /// of signing it with the node wallet (which can stay locked). The wallet inputs are the boxes
+    /// of the oracle address. Sign it on another machine with `sign-offline` and submit it with
+    /// `submit`.
+    #[clap(long, global = true, conflicts_with = "dry_run")]
+    export_unsigned: Option<PathBuf>,
+    /// Change address of the exported transaction. Default is the oracle address
+    #[clap(long, global = true, requires = "export_unsigned")]
+    change_address: Option<String>,
 }
 
 #[derive(Debug, Subcommand)]
@@ -128,7 +168,8 @@ enum Command {
         #[clap(long)]
         read_only: bool,
         #[clap(long)]
-        /// Set this flag to enable the REST API. NOTE: SSL is not used!
+        /// Set this flag to enable the REST API. Set `core_api_tls` in the config file to serve it
+        /// over HTTPS.
         enable_rest_api: bool,
     },
 
@@ -177,6 +218,54 @@ enum Command {
 
     /// Print base 64 encodings of the blake2b hash of ergo-tree bytes of each contract
     PrintContractHashes,
+
+    /// Print the actions built by the `run` command and the status of their transactions, newest
+    /// first
+    PrintActionHistory {
+        /// Number of actions to print
+        #[clap(short, long, default_value_t = 20)]
+        limit: u32,
+    },
+
+    /// Sign a transaction exported with `--export-unsigned` with a local wallet. Doesn't need a
+    /// node or a config file, meant to run on an air-gapped machine.
+    SignOffline {
+        /// The file written by `--export-unsigned`
+        bundle_file: PathBuf,
+        /// File with either the node keystore, the mnemonic or the base16 encoded secret key of the
+        /// wallet
+        #[clap(long)]
+        secret_file: PathBuf,
+        /// File to write the signed transaction to
+        #[clap(long, default_value = "signed_tx.json")]
+        signed_tx_file: PathBuf,
+    },
+
+    /// Submit a transaction signed with `sign-offline`
+    Submit {
+        /// The file written by `sign-offline`
+        signed_tx_file: PathBuf,
+    },
+}
+
+impl Command {
+    /// Commands ending in a single transaction, which can be exported for offline signing
+    fn supports_offline_signing(&self) -> bool {
+        match self {
+            Command::ExtractRewardTokens { .. }
+            | Command::TransferOracleToken { .. }
+            | Command::VoteUpdatePool { .. }
+            | Command::UpdatePool { .. } => true,
+            Command::Bootstrap { .. }
+            | Command::Run { .. }
+            | Command::PrintRewardTokens
+            | Command::PrepareUpdate { .. }
+            | Command::PrintContractHashes
+            | Command::PrintActionHistory { .. }
+            | Command::SignOffline { .. }
+            | Command::Submit { .. } => false,
+        }
+    }
 }
 
 fn main() {
@@ -188,6 +277,11 @@ fn main() {
                 .unwrap_or_else(|| oracle_config::DEFAULT_CONFIG_FILE_NAME.to_string()),
         )
         .unwrap();
+    if args.dry_run {
+        dry_run::DRY_RUN
+            .set(dry_run::DryRun::new(args.dry_run_output))
+            .unwrap();
+    }
 
     let cmdline_log_level = if args.verbose {
         Some(LevelFilter::Debug)
@@ -198,6 +292,26 @@ fn main() {
 
     log_on_launch();
 
+    if let Some(path) = args.export_unsigned {
+        if !args.command.supports_offline_signing() {
+            error!(
+                "--export-unsigned is only supported by the extract-reward-tokens, \
+                transfer-oracle-token, vote-update-pool and update-pool commands"
+            );
+            std::process::exit(exitcode::USAGE);
+        }
+        offline_signing::EXPORT_PATH.set(path).unwrap();
+    }
+    if let Some(change_address) = args.change_address {
+        if let Err(e) = AddressEncoder::unchecked_parse_network_address_from_str(&change_address) {
+            error!("Invalid --change-address {}: {}", change_address, e);
+            std::process::exit(exitcode::USAGE);
+        }
+        offline_signing::EXPORT_CHANGE_ADDRESS
+            .set(change_address)
+            .unwrap();
+    }
+
     #[allow(clippy::wildcard_enum_match_arm)]
     match args.command {
         Command::Bootstrap {
@@ -221,35 +335,143 @@ fn main() {
         Command::PrintContractHashes => {
             print_contract_hashes();
         }
+        Command::PrintActionHistory { limit } => {
+            if let Err(e) = cli_commands::print_action_history::print_action_history(limit) {
+                error!("Fatal print-action-history error: {}", e);
+                std::process::exit(exitcode::SOFTWARE);
+            }
+        }
+        Command::SignOffline {
+            bundle_file,
+            secret_file,
+            signed_tx_file,
+        } => {
+            if let Err(e) = cli_commands::sign_offline::sign_offline(
+                &bundle_file,
+                &secret_file,
+                &signed_tx_file,
+            ) {
+                error!("Fatal sign-offline error: {}", e);
+                std::process::exit(exitcode::SOFTWARE);
+            }
+        }
+        Command::Submit { signed_tx_file } => {
+            if let Err(e) = cli_commands::submit_tx::submit_tx(&signed_tx_file) {
+                error!("Fatal submit error: {}", e);
+                std::process::exit(exitcode::SOFTWARE);
+            }
+        }
         oracle_command => handle_oracle_command(oracle_command),
     }
 }
 
 /// Handle all non-bootstrap commands that require ORACLE_CONFIG/OraclePool
 fn handle_oracle_command(command: Command) {
-    assert_wallet_unlocked(&new_node_interface());
+    // The scans are registered in all the reachable nodes right after
+    if let Err(e) = node_interface::select_node(None) {
+        error!(
+            "No node available, using {}: {}",
+            node_interface::active_node_endpoint(),
+            e
+        );
+    }
+    let node_wallet_signs = ORACLE_CONFIG.local_wallet_secret_file.is_none();
+    if node_wallet_signs && offline_signing::export_path().is_none() {
+        assert_wallet_unlocked(&new_node_interface());
+    }
     register_and_save_scans().unwrap();
+    if let Some(secret_file) = &ORACLE_CONFIG.local_wallet_secret_file {
+        // `run` is usually a daemon without a terminal to ask the password on
+        let interactive = !matches!(command, Command::Run { .. });
+        if let Err(e) = local_wallet::init(secret_file, interactive) {
+            error!("Failed to load the local wallet: {}", e);
+            std::process::exit(exitcode::SOFTWARE);
+        }
+    }
     let op = OraclePool::new().unwrap();
     match command {
         Command::Run {
             read_only,
             enable_rest_api,
         } => {
-            assert_wallet_unlocked(&new_node_interface());
-            let (_, repost_receiver) = bounded::<bool>(1);
-            let op = OraclePool::new().unwrap();
+            if node_wallet_signs {
+                assert_wallet_unlocked(&new_node_interface());
+            }
+            let read_only = read_only || dry_run::dry_run().is_some();
+            let (repost_sender, repost_receiver) = bounded::<bool>(1);
+            // Commands from the REST API write endpoints, handled between the loop iterations
+            let (command_sender, command_receiver) = unbounded::<ApiCommandRequest>();
+            let (event_sender, _) =
+                tokio::sync::broadcast::channel::<PoolEvent>(events::EVENT_CHANNEL_CAPACITY);
+            let op = OraclePool::new().unwrap().with_data_point_metrics();
+            let op = match ORACLE_CONFIG.data_point_prefetch_interval() {
+                Some(interval) => op.with_data_point_prefetch(interval),
+                None => op,
+            };
+
+            let history_path = oracle_config::get_action_history_path();
+            let history = match ActionHistory::open(&history_path) {
+                Ok(history) => Arc::new(history),
+                Err(e) => {
+                    error!(
+                        "Failed to open action history {}: {}",
+                        history_path.display(),
+                        e
+                    );
+                    std::process::exit(exitcode::SOFTWARE);
+                }
+            };
 
             // Start Oracle Core GET API Server
-            if enable_rest_api {
-                let rt = tokio::runtime::Runtime::new().unwrap();
-                rt.block_on(start_rest_server(repost_receiver));
-            }
+            let api_snapshot = if enable_rest_api {
+                let api_snapshot = SharedApiSnapshot::default();
+                start_rest_server_thread(
+                    repost_receiver,
+                    api_snapshot.clone(),
+                    command_sender.clone(),
+                    event_sender.clone(),
+                    history.clone(),
+                )
+                .unwrap();
+                Some(api_snapshot)
+            } else {
+                None
+            };
+            let mut paused = false;
             loop {
-                if let Err(e) = main_loop_iteration(&op, read_only) {
+                if paused {
+                    debug!("Main loop is paused");
+                } else if let Err(e) = main_loop_iteration(
+                    &op,
+                    read_only,
+                    api_snapshot.as_ref(),
+                    &repost_sender,
+                    &event_sender,
+                    &history,
+                ) {
                     error!("error: {:?}", e);
                 }
-                // Delay loop restart
-                thread::sleep(Duration::new(30, 0));
+                if let Some(api_snapshot) = &api_snapshot {
+                    let checks = HealthChecks::run(&op, paused);
+                    api_snapshot.write().unwrap().health = Some(checks);
+                }
+                // Delay loop restart, handling the REST API commands in the meantime
+                let deadline = Instant::now() + Duration::new(30, 0);
+                while let Ok(request) = command_receiver.recv_deadline(deadline) {
+                    let result = handle_api_command(
+                        &op,
+                        read_only,
+                        &mut paused,
+                        &event_sender,
+                        &history,
+                        request.command,
+                    );
+                    if let Err(e) = &result {
+                        error!("REST API command failed: {:?}", e);
+                    }
+                    // The handler may be gone if the client disconnected
+                    let _ = request.reply.send(result.map_err(|e| e.to_string()));
+                }
             }
         }
 
@@ -332,46 +554,420 @@ fn handle_oracle_command(command: Command) {
                 std::process::exit(exitcode::SOFTWARE);
             }
         }
-        Command::Bootstrap { .. } | Command::PrintContractHashes => unreachable!(),
+        Command::Bootstrap { .. }
+        | Command::PrintContractHashes
+        | Command::PrintActionHistory { .. }
+        | Command::SignOffline { .. }
+        | Command::Submit { .. } => unreachable!(),
     }
 }
 
-fn main_loop_iteration(op: &OraclePool, read_only: bool) -> std::result::Result<(), anyhow::Error> {
+fn main_loop_iteration(
+    op: &OraclePool,
+    read_only: bool,
+    api_snapshot: Option<&SharedApiSnapshot>,
+    repost_sender: &Sender<bool>,
+    event_sender: &PoolEventSender,
+    history: &ActionHistory,
+) -> std::result::Result<(), anyhow::Error> {
+    // Switch to another node if the one in use is down, don't act if the nodes disagree. The scans
+    // are registered in the nodes which were down on start once they are back.
+    node_interface::select_node(Some(&MissingScansRegistration))
+        .context("Failed to select a node")?;
     let height = current_block_height().context("Failed to get the current height")? as u32;
+    let pending = pending_actions(history, height);
     let wallet = WalletData::new();
-    let network_change_address = get_change_address_from_node()?;
-    let pool_state = match op.get_live_epoch_state() {
-        Ok(live_epoch_state) => PoolState::LiveEpoch(live_epoch_state),
-        Err(error) => {
-            log::debug!("error getting live epoch state: {}", error);
-            PoolState::NeedsBootstrap
+    let network_change_address = get_change_address()?;
+    if let Some(api_snapshot) = api_snapshot {
+        let pool_state = match op.get_live_epoch_state() {
+            Ok(live_epoch_state) => PoolState::LiveEpoch(live_epoch_state),
+            Err(error) => {
+                log::debug!("error getting live epoch state: {}", error);
+                PoolState::NeedsBootstrap
+            }
+        };
+        let snapshot = ApiSnapshot::new(op, height, pool_state.clone());
+        metrics::update_from_snapshot(&snapshot);
+        metrics::update_wallet_balance(&wallet, network_change_address.network());
+        for event in events::snapshot_events(&api_snapshot.read().unwrap(), &snapshot) {
+            events::publish(event_sender, event);
         }
-    };
+        let mut guard = api_snapshot.write().unwrap();
+        let health = guard.health.take();
+        *guard = ApiSnapshot { health, ..snapshot };
+    }
     let epoch_length = ORACLE_CONFIG
         .refresh_box_wrapper_inputs
         .contract_inputs
         .contract_parameters()
         .epoch_length() as u32;
+    // The identities share the wallet and the pool boxes, the boxes spent by the action of one
+    // identity (or by a pending tx) are not available to the next ones until the next iteration
+    let mut wallet = ReservingWallet::new(&wallet);
+    for record in &pending {
+        wallet.reserve(&record.input_box_ids);
+    }
+    for identity in op.identities() {
+        if let Some(record) = identity_pending_action(&pending, identity) {
+            log::info!(
+                "Height {height}. Oracle {} is waiting for {} tx {} to be confirmed",
+                identity.address.to_base58(),
+                record.action,
+                record.tx_id.as_deref().unwrap_or_default()
+            );
+            continue;
+        }
+        if let Err(e) = identity_iteration(
+            op,
+            identity,
+            &mut wallet,
+            epoch_length,
+            height,
+            read_only,
+            &network_change_address,
+            repost_sender,
+            event_sender,
+            history,
+        ) {
+            error!("Oracle {} error: {:?}", identity.address.to_base58(), e);
+        }
+    }
+    Ok(())
+}
+
+/// Builds and submits the action of the oracle identity, if any
+#[allow(clippy::too_many_arguments)]
+fn identity_iteration(
+    op: &OraclePool,
+    identity: &OracleIdentity,
+    wallet: &mut ReservingWallet,
+    epoch_length: u32,
+    height: u32,
+    read_only: bool,
+    network_change_address: &NetworkAddress,
+    repost_sender: &Sender<bool>,
+    event_sender: &PoolEventSender,
+    history: &ActionHistory,
+) -> std::result::Result<(), anyhow::Error> {
+    let pool_state = match op.get_identity_live_epoch_state(identity) {
+        Ok(live_epoch_state) => PoolState::LiveEpoch(live_epoch_state),
+        Err(error) => {
+            log::debug!("error getting live epoch state: {}", error);
+            PoolState::NeedsBootstrap
+        }
+    };
     if let Some(cmd) = process(pool_state, epoch_length, height) {
-        log::info!("Height {height}. Building action for command: {:?}", cmd);
+        log::info!(
+            "Height {height}. Building action for command: {:?} (oracle {})",
+            cmd,
+            identity.address.to_base58()
+        );
+        if matches!(
+            cmd,
+            PoolCommand::PublishSubsequentDataPoint { republish: true }
+        ) {
+            // Served by /requireDatapointRepost, ignored if the previous one wasn't read yet
+            let _ = repost_sender.try_send(true);
+        }
+        let action_name = metrics::pool_command_name(&cmd);
         let build_action_res = build_action(
             cmd,
             op,
-            &wallet,
+            identity,
+            &*wallet,
             height as u32,
             network_change_address.address(),
         );
+        if let Err(e) = &build_action_res {
+            metrics::record_build_failure(action_name, e);
+        }
         if let Some(action) =
             log_and_continue_if_non_fatal(network_change_address.network(), build_action_res)?
         {
-            if !read_only {
-                execute_action(action)?;
+            if wallet.spends_reserved(action.tx()) {
+                // e.g. the refresh of another identity already collects our datapoint box
+                log::info!(
+                    "Skipping {} action of oracle {}, its inputs are spent by another action",
+                    action_name,
+                    identity.address.to_base58()
+                );
+                return Ok(());
+            }
+            wallet.reserve_inputs(action.tx());
+            if read_only {
+                record_built_action(history, identity, action_name, &action, height);
+                write_dry_run_action(action_name, &action, network_change_address.network())?;
+            } else {
+                submit_action(action, identity, action_name, height, event_sender, history)?;
             }
         };
     }
     Ok(())
 }
 
+fn handle_api_command(
+    op: &OraclePool,
+    read_only: bool,
+    paused: &mut bool,
+    event_sender: &PoolEventSender,
+    history: &ActionHistory,
+    command: ApiCommand,
+) -> Result<String, anyhow::Error> {
+    log::info!("REST API command: {:?}", command);
+    match command {
+        ApiCommand::PublishDatapoint { oracle_address } => {
+            let identity = api_command_identity(op, oracle_address.as_deref())?;
+            let cmd = if identity
+                .get_local_datapoint_box_source()
+                .get_local_oracle_datapoint_box()?
+                .is_some()
+            {
+                PoolCommand::PublishSubsequentDataPoint { republish: true }
+            } else {
+                PoolCommand::PublishFirstDataPoint
+            };
+            run_pool_command_now(op, identity, cmd, read_only, event_sender, history)
+        }
+        ApiCommand::Refresh { oracle_address } => {
+            let identity = api_command_identity(op, oracle_address.as_deref())?;
+            run_pool_command_now(
+                op,
+                identity,
+                PoolCommand::Refresh,
+                read_only,
+                event_sender,
+                history,
+            )
+        }
+        ApiCommand::Pause => {
+            *paused = true;
+            Ok("main loop paused".to_string())
+        }
+        ApiCommand::Resume => {
+            *paused = false;
+            Ok("main loop resumed".to_string())
+        }
+        ApiCommand::ExtractRewardTokens {
+            oracle_address,
+            rewards_address,
+        } => {
+            let identity = api_command_identity(op, oracle_address.as_deref())?;
+            run_action_now(
+                identity,
+                "extract_reward_tokens",
+                read_only,
+                event_sender,
+                history,
+                |wallet, _, _| {
+                    let action =
+                        cli_commands::extract_reward_tokens::build_extract_reward_tokens_action(
+                            wallet,
+                            identity.get_local_datapoint_box_source(),
+                            &rewards_address,
+                        )?;
+                    Ok(action.into())
+                },
+            )
+        }
+    }
+}
+
+/// The oracle identity targeted by a REST API command, the primary one if no address is given
+fn api_command_identity<'a, 'b>(
+    op: &'b OraclePool<'a>,
+    oracle_address: Option<&str>,
+) -> Result<&'b OracleIdentity<'a>, anyhow::Error> {
+    op.identity(oracle_address).ok_or_else(|| {
+        anyhow!(
+            "unknown oracle address {}",
+            oracle_address.unwrap_or_default()
+        )
+    })
+}
+
+/// Builds and submits the action for the command right away, regardless of the pool state
+fn run_pool_command_now(
+    op: &OraclePool,
+    identity: &OracleIdentity,
+    cmd: PoolCommand,
+    read_only: bool,
+    event_sender: &PoolEventSender,
+    history: &ActionHistory,
+) -> Result<String, anyhow::Error> {
+    let action_name = metrics::pool_command_name(&cmd);
+    run_action_now(
+        identity,
+        action_name,
+        read_only,
+        event_sender,
+        history,
+        |wallet, height, network_change_address| {
+            let build_action_res = build_action(
+                cmd,
+                op,
+                identity,
+                wallet,
+                height,
+                network_change_address.address(),
+            );
+            if let Err(e) = &build_action_res {
+                metrics::record_build_failure(action_name, e);
+            }
+            Ok(build_action_res?)
+        },
+    )
+}
+
+/// Builds the action of the identity with the inputs of the pending txs reserved, and submits it
+/// (or writes it in read-only mode) like the actions of the main loop
+fn run_action_now<F>(
+    identity: &OracleIdentity,
+    action_name: &str,
+    read_only: bool,
+    event_sender: &PoolEventSender,
+    history: &ActionHistory,
+    build: F,
+) -> Result<String, anyhow::Error>
+where
+    F: FnOnce(&dyn WalletDataSource, u32, &NetworkAddress) -> Result<PoolAction, anyhow::Error>,
+{
+    let height = current_block_height().context("Failed to get the current height")? as u32;
+    let pending = pending_actions(history, height);
+    if let Some(record) = identity_pending_action(&pending, identity) {
+        return Err(anyhow!(
+            "{} tx {} is not confirmed yet",
+            record.action,
+            record.tx_id.as_deref().unwrap_or_default()
+        ));
+    }
+    let node_wallet = WalletData::new();
+    let mut wallet = ReservingWallet::new(&node_wallet);
+    for record in &pending {
+        wallet.reserve(&record.input_box_ids);
+    }
+    let network_change_address = get_change_address()?;
+    let action = build(&wallet, height, &network_change_address)?;
+    if wallet.spends_reserved(action.tx()) {
+        return Err(anyhow!(
+            "{} action spends the inputs of a tx which is not confirmed yet",
+            action_name
+        ));
+    }
+    if read_only {
+        record_built_action(history, identity, action_name, &action, height);
+        write_dry_run_action(action_name, &action, network_change_address.network())?;
+        return Ok(format!(
+            "{} action built, not submitted in read-only mode",
+            action_name
+        ));
+    }
+    let tx_id = submit_action(action, identity, action_name, height, event_sender, history)?;
+    Ok(format!("{} action submitted in tx {}", action_name, tx_id))
+}
+
+/// Updates the status of our submitted txs in the history and returns the ones still waiting in
+/// the mempool. An evicted tx is rebuilt on the next iteration.
+fn pending_actions(history: &ActionHistory, height: u32) -> Vec<ActionRecord> {
+    if let Err(e) = history.update_pending(height, node_interface::tx_status) {
+        error!("Failed to update the action history: {}", e);
+    }
+    history.pending().unwrap_or_else(|e| {
+        error!("Failed to read the pending actions from the history: {}", e);
+        Vec::new()
+    })
+}
+
+/// The last pending tx of the identity. No action is built for the identity while its tx is
+/// pending since the tx spends its oracle box.
+fn identity_pending_action<'r>(
+    pending: &'r [ActionRecord],
+    identity: &OracleIdentity,
+) -> Option<&'r ActionRecord> {
+    let address = identity.address.to_base58();
+    pending
+        .iter()
+        .rev()
+        .find(|record| record.oracle_address == address)
+}
+
+/// Writes the tx of the built action in dry-run mode
+fn write_dry_run_action(
+    action_name: &str,
+    action: &PoolAction,
+    network_prefix: NetworkPrefix,
+) -> Result<(), DryRunError> {
+    match dry_run::dry_run() {
+        Some(dry_run) => dry_run.write_unsigned_tx(action_name, action.tx(), network_prefix),
+        None => Ok(()),
+    }
+}
+
+/// Records the built action in the history. Failures are only logged, they must not stop the
+/// oracle.
+fn record_built_action(
+    history: &ActionHistory,
+    identity: &OracleIdentity,
+    action_name: &str,
+    action: &PoolAction,
+    height: u32,
+) -> Option<i64> {
+    let input_box_ids: Vec<BoxId> = action
+        .tx()
+        .inputs
+        .as_vec()
+        .iter()
+        .map(|input| input.box_id.clone())
+        .collect();
+    history
+        .record_built(
+            action_name,
+            &identity.address.to_base58(),
+            height,
+            action.datapoint(),
+            *action.fee().as_u64(),
+            &input_box_ids,
+        )
+        .map_err(|e| {
+            error!(
+                "Failed to record {} action in the history: {}",
+                action_name, e
+            )
+        })
+        .ok()
+}
+
+/// Executes the action, recording the metrics, the history and the event for the submitted tx
+fn submit_action(
+    action: PoolAction,
+    identity: &OracleIdentity,
+    action_name: &str,
+    height: u32,
+    event_sender: &PoolEventSender,
+    history: &ActionHistory,
+) -> Result<TxId, ActionExecError> {
+    let record_id = record_built_action(history, identity, action_name, &action, height);
+    let exec_res = execute_action(action);
+    metrics::record_execution(action_name, &exec_res);
+    if let Some(record_id) = record_id {
+        if let Err(e) = history.record_submission(record_id, &exec_res) {
+            error!(
+                "Failed to record {} submission in the history: {}",
+                action_name, e
+            );
+        }
+    }
+    if let Ok(tx_id) = &exec_res {
+        events::publish(
+            event_sender,
+            PoolEvent::ActionSubmitted {
+                action: action_name.to_string(),
+                tx_id: tx_id.clone(),
+            },
+        );
+    }
+    exec_res
+}
+
 fn log_and_continue_if_non_fatal(
     network_prefix: NetworkPrefix,
     res: Result<PoolAction, PoolCommandError>,
@@ -395,13 +991,16 @@ fn log_and_continue_if_non_fatal(
             log::error!("Failed to get datapoint with error: {}", e);
             Ok(None)
         }
+        Err(PoolCommandError::PublishDatapointActionError(DatapointRejected(e))) => {
+            log::error!("Refusing to publish datapoint: {}", e);
+            Ok(None)
+        }
         Err(e) => Err(e),
     }
 }
 
-fn get_change_address_from_node() -> Result<NetworkAddress, anyhow::Error> {
-    let change_address_str = get_wallet_status()?
-        .change_address
+fn get_change_address() -> Result<NetworkAddress, anyhow::Error> {
+    let change_address_str = wallet::change_address()?
         .ok_or_else(|| anyhow!("failed to get wallet's change address (locked wallet?)"))?;
     let addr = AddressEncoder::unchecked_parse_network_address_from_str(&change_address_str)?;
     Ok(addr)
@@ -0,0 +1,276 @@
+//! Prometheus metrics, served by the REST API at `/metrics`
+
+use std::collections::BTreeMap;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use ergo_lib::ergotree_ir::chain::address::{Address, NetworkAddress, NetworkPrefix};
+use prometheus::{
+    register_histogram, register_int_counter, register_int_counter_vec, register_int_gauge,
+    register_int_gauge_vec, Encoder, Histogram, IntCounter, IntCounterVec, IntGauge, IntGaugeVec,
+    TextEncoder,
+};
+
+use crate::actions::ActionExecError;
+use crate::api::ApiSnapshot;
+use crate::datapoint_source::{DataPoint, DataPointSource, DataPointSourceError};
+use crate::pool_commands::{PoolCommand, PoolCommandError};
+use crate::wallet::WalletDataSource;
+
+lazy_static! {
+    static ref CURRENT_HEIGHT: IntGauge = register_int_gauge!(
+        "oracle_core_current_height",
+        "Current height of the blockchain"
+    )
+    .unwrap();
+    static ref POOL_EPOCH_COUNTER: IntGauge = register_int_gauge!(
+        "oracle_core_pool_epoch_counter",
+        "Epoch counter of the pool box"
+    )
+    .unwrap();
+    static ref POOL_RATE: IntGauge =
+        register_int_gauge!("oracle_core_pool_rate", "Rate in the pool box").unwrap();
+    static ref MY_RATE: IntGaugeVec = register_int_gauge_vec!(
+        "oracle_core_my_rate",
+        "Rate in the last datapoint posted by this oracle",
+        &["address"]
+    )
+    .unwrap();
+    static ref MY_RATE_AGE_BLOCKS: IntGaugeVec = register_int_gauge_vec!(
+        "oracle_core_my_rate_age_blocks",
+        "Number of blocks since this oracle's box was created",
+        &["address"]
+    )
+    .unwrap();
+    static ref EPOCH_DATAPOINTS: IntGauge = register_int_gauge!(
+        "oracle_core_epoch_datapoints",
+        "Number of datapoints posted in the current epoch"
+    )
+    .unwrap();
+    static ref WALLET_BALANCE: IntGaugeVec = register_int_gauge_vec!(
+        "oracle_core_wallet_balance_nanoerg",
+        "Total value of the unspent boxes in the wallet",
+        &["address"]
+    )
+    .unwrap();
+    static ref REWARD_TOKENS: IntGaugeVec = register_int_gauge_vec!(
+        "oracle_core_reward_tokens",
+        "Number of reward tokens in this oracle's box",
+        &["address"]
+    )
+    .unwrap();
+    static ref ACTIONS_SUBMITTED: IntCounterVec = register_int_counter_vec!(
+        "oracle_core_actions_submitted_total",
+        "Number of transactions submitted to the node",
+        &["action"]
+    )
+    .unwrap();
+    static ref ACTIONS_FAILED: IntCounterVec = register_int_counter_vec!(
+        "oracle_core_actions_failed_total",
+        "Number of actions that failed to build or submit",
+        &["action", "error"]
+    )
+    .unwrap();
+    static ref DATAPOINT_FETCH_SECONDS: Histogram = register_histogram!(
+        "oracle_core_datapoint_fetch_seconds",
+        "Time to fetch a datapoint from the datapoint source"
+    )
+    .unwrap();
+    static ref DATAPOINT_FETCH_ERRORS: IntCounter = register_int_counter!(
+        "oracle_core_datapoint_fetch_errors_total",
+        "Number of failed datapoint fetches"
+    )
+    .unwrap();
+    static ref DATAPOINT_SOURCE_UP: IntGauge = register_int_gauge!(
+        "oracle_core_datapoint_source_up",
+        "Whether the last datapoint fetch succeeded"
+    )
+    .unwrap();
+    static ref LAST_ACTION_TIMESTAMP: IntGauge = register_int_gauge!(
+        "oracle_core_last_action_timestamp_seconds",
+        "Unix time of the last transaction submitted by this oracle"
+    )
+    .unwrap();
+}
+
+/// All metrics in the Prometheus text format
+pub fn encode() -> String {
+    let mut buffer = Vec::new();
+    if let Err(e) = TextEncoder::new().encode(&prometheus::gather(), &mut buffer) {
+        log::error!("Failed to encode metrics: {}", e);
+    }
+    String::from_utf8(buffer).unwrap_or_default()
+}
+
+pub fn update_from_snapshot(snapshot: &ApiSnapshot) {
+    if let Some(height) = snapshot.height {
+        CURRENT_HEIGHT.set(height as i64);
+    }
+    if let Some(pool) = &snapshot.pool {
+        POOL_EPOCH_COUNTER.set(pool.pool_box.epoch_counter as i64);
+        POOL_RATE.set(pool.pool_box.rate);
+    }
+    if let Some(epoch) = &snapshot.epoch {
+        EPOCH_DATAPOINTS.set(epoch.oracle_boxes.len() as i64);
+    }
+    // Labeled with the address of each oracle identity
+    for oracle in snapshot.oracles.iter().flatten() {
+        let oracle_box = match &oracle.oracle_box {
+            Some(oracle_box) => oracle_box,
+            None => continue,
+        };
+        let address = [oracle.oracle_address.as_str()];
+        if let Some(rate) = oracle_box.rate {
+            MY_RATE.with_label_values(&address).set(rate as i64);
+        }
+        if let Some(height) = snapshot.height {
+            MY_RATE_AGE_BLOCKS
+                .with_label_values(&address)
+                .set(height as i64 - oracle_box.creation_height as i64);
+        }
+        REWARD_TOKENS
+            .with_label_values(&address)
+            .set(oracle_box.reward_tokens as i64);
+    }
+}
+
+/// Wallet balance labeled by the address of the boxes, since the oracle identities share the wallet
+pub fn update_wallet_balance(wallet: &dyn WalletDataSource, network_prefix: NetworkPrefix) {
+    let boxes = match wallet.get_unspent_wallet_boxes() {
+        Ok(boxes) => boxes,
+        Err(e) => {
+            log::debug!("Failed to get wallet balance for metrics: {}", e);
+            return;
+        }
+    };
+    let mut balances: BTreeMap<String, i64> = BTreeMap::new();
+    for b in boxes {
+        let address = Address::recreate_from_ergo_tree(&b.ergo_tree)
+            .map(|address| NetworkAddress::new(network_prefix, &address).to_base58())
+            .unwrap_or_else(|_| "unknown".to_string());
+        *balances.entry(address).or_default() += *b.value.as_u64() as i64;
+    }
+    // Drops the addresses which have no boxes left
+    WALLET_BALANCE.reset();
+    for (address, balance) in balances {
+        WALLET_BALANCE.with_label_values(&[&address]).set(balance);
+    }
+}
+
+pub fn pool_command_name(cmd: &PoolCommand) -> &'static str {
+    match cmd {
+        PoolCommand::Refresh => "refresh",
+        PoolCommand::PublishFirstDataPoint | PoolCommand::PublishSubsequentDataPoint { .. } => {
+            "publish_datapoint"
+        }
+    }
+}
+
+pub fn record_build_failure(action: &str, error: &PoolCommandError) {
+    let error = match error {
+        PoolCommandError::StageError(_) => "StageError",
+        PoolCommandError::Unexpected(_) => "Unexpected",
+        PoolCommandError::RefreshActionError(_) => "RefreshActionError",
+        PoolCommandError::PublishDatapointActionError(_) => "PublishDatapointActionError",
+        PoolCommandError::Digest(_) => "Digest",
+        PoolCommandError::AddressEncoder(_) => "AddressEncoder",
+        PoolCommandError::WrongOracleAddressType => "WrongOracleAddressType",
+    };
+    ACTIONS_FAILED.with_label_values(&[action, error]).inc();
+}
+
+pub fn record_execution<T>(action: &str, result: &Result<T, ActionExecError>) {
+    match result {
+        Ok(_) => {
+            ACTIONS_SUBMITTED.with_label_values(&[action]).inc();
+            LAST_ACTION_TIMESTAMP.set(unix_time(SystemTime::now()) as i64);
+        }
+        Err(ActionExecError::NodeError(_)) => ACTIONS_FAILED
+            .with_label_values(&[action, "NodeError"])
+            .inc(),
+        Err(ActionExecError::InputsSpentInMempool(_)) => ACTIONS_FAILED
+            .with_label_values(&[action, "InputsSpentInMempool"])
+            .inc(),
+    }
+}
+
+/// Time of the last transaction submitted since the start
+pub fn last_action_time() -> Option<SystemTime> {
+    match LAST_ACTION_TIMESTAMP.get() {
+        0 => None,
+        secs => Some(UNIX_EPOCH + Duration::from_secs(secs as u64)),
+    }
+}
+
+/// Whether the last datapoint fetch succeeded, `None` if there was none yet
+pub fn datapoint_source_up() -> Option<bool> {
+    if DATAPOINT_FETCH_SECONDS.get_sample_count() == 0 {
+        None
+    } else {
+        Some(DATAPOINT_SOURCE_UP.get() == 1)
+    }
+}
+
+fn unix_time(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+/// Records the fetch time and the errors of the wrapped source
+#[derive(Debug)]
+pub struct MeteredDataPointSource {
+    source: Box<dyn DataPointSource + Send + Sync>,
+}
+
+impl MeteredDataPointSource {
+    pub fn new(source: Box<dyn DataPointSource + Send + Sync>) -> Self {
+        MeteredDataPointSource { source }
+    }
+}
+
+impl DataPointSource for MeteredDataPointSource {
+    fn get_datapoint(&self) -> Result<i64, DataPointSourceError> {
+        self.get_datapoint_with_metadata()
+            .map(|datapoint| datapoint.value)
+    }
+
+    fn get_datapoint_with_metadata(&self) -> Result<DataPoint, DataPointSourceError> {
+        let timer = DATAPOINT_FETCH_SECONDS.start_timer();
+        let result = self.source.get_datapoint_with_metadata();
+        if result.is_err() {
+            DATAPOINT_FETCH_ERRORS.inc();
+        }
+        DATAPOINT_SOURCE_UP.set(result.is_ok() as i64);
+        timer.observe_duration();
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug)]
+    struct FailingSource;
+
+    impl DataPointSource for FailingSource {
+        fn get_datapoint(&self) -> Result<i64, DataPointSourceError> {
+            Err(DataPointSourceError::JsonMissingField)
+        }
+    }
+
+    #[test]
+    fn test_metered_data_point_source() {
+        let errors_before = DATAPOINT_FETCH_ERRORS.get();
+        let fetches_before = DATAPOINT_FETCH_SECONDS.get_sample_count();
+        let source = MeteredDataPointSource::new(Box::new(FailingSource));
+        assert!(source.get_datapoint().is_err());
+        assert_eq!(DATAPOINT_FETCH_ERRORS.get(), errors_before + 1);
+        assert_eq!(
+            DATAPOINT_FETCH_SECONDS.get_sample_count(),
+            fetches_before + 1
+        );
+        assert_eq!(datapoint_source_up(), Some(false));
+        assert!(encode().contains("oracle_core_datapoint_fetch_errors_total"));
+    }
+}
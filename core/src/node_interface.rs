@@ -1,9 +1,17 @@
+use std::collections::BTreeSet;
+use std::ops::RangeInclusive;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+
 use crate::{
-    oracle_config::{get_node_api_key, get_node_ip, get_node_port},
+    local_wallet::local_wallet,
+    oracle_config::{NodeEndpoint, ORACLE_CONFIG},
+    scans::scan_ids_path,
     wallet::{WalletDataError, WalletDataSource},
 };
 use ergo_lib::{
     chain::transaction::{unsigned::UnsignedTransaction, Transaction, TxIoVec},
+    ergo_chain_types::Header,
     ergotree_ir::chain::ergo_box::ErgoBox,
 };
 use ergo_node_interface::{
@@ -12,6 +20,7 @@ use ergo_node_interface::{
 };
 use log::debug;
 use log::error;
+use once_cell::sync::Lazy;
 
 pub type Result<T> = std::result::Result<T, NodeError>;
 pub type ScanID = String;
@@ -19,6 +28,26 @@ pub type TxId = String;
 pub type P2PKAddressString = String;
 pub type P2SAddressString = String;
 
+/// Maximum number of blocks a node can be behind the best known header to be synced
+pub const MAX_NODE_SYNC_LAG: u32 = 2;
+/// Maximum difference between the heights of the synced nodes for them to be consistent
+pub const MAX_NODE_HEIGHT_SPREAD: u32 = 2;
+
+/// Index in `OracleConfig::node_endpoints` of the node the requests are sent to
+static ACTIVE_NODE: AtomicUsize = AtomicUsize::new(0);
+
+/// Nodes whose scans were registered by `select_node`, not used until their rescan is done
+static SCANS_CATCHING_UP: Lazy<Mutex<BTreeSet<usize>>> = Lazy::new(Default::default);
+
+/// Registers the scans in the nodes which don't have them yet, see `select_node`
+pub trait NodeScansRegistration {
+    /// Registers the scans in the node at `index` in `OracleConfig::node_endpoints` and triggers
+    /// its rescan, without waiting for it
+    fn register(&self, index: usize) -> std::result::Result<(), String>;
+    /// Whether the rescan of the node at `index` is done
+    fn caught_up(&self, index: usize) -> std::result::Result<bool, String>;
+}
+
 pub trait SubmitTransaction {
     fn submit_transaction(&self, tx: &Transaction) -> Result<String>;
 }
@@ -64,13 +93,177 @@ impl WalletDataSource for NodeInterface {
 }
 
 pub fn new_node_interface() -> NodeInterface {
-    NodeInterface::new(&get_node_api_key(), &get_node_ip(), &get_node_port())
+    new_node_interface_for(active_node())
+}
+
+/// Interface to the node at `index` in `OracleConfig::node_endpoints`, whichever node is in use
+pub fn new_node_interface_for(index: usize) -> NodeInterface {
+    endpoint_node_interface(&node_endpoint(index))
+}
+
+fn endpoint_node_interface(endpoint: &NodeEndpoint) -> NodeInterface {
+    NodeInterface::new(
+        &endpoint.node_api_key,
+        &endpoint.node_ip,
+        &endpoint.node_port.to_string(),
+    )
+}
+
+pub fn active_node() -> usize {
+    ACTIVE_NODE.load(Ordering::Relaxed)
+}
+
+pub fn set_active_node(index: usize) {
+    ACTIVE_NODE.store(index, Ordering::Relaxed);
+}
+
+/// The node the requests are sent to
+pub fn active_node_endpoint() -> NodeEndpoint {
+    node_endpoint(active_node())
+}
+
+/// The node at `index` in `OracleConfig::node_endpoints` (the last one if out of range)
+pub fn node_endpoint(index: usize) -> NodeEndpoint {
+    let mut endpoints = ORACLE_CONFIG.node_endpoints();
+    let index = index.min(endpoints.len() - 1);
+    endpoints.swap_remove(index)
+}
+
+/// Checks all the nodes and switches to the first one (in the config order) which is reachable,
+/// synced and has the scans registered (unless none has, before the first registration). Fails if
+/// there is none, or if the synced nodes disagree on the height, in which case no action should be
+/// taken. With `registration`, the scans are registered in the reachable nodes which don't have
+/// them (e.g. a fallback node which was down on start), see `check_node_scans`.
+pub fn select_node(registration: Option<&dyn NodeScansRegistration>) -> Result<()> {
+    let endpoints = ORACLE_CONFIG.node_endpoints();
+    let mut scans_registered: Vec<bool> = endpoints
+        .iter()
+        .enumerate()
+        .map(|(index, endpoint)| scan_ids_path(index, endpoint).exists())
+        .collect();
+    let mut statuses: Vec<std::result::Result<NodeSyncStatus, String>> = endpoints
+        .iter()
+        .map(|endpoint| sync_status(&endpoint_node_interface(endpoint)).map_err(|e| e.to_string()))
+        .collect();
+    check_node_scans(
+        &endpoints,
+        &mut statuses,
+        &mut scans_registered,
+        &mut SCANS_CATCHING_UP.lock().unwrap(),
+        registration,
+    );
+    for (endpoint, status) in endpoints.iter().zip(&statuses) {
+        match status {
+            Ok(status) if status.lag() > MAX_NODE_SYNC_LAG => log::warn!(
+                "Node {} is {} blocks behind its best header",
+                endpoint,
+                status.lag()
+            ),
+            Ok(_) => (),
+            Err(e) => log::warn!("Node {} is unavailable: {}", endpoint, e),
+        }
+    }
+    let index = choose_node(&statuses, MAX_NODE_SYNC_LAG, MAX_NODE_HEIGHT_SPREAD)
+        .map_err(NodeError::Other)?;
+    let active = active_node();
+    if index != active {
+        log::warn!(
+            "Switching from node {} to node {}",
+            endpoints[active.min(endpoints.len() - 1)],
+            endpoints[index]
+        );
+        set_active_node(index);
+    }
+    Ok(())
 }
 
-/// Registers a scan with the node and either returns the `scan_id` or an error
-pub fn register_scan(scan_json: &serde_json::Value) -> Result<ScanID> {
+/// Marks the nodes which can't be used because of their scans as unavailable in `statuses`. Once
+/// the scans are registered in a node (`register_and_save_scans` registers them in all the
+/// reachable nodes on start), they are registered with `registration` in the other nodes as soon
+/// as they are reachable. These nodes are `catching_up` and are not used until their rescan is
+/// done.
+fn check_node_scans(
+    endpoints: &[NodeEndpoint],
+    statuses: &mut [std::result::Result<NodeSyncStatus, String>],
+    scans_registered: &mut [bool],
+    catching_up: &mut BTreeSet<usize>,
+    registration: Option<&dyn NodeScansRegistration>,
+) {
+    if !scans_registered.contains(&true) {
+        return;
+    }
+    for (index, endpoint) in endpoints.iter().enumerate() {
+        if statuses[index].is_err() {
+            continue;
+        }
+        if !scans_registered[index] {
+            if let Some(registration) = registration {
+                log::info!("Registering the scans in node {}", endpoint);
+                match registration.register(index) {
+                    Ok(()) => {
+                        scans_registered[index] = true;
+                        catching_up.insert(index);
+                    }
+                    Err(e) => {
+                        log::error!("Failed to register the scans in node {}: {}", endpoint, e)
+                    }
+                }
+            }
+        }
+        if catching_up.contains(&index) {
+            match registration.map(|registration| registration.caught_up(index)) {
+                Some(Ok(true)) => {
+                    log::info!("The scans of node {} caught up", endpoint);
+                    catching_up.remove(&index);
+                }
+                Some(Ok(false)) | None => (),
+                Some(Err(e)) => {
+                    log::warn!("Failed to check the rescan of node {}: {}", endpoint, e)
+                }
+            }
+        }
+        if !scans_registered[index] {
+            statuses[index] = Err("scans not registered".to_string());
+        } else if catching_up.contains(&index) {
+            statuses[index] = Err("scans are catching up".to_string());
+        }
+    }
+}
+
+/// Index of the first node synced within `max_sync_lag` blocks, provided that all the synced nodes
+/// report heights at most `max_height_spread` blocks apart
+fn choose_node(
+    statuses: &[std::result::Result<NodeSyncStatus, String>],
+    max_sync_lag: u32,
+    max_height_spread: u32,
+) -> std::result::Result<usize, String> {
+    let synced: Vec<(usize, u32)> = statuses
+        .iter()
+        .enumerate()
+        .filter_map(|(index, status)| match status {
+            Ok(status) if status.lag() <= max_sync_lag => Some((index, status.full_height)),
+            Ok(_) | Err(_) => None,
+        })
+        .collect();
+    let first = synced
+        .first()
+        .map(|(index, _)| *index)
+        .ok_or_else(|| "no node is reachable and synced".to_string())?;
+    let lowest = synced.iter().map(|(_, height)| *height).min().unwrap_or(0);
+    let highest = synced.iter().map(|(_, height)| *height).max().unwrap_or(0);
+    if highest - lowest > max_height_spread {
+        return Err(format!(
+            "the synced nodes disagree on the height ({} to {})",
+            lowest, highest
+        ));
+    }
+    Ok(first)
+}
+
+/// Registers a scan with the node at `node_index` and either returns the `scan_id` or an error
+pub fn register_scan(node_index: usize, scan_json: &serde_json::Value) -> Result<ScanID> {
     let scan_json_t = json::parse(&serde_json::to_string(scan_json).unwrap()).unwrap();
-    new_node_interface().register_scan(&scan_json_t)
+    new_node_interface_for(node_index).register_scan(&scan_json_t)
 }
 
 /// Acquires unspent boxes from the node wallet
@@ -96,17 +289,168 @@ pub fn get_serialized_highest_value_unspent_box() -> Result<String> {
 
 /// Using the `scan_id` of a registered scan, acquires unspent boxes which have been found by said scan
 pub fn get_scan_boxes(scan_id: &String) -> Result<Vec<ErgoBox>> {
-    new_node_interface().scan_boxes(scan_id)
+    get_node_scan_boxes(active_node(), scan_id)
 }
 
-pub fn rescan_from_height(height: u32) -> Result<()> {
-    new_node_interface().send_post_req(
+/// Same as `get_scan_boxes` with the node at `node_index`, `scan_id` being its id in this node
+pub fn get_node_scan_boxes(node_index: usize, scan_id: &String) -> Result<Vec<ErgoBox>> {
+    new_node_interface_for(node_index).scan_boxes(scan_id)
+}
+
+pub fn rescan_from_height(node_index: usize, height: u32) -> Result<()> {
+    new_node_interface_for(node_index).send_post_req(
         "/wallet/rescan",
         format!("{{ \"fromHeight\": {} }} ", height),
     )?;
     Ok(())
 }
 
+/// Heights of the node's best header and best fully validated block
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct NodeSyncStatus {
+    pub headers_height: u32,
+    pub full_height: u32,
+}
+
+impl NodeSyncStatus {
+    /// Number of blocks the node is behind the best known header
+    pub fn lag(&self) -> u32 {
+        self.headers_height.saturating_sub(self.full_height)
+    }
+}
+
+pub fn node_sync_status() -> Result<NodeSyncStatus> {
+    sync_status(&new_node_interface())
+}
+
+pub fn node_sync_status_for(index: usize) -> Result<NodeSyncStatus> {
+    sync_status(&new_node_interface_for(index))
+}
+
+fn sync_status(node: &NodeInterface) -> Result<NodeSyncStatus> {
+    let info = node.parse_response_to_json(node.send_get_req("/info"))?;
+    let height = |field: &str| {
+        info[field].as_u32().ok_or_else(|| {
+            NodeError::FailedParsingNodeResponse(format!("no {} in /info response", field))
+        })
+    };
+    Ok(NodeSyncStatus {
+        headers_height: height("headersHeight")?,
+        full_height: height("fullHeight")?,
+    })
+}
+
+/// Ids of all the scans registered in the node
+pub fn registered_scan_ids() -> Result<Vec<ScanID>> {
+    let node = new_node_interface();
+    let scans = node.parse_response_to_json(node.send_get_req("/scan/listAll"))?;
+    Ok(scans
+        .members()
+        .map(|scan| scan["scanId"].to_string())
+        .collect())
+}
+
+/// Where the node sees a transaction
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TxStatus {
+    InMempool,
+    /// Included in a block at the given height
+    Confirmed(u32),
+    /// Neither in the mempool nor in the searched blocks
+    NotFound,
+}
+
+/// Status of a transaction, looked up in the mempool and then in the best chain blocks at
+/// `heights`. Only uses the mempool and blocks API, so it doesn't depend on the node wallet (which
+/// might be disabled, lagging behind or, after switching nodes, have never seen the tx).
+pub fn tx_status(tx_id: &str, heights: RangeInclusive<u32>) -> Result<TxStatus> {
+    let node = new_node_interface();
+    let tx_id = tx_id.replace('"', "");
+    let mempool_res = node.send_get_req(&format!(
+        "/transactions/unconfirmed/byTransactionId/{}",
+        tx_id
+    ))?;
+    if mempool_res.status().is_success() {
+        return Ok(TxStatus::InMempool);
+    }
+    find_tx_in_blocks(&tx_id, heights, |height| block_tx_ids(&node, height))
+}
+
+/// Looks for the tx in the blocks at `heights`, given the ids of the txs of the block at a height
+pub fn find_tx_in_blocks<E>(
+    tx_id: &str,
+    heights: RangeInclusive<u32>,
+    block_tx_ids: impl Fn(u32) -> std::result::Result<Vec<TxId>, E>,
+) -> std::result::Result<TxStatus, E> {
+    for height in heights {
+        if block_tx_ids(height)?.iter().any(|id| id == tx_id) {
+            return Ok(TxStatus::Confirmed(height));
+        }
+    }
+    Ok(TxStatus::NotFound)
+}
+
+/// Ids of the txs of the best chain block at `height`, none if the node doesn't have it yet
+fn block_tx_ids(node: &NodeInterface, height: u32) -> Result<Vec<TxId>> {
+    let header_ids =
+        node.parse_response_to_json(node.send_get_req(&format!("/blocks/at/{}", height)))?;
+    // The header of the best chain comes first
+    let header_id = match header_ids[0].as_str() {
+        Some(header_id) => header_id.to_string(),
+        None => return Ok(Vec::new()),
+    };
+    let block_txs = node.parse_response_to_json(
+        node.send_get_req(&format!("/blocks/{}/transactions", header_id)),
+    )?;
+    Ok(block_txs["transactions"]
+        .members()
+        .filter_map(|tx| tx["id"].as_str().map(str::to_string))
+        .collect())
+}
+
+/// Unspent box by id (in the node JSON format), including the boxes created by the mempool txs
+pub fn unspent_box_json(box_id: &str) -> Result<serde_json::Value> {
+    let node = new_node_interface();
+    let res = node.send_get_req(&format!("/utxo/withPool/byId/{}", box_id))?;
+    if !res.status().is_success() {
+        return Err(NodeError::BadRequest(format!(
+            "box {} is not in the UTXO set",
+            box_id
+        )));
+    }
+    let json = node.parse_response_to_json(Ok(res))?;
+    serde_json::from_str(&json.dump())
+        .map_err(|e| NodeError::FailedParsingNodeResponse(e.to_string()))
+}
+
+/// The input and data input boxes of a tx spending boxes of the UTXO set (or the mempool)
+pub fn tx_input_boxes(tx: &UnsignedTransaction) -> Result<(Vec<ErgoBox>, Vec<ErgoBox>)> {
+    let tx_json = serde_json::to_value(tx).map_err(|e| NodeError::Other(e.to_string()))?;
+    let fetch_boxes = |field: &str| -> Result<Vec<ErgoBox>> {
+        tx_json[field]
+            .as_array()
+            .cloned()
+            .unwrap_or_default()
+            .iter()
+            .map(|input| {
+                let box_id = input["boxId"].as_str().unwrap_or_default();
+                serde_json::from_value(unspent_box_json(box_id)?)
+                    .map_err(|e| NodeError::FailedParsingNodeResponse(e.to_string()))
+            })
+            .collect()
+    };
+    Ok((fetch_boxes("inputs")?, fetch_boxes("dataInputs")?))
+}
+
+/// The last `count` block headers, oldest first
+pub fn last_block_headers(count: u32) -> Result<Vec<Header>> {
+    let node = new_node_interface();
+    let json =
+        node.parse_response_to_json(node.send_get_req(&format!("/blocks/lastHeaders/{}", count)))?;
+    serde_json::from_str(&json.dump())
+        .map_err(|e| NodeError::FailedParsingNodeResponse(e.to_string()))
+}
+
 /// Get the current block height of the chain
 pub fn current_block_height() -> Result<BlockHeight> {
     new_node_interface().current_block_height()
@@ -133,7 +477,12 @@ pub fn sign_and_submit_transaction(unsigned_tx: &UnsignedTransaction) -> Result<
         "Signing transaction: {}",
         serde_json::to_string_pretty(&unsigned_tx).unwrap()
     );
-    let signed_tx = node.sign_transaction(unsigned_tx, None, None)?;
+    let signed_tx = match local_wallet() {
+        Some(local_wallet) => local_wallet
+            .sign_unsigned_tx(unsigned_tx)
+            .map_err(|e| NodeError::Other(e.to_string()))?,
+        None => node.sign_transaction(unsigned_tx, None, None)?,
+    };
     log::trace!(
         "Submitting signed transaction: {}",
         serde_json::to_string_pretty(&signed_tx).unwrap()
@@ -150,3 +499,112 @@ pub fn assert_wallet_unlocked(node: &NodeInterface) {
         debug!("Wallet unlocked");
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn synced(height: u32) -> std::result::Result<NodeSyncStatus, String> {
+        Ok(NodeSyncStatus {
+            headers_height: height,
+            full_height: height,
+        })
+    }
+
+    #[derive(Default)]
+    struct RegistrationMock {
+        registered: std::cell::RefCell<Vec<usize>>,
+        caught_up: std::cell::Cell<bool>,
+    }
+
+    impl NodeScansRegistration for RegistrationMock {
+        fn register(&self, index: usize) -> std::result::Result<(), String> {
+            self.registered.borrow_mut().push(index);
+            Ok(())
+        }
+
+        fn caught_up(&self, _index: usize) -> std::result::Result<bool, String> {
+            Ok(self.caught_up.get())
+        }
+    }
+
+    #[test]
+    fn test_check_node_scans() {
+        let endpoints: Vec<NodeEndpoint> = (0..2)
+            .map(|index| NodeEndpoint {
+                node_ip: format!("10.0.0.{}", index + 1),
+                node_port: 9053,
+                node_api_key: "hello".to_string(),
+            })
+            .collect();
+        let unreachable = Err("connection refused".to_string());
+        let registration = RegistrationMock::default();
+        // The fallback node was down on start, its scans are not registered
+        let mut scans_registered = vec![true, false];
+        let mut catching_up = BTreeSet::new();
+        let mut statuses = vec![synced(100), unreachable.clone()];
+        check_node_scans(
+            &endpoints,
+            &mut statuses,
+            &mut scans_registered,
+            &mut catching_up,
+            Some(&registration),
+        );
+        assert!(registration.registered.borrow().is_empty());
+        assert_eq!(choose_node(&statuses, 2, 2), Ok(0));
+
+        // Registered once it's reachable, not used while its rescan is running
+        let mut statuses = vec![unreachable.clone(), synced(101)];
+        check_node_scans(
+            &endpoints,
+            &mut statuses,
+            &mut scans_registered,
+            &mut catching_up,
+            Some(&registration),
+        );
+        assert_eq!(*registration.registered.borrow(), vec![1]);
+        assert_eq!(scans_registered, vec![true, true]);
+        assert!(choose_node(&statuses, 2, 2).is_err());
+
+        registration.caught_up.set(true);
+        let mut statuses = vec![unreachable, synced(102)];
+        check_node_scans(
+            &endpoints,
+            &mut statuses,
+            &mut scans_registered,
+            &mut catching_up,
+            Some(&registration),
+        );
+        assert!(catching_up.is_empty());
+        assert_eq!(choose_node(&statuses, 2, 2), Ok(1));
+        assert_eq!(*registration.registered.borrow(), vec![1]);
+
+        // Without registration (on start) the nodes without scans are only skipped
+        let mut scans_registered = vec![true, false];
+        let mut statuses = vec![synced(100), synced(100)];
+        check_node_scans(
+            &endpoints,
+            &mut statuses,
+            &mut scans_registered,
+            &mut catching_up,
+            None,
+        );
+        assert!(statuses[1].is_err());
+    }
+
+    #[test]
+    fn test_choose_node() {
+        let lagging = Ok(NodeSyncStatus {
+            headers_height: 110,
+            full_height: 100,
+        });
+        let unreachable = Err("connection refused".to_string());
+        assert_eq!(choose_node(&[synced(100), synced(101)], 2, 2), Ok(0));
+        assert_eq!(
+            choose_node(&[unreachable.clone(), lagging.clone(), synced(110)], 2, 2),
+            Ok(2)
+        );
+        assert!(choose_node(&[unreachable, lagging], 2, 2).is_err());
+        assert!(choose_node(&[synced(100), synced(110)], 2, 2).is_err());
+    }
+}
@@ -0,0 +1,213 @@
+//! Offline signing: instead of being signed by the node wallet, a tx is exported as a bundle with
+//! everything needed to sign it (the input boxes and the last block headers), signed on another
+//! (air-gapped) machine with the `sign-offline` command and submitted with the `submit` command.
+//! The wallet inputs of the exported txs are the boxes of the oracle address, found with a node
+//! scan, so the node wallet isn't used.
+
+use std::path::{Path, PathBuf};
+
+use derive_more::From;
+use ergo_lib::chain::ergo_state_context::ErgoStateContext;
+use ergo_lib::chain::transaction::unsigned::UnsignedTransaction;
+use ergo_lib::chain::transaction::Transaction;
+use ergo_lib::ergo_chain_types::Header;
+use ergo_lib::ergotree_ir::chain::address::NetworkPrefix;
+use ergo_lib::ergotree_ir::chain::ergo_box::ErgoBox;
+use ergo_lib::wallet::signing::{TransactionContext, TxSigningError};
+use ergo_lib::wallet::{Wallet, WalletError};
+use ergo_node_interface::node_interface::NodeError;
+use once_cell::sync::OnceCell;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::dry_run::{unsigned_tx_summary, DryRunError};
+use crate::local_wallet::{next_block_state_context, LocalWalletError, SIGNING_CONTEXT_HEADERS};
+use crate::node_interface::{last_block_headers, tx_input_boxes};
+use crate::oracle_config::ORACLE_CONFIG;
+
+/// Set in `main` when the `--export-unsigned` option is passed
+pub static EXPORT_PATH: OnceCell<PathBuf> = OnceCell::new();
+/// Set in `main` when the `--change-address` option is passed
+pub static EXPORT_CHANGE_ADDRESS: OnceCell<String> = OnceCell::new();
+
+#[derive(Debug, Error, From)]
+pub enum OfflineSigningError {
+    #[error("offline signing: io error {0}")]
+    Io(std::io::Error),
+    #[error("offline signing: json error {0}")]
+    Json(serde_json::Error),
+    #[error("offline signing: node error {0}")]
+    Node(NodeError),
+    #[error("offline signing: {0}")]
+    DryRun(DryRunError),
+    #[error("offline signing: tx signing error {0}")]
+    TxSigning(TxSigningError),
+    #[error("offline signing: wallet error {0}")]
+    Wallet(WalletError),
+    #[error("offline signing: {0}")]
+    LocalWallet(LocalWalletError),
+    #[error("offline signing: unknown network {0}")]
+    #[from(ignore)]
+    UnknownNetwork(String),
+}
+
+/// Returns the bundle path if the `--export-unsigned` option is passed
+pub fn export_path() -> Option<&'static PathBuf> {
+    EXPORT_PATH.get()
+}
+
+/// Change address of the exported txs: `--change-address` if passed, the oracle address otherwise
+pub fn export_change_address() -> String {
+    EXPORT_CHANGE_ADDRESS
+        .get()
+        .cloned()
+        .unwrap_or_else(|| ORACLE_CONFIG.oracle_address.to_base58())
+}
+
+/// An unsigned tx with the context needed to sign it without a node
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UnsignedTxBundle {
+    /// What the tx does, shown before signing
+    pub description: String,
+    /// "mainnet" or "testnet"
+    pub network: String,
+    pub tx: UnsignedTransaction,
+    pub inputs: Vec<ErgoBox>,
+    pub data_inputs: Vec<ErgoBox>,
+    /// The last block headers, newest first
+    pub headers: Vec<Header>,
+}
+
+impl UnsignedTxBundle {
+    pub fn network_prefix(&self) -> Result<NetworkPrefix, OfflineSigningError> {
+        match self.network.as_str() {
+            "mainnet" => Ok(NetworkPrefix::Mainnet),
+            "testnet" => Ok(NetworkPrefix::Testnet),
+            _ => Err(OfflineSigningError::UnknownNetwork(self.network.clone())),
+        }
+    }
+
+    pub fn summary(&self) -> Result<String, OfflineSigningError> {
+        Ok(unsigned_tx_summary(
+            &self.description,
+            &self.tx,
+            &self.inputs,
+            &self.data_inputs,
+            self.network_prefix()?,
+        )?)
+    }
+
+    /// Signing context of the next block
+    pub fn state_context(&self) -> Result<ErgoStateContext, OfflineSigningError> {
+        Ok(next_block_state_context(self.headers.clone())?)
+    }
+
+    pub fn sign(&self, wallet: &Wallet) -> Result<Transaction, OfflineSigningError> {
+        let tx_context = TransactionContext::new(
+            self.tx.clone(),
+            self.inputs.clone(),
+            self.data_inputs.clone(),
+        )?;
+        Ok(wallet.sign_transaction(tx_context, &self.state_context()?, None)?)
+    }
+}
+
+/// Writes the bundle of a tx spending boxes of the UTXO set (or the mempool), fetched from the
+/// node along with the last block headers
+pub fn export_unsigned_tx(
+    path: &Path,
+    description: &str,
+    tx: &UnsignedTransaction,
+    network_prefix: NetworkPrefix,
+) -> Result<(), OfflineSigningError> {
+    let (inputs, data_inputs) = tx_input_boxes(tx)?;
+    let mut headers = last_block_headers(SIGNING_CONTEXT_HEADERS as u32)?;
+    headers.reverse();
+    let bundle = UnsignedTxBundle {
+        description: description.to_string(),
+        network: match network_prefix {
+            NetworkPrefix::Mainnet => "mainnet",
+            NetworkPrefix::Testnet => "testnet",
+        }
+        .to_string(),
+        tx: tx.clone(),
+        inputs,
+        data_inputs,
+        headers,
+    };
+    println!("{}", bundle.summary()?);
+    std::fs::write(path, serde_json::to_string_pretty(&bundle)?)?;
+    println!(
+        "Unsigned tx exported to {}, sign it with the sign-offline command",
+        path.display()
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::local_wallet::load_secrets;
+    use crate::oracle_config::BASE_FEE;
+    use crate::pool_commands::test_utils::make_wallet_unspent_box;
+    use ergo_lib::chain::ergo_box::box_builder::ErgoBoxCandidateBuilder;
+    use ergo_lib::ergotree_interpreter::sigma_protocol::private_input::DlogProverInput;
+    use ergo_lib::ergotree_ir::chain::address::Address;
+    use ergo_lib::wallet::box_selector::{BoxSelector, SimpleBoxSelector};
+    use ergo_lib::wallet::tx_builder::TxBuilder;
+    use sigma_test_util::force_any_val;
+
+    #[test]
+    fn test_sign_bundle() {
+        let ctx = force_any_val::<ErgoStateContext>();
+        let headers = ctx.headers.to_vec();
+        let height = headers[0].height + 1;
+        let secret = force_any_val::<DlogProverInput>();
+        let secret_file = std::env::temp_dir().join("oracle_core_test_offline_signing_secret");
+        std::fs::write(&secret_file, base16::encode_lower(&secret.to_bytes())).unwrap();
+        let wallet = Wallet::from_secrets(load_secrets(&secret_file, "").unwrap());
+        std::fs::remove_file(&secret_file).unwrap();
+
+        let address = Address::P2Pk(secret.public_image());
+        let wallet_box = make_wallet_unspent_box(
+            secret.public_image(),
+            BASE_FEE.checked_mul_u32(10).unwrap(),
+            None,
+        );
+        let output = ErgoBoxCandidateBuilder::new(*BASE_FEE, address.script().unwrap(), height)
+            .build()
+            .unwrap();
+        let box_selection = SimpleBoxSelector::new()
+            .select(
+                vec![wallet_box.clone()],
+                BASE_FEE.checked_mul_u32(2).unwrap(),
+                &[],
+            )
+            .unwrap();
+        let tx = TxBuilder::new(box_selection, vec![output], height, *BASE_FEE, address)
+            .build()
+            .unwrap();
+
+        let mut bundle = UnsignedTxBundle {
+            description: "Test".to_string(),
+            network: "mainnet".to_string(),
+            tx,
+            inputs: vec![wallet_box],
+            data_inputs: Vec::new(),
+            headers,
+        };
+        assert_eq!(bundle.state_context().unwrap().pre_header.height, height);
+        assert!(bundle.summary().unwrap().contains("Fee: "));
+        let signed_tx = bundle.sign(&wallet).unwrap();
+        assert_eq!(signed_tx.id(), bundle.tx.id());
+
+        bundle.headers.pop();
+        assert!(matches!(
+            bundle.state_context(),
+            Err(OfflineSigningError::LocalWallet(
+                LocalWalletError::WrongHeaderCount(9)
+            ))
+        ));
+    }
+}
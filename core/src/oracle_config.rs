@@ -1,4 +1,7 @@
 use std::convert::TryFrom;
+use std::net::{IpAddr, Ipv4Addr};
+use std::path::PathBuf;
+use std::time::Duration;
 
 use crate::{
     box_kind::{
@@ -10,7 +13,14 @@ use crate::{
         ballot::BallotContractError, oracle::OracleContractError, pool::PoolContractError,
         refresh::RefreshContractError, update::UpdateContractError,
     },
-    datapoint_source::{DataPointSource, ExternalScript, PredefinedDataPointSource},
+    datapoint_source::{
+        registry::{NamedDataPointSourceConfig, REPLAY_SOURCE_NAME},
+        AggregateDataPointSourceConfig, DataPointSource, DerivedDataPointSourceConfig,
+        ExternalScript, HttpJsonDataPointSource, PredefinedDataPointSource,
+        RecordingDataPointSource,
+    },
+    node_interface::active_node_endpoint,
+    pool_commands::publish_datapoint::DatapointGuards,
 };
 use anyhow::anyhow;
 use derive_more::From;
@@ -24,8 +34,11 @@ use log::LevelFilter;
 use once_cell::sync;
 use serde::{Deserialize, Serialize};
 use thiserror::Error;
+use utoipa::ToSchema;
 
 pub const DEFAULT_CONFIG_FILE_NAME: &str = "oracle_config.yaml";
+pub const DEFAULT_DATA_POINT_PREFETCH_INTERVAL_SECS: u64 = 60;
+pub const DEFAULT_ACTION_HISTORY_PATH: &str = "action_history.sqlite";
 
 #[derive(Debug, Clone, Deserialize, Serialize)]
 #[serde(
@@ -36,12 +49,44 @@ pub struct OracleConfig {
     pub node_ip: String,
     pub node_port: u16,
     pub node_api_key: String,
+    /// Nodes switched to when the node of `node_ip` is unreachable or out of sync, in order
+    pub fallback_nodes: Vec<NodeEndpoint>,
     pub base_fee: u64,
     pub log_level: Option<LevelFilter>,
     pub core_api_port: u16,
+    /// Bearer token (or `X-Api-Key` header value) required by the REST API write endpoints, which
+    /// are disabled when it's not set
+    pub core_api_auth_token: Option<String>,
+    /// Address the REST API listens on, all interfaces (`0.0.0.0`) if not set
+    pub core_api_bind_address: Option<IpAddr>,
+    /// Serve the REST API over HTTPS
+    pub core_api_tls: Option<CoreApiTlsConfig>,
+    /// Origins allowed by CORS, any origin if not set
+    pub core_api_allowed_origins: Option<Vec<String>>,
     pub oracle_address: NetworkAddress,
+    /// Addresses of the other oracle tokens operated by this process, each with its own local
+    /// scans and actions. The datapoint source and the wallet are shared.
+    pub additional_oracle_addresses: Vec<NetworkAddress>,
     pub data_point_source: Option<PredefinedDataPointSource>,
-    pub data_point_source_custom_script: Option<String>,
+    pub data_point_source_custom_script: Option<ExternalScript>,
+    pub data_point_source_http_json: Option<HttpJsonDataPointSource>,
+    pub data_point_source_aggregate: Option<AggregateDataPointSourceConfig>,
+    pub data_point_source_named: Option<NamedDataPointSourceConfig>,
+    pub data_point_source_derived: Option<DerivedDataPointSourceConfig>,
+    /// Append every fetched datapoint to this file
+    pub data_point_source_record_path: Option<PathBuf>,
+    pub data_point_guards: DatapointGuards,
+    /// Fetch datapoints in the background every this many seconds while running
+    pub data_point_prefetch_interval_secs: Option<u64>,
+    /// SQLite database of the actions built by the main loop, `action_history.sqlite` if not set
+    pub action_history_path: Option<PathBuf>,
+    /// Sign with a local wallet instead of the node wallet: an Ergo node keystore file (encrypted
+    /// with the wallet password) or a file with the mnemonic. The wallet inputs are the boxes of
+    /// `oracle_address`, found with a node scan.
+    pub local_wallet_secret_file: Option<PathBuf>,
+    /// File with the password of `local_wallet_secret_file` (the `ORACLE_WALLET_PASSWORD`
+    /// environment variable takes precedence). One of them is required by `run`.
+    pub local_wallet_password_file: Option<PathBuf>,
     pub oracle_box_wrapper_inputs: OracleBoxWrapperInputs,
     pub pool_box_wrapper_inputs: PoolBoxWrapperInputs,
     pub refresh_box_wrapper_inputs: RefreshBoxWrapperInputs,
@@ -51,46 +96,75 @@ pub struct OracleConfig {
     pub rescan_height: u32,
 }
 
+/// An Ergo node the oracle can send its requests to
 #[derive(Debug, Clone, Deserialize, Serialize, PartialEq, Eq)]
+pub struct NodeEndpoint {
+    pub node_ip: String,
+    pub node_port: u16,
+    pub node_api_key: String,
+}
+
+impl std::fmt::Display for NodeEndpoint {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}:{}", self.node_ip, self.node_port)
+    }
+}
+
+/// PEM encoded certificate (chain) and private key of the REST API
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq, Eq)]
+pub struct CoreApiTlsConfig {
+    pub cert_path: PathBuf,
+    pub key_path: PathBuf,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq, Eq, ToSchema)]
 pub struct CastBallotBoxVoteParameters {
+    #[schema(value_type = String)]
     pub pool_box_address_hash: Digest32,
+    #[schema(value_type = String)]
     pub reward_token_id: TokenId,
     pub reward_token_quantity: u64,
     pub update_box_creation_height: i32,
 }
 
 /// Holds the token ids of every important token used by the oracle pool.
-#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, ToSchema)]
 pub struct TokenIds {
     #[serde(
         serialize_with = "crate::serde::token_id_as_base64_string",
         deserialize_with = "crate::serde::token_id_from_base64"
     )]
+    #[schema(value_type = String)]
     pub pool_nft_token_id: TokenId,
     #[serde(
         serialize_with = "crate::serde::token_id_as_base64_string",
         deserialize_with = "crate::serde::token_id_from_base64"
     )]
+    #[schema(value_type = String)]
     pub refresh_nft_token_id: TokenId,
     #[serde(
         serialize_with = "crate::serde::token_id_as_base64_string",
         deserialize_with = "crate::serde::token_id_from_base64"
     )]
+    #[schema(value_type = String)]
     pub update_nft_token_id: TokenId,
     #[serde(
         serialize_with = "crate::serde::token_id_as_base64_string",
         deserialize_with = "crate::serde::token_id_from_base64"
     )]
+    #[schema(value_type = String)]
     pub oracle_token_id: TokenId,
     #[serde(
         serialize_with = "crate::serde::token_id_as_base64_string",
         deserialize_with = "crate::serde::token_id_from_base64"
     )]
+    #[schema(value_type = String)]
     pub reward_token_id: TokenId,
     #[serde(
         serialize_with = "crate::serde::token_id_as_base64_string",
         deserialize_with = "crate::serde::token_id_from_base64"
     )]
+    #[schema(value_type = String)]
     pub ballot_token_id: TokenId,
 }
 
@@ -134,12 +208,28 @@ impl OracleConfig {
             node_ip: bootstrap.node_ip,
             node_port: bootstrap.node_port,
             node_api_key: bootstrap.node_api_key,
+            fallback_nodes: Vec::new(),
             base_fee: bootstrap.base_fee,
             log_level: None,
             core_api_port: bootstrap.core_api_port,
+            core_api_auth_token: None,
+            core_api_bind_address: None,
+            core_api_tls: None,
+            core_api_allowed_origins: None,
             oracle_address: bootstrap.oracle_address,
+            additional_oracle_addresses: Vec::new(),
             data_point_source: bootstrap.data_point_source,
             data_point_source_custom_script: bootstrap.data_point_source_custom_script,
+            data_point_source_http_json: None,
+            data_point_source_aggregate: None,
+            data_point_source_named: None,
+            data_point_source_derived: None,
+            data_point_source_record_path: None,
+            data_point_guards: DatapointGuards::default(),
+            data_point_prefetch_interval_secs: Some(DEFAULT_DATA_POINT_PREFETCH_INTERVAL_SECS),
+            action_history_path: None,
+            local_wallet_secret_file: None,
+            local_wallet_password_file: None,
             oracle_box_wrapper_inputs,
             pool_box_wrapper_inputs,
             refresh_box_wrapper_inputs,
@@ -150,6 +240,24 @@ impl OracleConfig {
         })
     }
 
+    /// All the nodes, the one of `node_ip` first
+    pub fn node_endpoints(&self) -> Vec<NodeEndpoint> {
+        let mut endpoints = vec![NodeEndpoint {
+            node_ip: self.node_ip.clone(),
+            node_port: self.node_port,
+            node_api_key: self.node_api_key.clone(),
+        }];
+        endpoints.extend(self.fallback_nodes.iter().cloned());
+        endpoints
+    }
+
+    /// Addresses of all the oracle identities, `oracle_address` first
+    pub fn oracle_addresses(&self) -> Vec<NetworkAddress> {
+        let mut addresses = vec![self.oracle_address.clone()];
+        addresses.extend(self.additional_oracle_addresses.iter().cloned());
+        addresses
+    }
+
     fn load() -> Result<Self, anyhow::Error> {
         let config_file_path = CONFIG_FILE_PATH
             .get()
@@ -161,21 +269,96 @@ impl OracleConfig {
         serde_yaml::from_str(config_str).map_err(|e| anyhow!(e))
     }
 
+    /// Interval of the datapoint prefetch while running, `None` when replaying a recording (see
+    /// `data_point_source_record_path`) so that the datapoints are replayed in the order and at
+    /// the times the main loop asks for them
+    pub fn data_point_prefetch_interval(&self) -> Option<Duration> {
+        let replaying = self
+            .data_point_source_named
+            .as_ref()
+            .map_or(false, |named| named.name == REPLAY_SOURCE_NAME);
+        if replaying {
+            return None;
+        }
+        self.data_point_prefetch_interval_secs
+            .map(Duration::from_secs)
+    }
+
+    /// The datapoint source set in the config. Exactly one of the `data_point_source*` options
+    /// (apart from `data_point_source_record_path`) must be set.
     pub fn data_point_source(
         &self,
     ) -> Result<Box<dyn DataPointSource + Send + Sync>, anyhow::Error> {
-        let data_point_source: Box<dyn DataPointSource + Send + Sync> = if let Some(
-            external_script_name,
-        ) =
-            self.data_point_source_custom_script.clone()
-        {
-            Box::new(ExternalScript::new(external_script_name.clone()))
-        } else {
-            match self.data_point_source {
-                Some(datasource) => Box::new(datasource),
-                _ => return Err(anyhow!("Config: data_point_source is invalid (must be one of 'NanoErgUsd', 'NanoErgXau' or 'NanoAdaUsd'")),
-            }
-        };
+        let set_sources: Vec<&str> = [
+            ("data_point_source", self.data_point_source.is_some()),
+            (
+                "data_point_source_custom_script",
+                self.data_point_source_custom_script.is_some(),
+            ),
+            (
+                "data_point_source_http_json",
+                self.data_point_source_http_json.is_some(),
+            ),
+            (
+                "data_point_source_named",
+                self.data_point_source_named.is_some(),
+            ),
+            (
+                "data_point_source_derived",
+                self.data_point_source_derived.is_some(),
+            ),
+            (
+                "data_point_source_aggregate",
+                self.data_point_source_aggregate.is_some(),
+            ),
+        ]
+        .iter()
+        .filter(|(_, is_set)| *is_set)
+        .map(|(name, _)| *name)
+        .collect();
+        if set_sources.len() > 1 {
+            return Err(anyhow!(
+                "Config: only one datapoint source can be set, found {}",
+                set_sources.join(", ")
+            ));
+        }
+        let data_point_source: Box<dyn DataPointSource + Send + Sync> =
+            if let Some(external_script) = self.data_point_source_custom_script.clone() {
+                Box::new(external_script)
+            } else if let Some(http_json) = &self.data_point_source_http_json {
+                Box::new(http_json.clone())
+            } else if let Some(named) = &self.data_point_source_named {
+                named.build()?
+            } else if let Some(derived) = &self.data_point_source_derived {
+                Box::new(derived.build()?)
+            } else if let Some(aggregate) = &self.data_point_source_aggregate {
+                let max_deviation_percent = self
+                    .refresh_box_wrapper_inputs
+                    .contract_inputs
+                    .contract_parameters()
+                    .max_deviation_percent() as u32;
+                Box::new(aggregate.build(max_deviation_percent)?)
+            } else if let Some(datasource) = self.data_point_source {
+                Box::new(datasource)
+            } else {
+                let predefined: Vec<String> = PredefinedDataPointSource::ALL
+                    .iter()
+                    .map(|source| format!("'{:?}'", source))
+                    .collect();
+                return Err(anyhow!(
+                    "Config: no datapoint source set (set data_point_source to one of {}, or one \
+                     of data_point_source_custom_script, data_point_source_http_json, \
+                     data_point_source_named, data_point_source_derived or \
+                     data_point_source_aggregate)",
+                    predefined.join(", ")
+                ));
+            };
+        if let Some(record_path) = &self.data_point_source_record_path {
+            return Ok(Box::new(RecordingDataPointSource::new(
+                data_point_source,
+                record_path,
+            )?));
+        }
         Ok(data_point_source)
     }
 }
@@ -210,22 +393,40 @@ pub fn get_core_api_port() -> String {
     ORACLE_CONFIG.core_api_port.to_string()
 }
 
+/// Returns "action_history_path" from the config file, `action_history.sqlite` if not set
+pub fn get_action_history_path() -> PathBuf {
+    ORACLE_CONFIG
+        .action_history_path
+        .clone()
+        .unwrap_or_else(|| PathBuf::from(DEFAULT_ACTION_HISTORY_PATH))
+}
+
+/// Returns "core_api_bind_address" from the config file, `0.0.0.0` if not set
+pub fn get_core_api_bind_address() -> IpAddr {
+    ORACLE_CONFIG
+        .core_api_bind_address
+        .unwrap_or(IpAddr::V4(Ipv4Addr::UNSPECIFIED))
+}
+
+/// Returns the ip of the node in use (see `node_interface::select_node`)
 pub fn get_node_ip() -> String {
-    ORACLE_CONFIG.node_ip.clone()
+    active_node_endpoint().node_ip
 }
 
 pub fn get_node_port() -> String {
-    ORACLE_CONFIG.node_port.to_string()
+    active_node_endpoint().node_port.to_string()
 }
 
-/// Returns the `node_api_key`
+/// Returns the api key of the node in use
 pub fn get_node_api_key() -> String {
-    ORACLE_CONFIG.node_api_key.clone()
+    active_node_endpoint().node_api_key
 }
 
 #[cfg(test)]
 mod tests {
+    use std::time::Instant;
 
+    use crate::datapoint_source::DataPointSourceError;
     use crate::pool_commands::test_utils::generate_token_ids;
 
     use super::*;
@@ -236,4 +437,41 @@ mod tests {
         let s = serde_yaml::to_string(&token_ids).unwrap();
         assert_eq!(token_ids, serde_yaml::from_str::<TokenIds>(&s).unwrap());
     }
+
+    #[test]
+    fn test_replay_data_point_source() {
+        let path = std::env::temp_dir().join(format!(
+            "oracle-core-replay-config-test-{}.jsonl",
+            std::process::id()
+        ));
+        std::fs::write(
+            &path,
+            r#"{"recorded_at":0,"value":100,"observed_at":null,"source":"a","error":null}
+{"recorded_at":1,"value":null,"observed_at":null,"source":null,"error":"Missing JSON field"}
+{"recorded_at":2,"value":102,"observed_at":null,"source":"a","error":null}
+"#,
+        )
+        .unwrap();
+        let mut config =
+            OracleConfig::create(BootstrapConfig::default(), generate_token_ids(), 0).unwrap();
+        config.data_point_source = None;
+        config.data_point_source_custom_script = None;
+        config.data_point_source_named = Some(NamedDataPointSourceConfig {
+            name: REPLAY_SOURCE_NAME.to_string(),
+            params: serde_yaml::from_str(&format!("path: {}", path.display())).unwrap(),
+        });
+        // The prefetch thread would replay the records on its own schedule
+        assert_eq!(config.data_point_prefetch_interval(), None);
+        let source = config.data_point_source().unwrap();
+        std::fs::remove_file(&path).unwrap();
+        let started = Instant::now();
+        assert_eq!(source.get_datapoint_retry(3).unwrap(), 100);
+        // The recorded failure is retried right away, like it was when recorded
+        assert_eq!(source.get_datapoint_retry(3).unwrap(), 102);
+        assert!(started.elapsed() < Duration::from_secs(1));
+        assert!(matches!(
+            source.get_datapoint_retry(3),
+            Err(DataPointSourceError::ReplayExhausted)
+        ));
+    }
 }
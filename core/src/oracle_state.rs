@@ -7,24 +7,33 @@ use crate::box_kind::{
 };
 use crate::contracts::ballot::BallotContract;
 use crate::contracts::oracle::OracleContract;
-use crate::datapoint_source::{DataPointSource, DataPointSourceError};
-use crate::node_interface::{current_block_height, get_wallet_status, rescan_from_height};
-use crate::oracle_config::ORACLE_CONFIG;
+use crate::datapoint_source::{DataPointSource, DataPointSourceError, PrefetchingDataPointSource};
+use crate::metrics::MeteredDataPointSource;
+use crate::node_interface::{
+    new_node_interface_for, node_endpoint, node_sync_status_for, rescan_from_height,
+    NodeScansRegistration, NodeSyncStatus, MAX_NODE_SYNC_LAG,
+};
+use crate::offline_signing::export_path;
+use crate::oracle_config::{OracleConfig, ORACLE_CONFIG};
 use crate::scans::{
-    register_ballot_box_scan, register_datapoint_scan, register_local_ballot_box_scan,
-    register_local_oracle_datapoint_scan, register_pool_box_scan, register_refresh_box_scan,
-    register_update_box_scan, save_scan_ids_locally, Scan, ScanError,
+    local_scan_name, register_ballot_box_scan, register_datapoint_scan,
+    register_local_ballot_box_scan, register_local_oracle_datapoint_scan,
+    register_local_wallet_scan, register_pool_box_scan, register_refresh_box_scan,
+    register_update_box_scan, save_scan_ids_locally, scan_ids_path, NodeScanIds, Scan, ScanError,
+    LOCAL_BALLOT_BOX_SCAN_NAME, LOCAL_ORACLE_DATAPOINT_SCAN_NAME, LOCAL_WALLET_SCAN_NAME,
+    POOL_BOX_SCAN_NAME, REFRESH_BOX_SCAN_NAME,
 };
 use crate::state::PoolState;
+use anyhow::anyhow;
 use anyhow::Error;
 use derive_more::From;
 
 use ergo_lib::ergo_chain_types::blake2b256_hash;
-use ergo_lib::ergotree_ir::chain::address::Address;
+use ergo_lib::ergotree_ir::chain::address::{Address, NetworkAddress};
 use ergo_lib::ergotree_ir::chain::ergo_box::ErgoBox;
 use ergo_lib::ergotree_ir::mir::constant::TryExtractFromError;
 use ergo_lib::ergotree_ir::serialization::SigmaSerializable;
-use std::path::Path;
+use std::time::Duration;
 use thiserror::Error;
 
 pub type Result<T> = std::result::Result<T, StageError>;
@@ -107,14 +116,22 @@ pub struct OraclePool<'a> {
     pub data_point_source: Box<dyn DataPointSource + Sync + Send>,
     /// Stages
     pub datapoint_stage: DatapointStage<'a>,
-    local_oracle_datapoint_scan: LocalOracleDatapointScan<'a>,
-    local_ballot_box_scan: LocalBallotBoxScan<'a>,
+    /// The oracle identities, the one of `oracle_address` first
+    identities: Vec<OracleIdentity<'a>>,
     pool_box_scan: PoolBoxScan<'a>,
     refresh_box_scan: RefreshBoxScan<'a>,
     ballot_boxes_scan: BallotBoxesScan<'a>,
     update_box_scan: UpdateBoxScan<'a>,
 }
 
+/// An oracle token holder operated by this process, with the scans of its own boxes
+#[derive(Debug)]
+pub struct OracleIdentity<'a> {
+    pub address: NetworkAddress,
+    local_oracle_datapoint_scan: LocalOracleDatapointScan<'a>,
+    local_ballot_box_scan: LocalBallotBoxScan<'a>,
+}
+
 #[derive(Debug)]
 pub struct DatapointStage<'a> {
     pub stage: Stage,
@@ -180,59 +197,57 @@ impl<'a> OraclePool<'a> {
 
         let data_point_source = config.data_point_source()?;
 
-        let refresh_box_scan_name = "Refresh Box Scan";
-
         let datapoint_contract =
             OracleContract::checked_load(&config.oracle_box_wrapper_inputs.contract_inputs)?
                 .ergo_tree();
 
-        // Read scanIDs.json for scan ids
-        let scan_json = json::parse(
-            &std::fs::read_to_string("scanIDs.json").expect("Unable to read scanIDs.json"),
-        )
-        .expect("Failed to parse scanIDs.json");
+        // Read the scanIDs.json of every node for scan ids
+        let scan_ids = NodeScanIds::load().expect("Unable to read scanIDs.json");
 
         // Create all `Scan` structs for protocol
-        let datapoint_scan = Scan::new(
-            "All Oracle Datapoints Scan",
-            &scan_json["All Datapoints Scan"].to_string(),
-        );
-        let local_scan_str = "Local Oracle Datapoint Scan";
-        let local_oracle_datapoint_scan = LocalOracleDatapointScan {
-            scan: Scan::new(
-                "Local Oracle Datapoint Scan",
-                &scan_json[local_scan_str].to_string(),
-            ),
-            oracle_box_wrapper_inputs: &config.oracle_box_wrapper_inputs,
-        };
-
-        let local_scan_str = "Local Ballot Box Scan";
-        let local_ballot_box_scan = LocalBallotBoxScan {
-            scan: Scan::new(local_scan_str, &scan_json[local_scan_str].to_string()),
-            ballot_box_wrapper_inputs: &config.ballot_box_wrapper_inputs,
-            ballot_token_owner_address: config.oracle_address.address(),
-        };
+        let datapoint_scan = scan_ids.scan("All Oracle Datapoints Scan", "All Datapoints Scan");
+        let identities = config
+            .oracle_addresses()
+            .into_iter()
+            .enumerate()
+            .map(|(index, address)| {
+                let local_scan_str =
+                    local_scan_name(LOCAL_ORACLE_DATAPOINT_SCAN_NAME, index, &address);
+                let local_oracle_datapoint_scan = LocalOracleDatapointScan {
+                    scan: scan_ids.scan(&local_scan_str, &local_scan_str),
+                    oracle_box_wrapper_inputs: &config.oracle_box_wrapper_inputs,
+                };
+                let local_scan_str = local_scan_name(LOCAL_BALLOT_BOX_SCAN_NAME, index, &address);
+                let local_ballot_box_scan = LocalBallotBoxScan {
+                    scan: scan_ids.scan(&local_scan_str, &local_scan_str),
+                    ballot_box_wrapper_inputs: &config.ballot_box_wrapper_inputs,
+                    ballot_token_owner_address: address.address(),
+                };
+                OracleIdentity {
+                    address,
+                    local_oracle_datapoint_scan,
+                    local_ballot_box_scan,
+                }
+            })
+            .collect();
 
         let ballot_boxes_scan = BallotBoxesScan {
-            scan: Scan::new("Ballot Box Scan", &scan_json["Ballot Box Scan"].to_string()),
+            scan: scan_ids.scan("Ballot Box Scan", "Ballot Box Scan"),
             ballot_box_wrapper_inputs: &config.ballot_box_wrapper_inputs,
         };
 
         let pool_box_scan = PoolBoxScan {
-            scan: Scan::new("Pool Box Scan", &scan_json["Pool Box Scan"].to_string()),
+            scan: scan_ids.scan(POOL_BOX_SCAN_NAME, POOL_BOX_SCAN_NAME),
             pool_box_wrapper_inputs: &config.pool_box_wrapper_inputs,
         };
 
         let refresh_box_scan = RefreshBoxScan {
-            scan: Scan::new(
-                refresh_box_scan_name,
-                &scan_json[refresh_box_scan_name].to_string(),
-            ),
+            scan: scan_ids.scan(REFRESH_BOX_SCAN_NAME, REFRESH_BOX_SCAN_NAME),
             refresh_box_wrapper_inputs: &config.refresh_box_wrapper_inputs,
         };
 
         let update_box_scan = UpdateBoxScan {
-            scan: Scan::new("Update Box Scan", &scan_json["Update Box Scan"].to_string()),
+            scan: scan_ids.scan("Update Box Scan", "Update Box Scan"),
             update_box_wrapper_inputs: &config.update_box_wrapper_inputs,
         };
 
@@ -248,8 +263,7 @@ impl<'a> OraclePool<'a> {
                 },
                 oracle_box_wrapper_inputs: &config.oracle_box_wrapper_inputs,
             },
-            local_oracle_datapoint_scan,
-            local_ballot_box_scan,
+            identities,
             ballot_boxes_scan,
             pool_box_scan,
             refresh_box_scan,
@@ -257,6 +271,63 @@ impl<'a> OraclePool<'a> {
         })
     }
 
+    /// Fetch datapoints in a background thread every `interval`, so that a recent one is ready
+    /// when it's time to publish
+    pub fn with_data_point_prefetch(self, interval: Duration) -> Self {
+        OraclePool {
+            data_point_source: Box::new(PrefetchingDataPointSource::new(
+                self.data_point_source,
+                interval,
+            )),
+            ..self
+        }
+    }
+
+    /// Record the fetch time and the errors of the datapoint source in the metrics
+    pub fn with_data_point_metrics(self) -> Self {
+        OraclePool {
+            data_point_source: Box::new(MeteredDataPointSource::new(self.data_point_source)),
+            ..self
+        }
+    }
+
+    /// All the scans used by the pool
+    pub fn scans(&self) -> Vec<&Scan> {
+        let mut scans = vec![
+            &self.datapoint_stage.stage.scan,
+            &self.pool_box_scan.scan,
+            &self.refresh_box_scan.scan,
+            &self.ballot_boxes_scan.scan,
+            &self.update_box_scan.scan,
+        ];
+        for identity in &self.identities {
+            scans.push(&identity.local_oracle_datapoint_scan.scan);
+            scans.push(&identity.local_ballot_box_scan.scan);
+        }
+        scans
+    }
+
+    /// All the oracle identities, the one of `oracle_address` first
+    pub fn identities(&self) -> &[OracleIdentity<'a>] {
+        &self.identities
+    }
+
+    /// The identity of `oracle_address`, used by the CLI commands and by default by the REST API
+    pub fn primary_identity(&self) -> &OracleIdentity<'a> {
+        &self.identities[0]
+    }
+
+    /// The identity with the given (base58) address, the primary one if `None`
+    pub fn identity(&self, address: Option<&str>) -> Option<&OracleIdentity<'a>> {
+        match address {
+            Some(address) => self
+                .identities
+                .iter()
+                .find(|identity| identity.address.to_base58() == address),
+            None => Some(self.primary_identity()),
+        }
+    }
+
     /// Get the current stage of the oracle pool box. Returns either `Preparation` or `Epoch`.
     pub fn check_oracle_pool_stage(&self) -> PoolState {
         match self.get_live_epoch_state() {
@@ -267,11 +338,19 @@ impl<'a> OraclePool<'a> {
 
     /// Get the state of the current oracle pool epoch
     pub fn get_live_epoch_state(&self) -> Result<LiveEpochState> {
+        self.get_identity_live_epoch_state(self.primary_identity())
+    }
+
+    /// Get the state of the current oracle pool epoch as seen by the given oracle identity
+    pub fn get_identity_live_epoch_state(
+        &self,
+        identity: &OracleIdentity,
+    ) -> Result<LiveEpochState> {
         let pool_box = self.get_pool_box_source().get_pool_box()?;
         let epoch_id: u32 = pool_box.epoch_counter();
 
         // Whether datapoint was commit in the current Live Epoch
-        let local_datapoint_box_state = self
+        let local_datapoint_box_state = identity
             .get_local_datapoint_box_source()
             .get_local_oracle_datapoint_box()?
             .map(|local_data_point_box| match local_data_point_box {
@@ -301,7 +380,7 @@ impl<'a> OraclePool<'a> {
     }
 
     pub fn get_local_ballot_box_source(&self) -> &dyn LocalBallotBoxSource {
-        &self.local_ballot_box_scan as &dyn LocalBallotBoxSource
+        self.primary_identity().get_local_ballot_box_source()
     }
 
     pub fn get_ballot_boxes_source(&self) -> &dyn VoteBallotBoxesSource {
@@ -317,7 +396,7 @@ impl<'a> OraclePool<'a> {
     }
 
     pub fn get_local_datapoint_box_source(&self) -> &dyn LocalDatapointBoxSource {
-        &self.local_oracle_datapoint_scan as &dyn LocalDatapointBoxSource
+        self.primary_identity().get_local_datapoint_box_source()
     }
 
     pub fn get_update_box_source(&self) -> &dyn UpdateBoxSource {
@@ -325,6 +404,16 @@ impl<'a> OraclePool<'a> {
     }
 }
 
+impl<'a> OracleIdentity<'a> {
+    pub fn get_local_ballot_box_source(&self) -> &dyn LocalBallotBoxSource {
+        &self.local_ballot_box_scan as &dyn LocalBallotBoxSource
+    }
+
+    pub fn get_local_datapoint_box_source(&self) -> &dyn LocalDatapointBoxSource {
+        &self.local_oracle_datapoint_scan as &dyn LocalDatapointBoxSource
+    }
+}
+
 impl<'a> PoolBoxSource for PoolBoxScan<'a> {
     fn get_pool_box(&self) -> Result<PoolBoxWrapper> {
         let box_wrapper = PoolBoxWrapper::new(
@@ -440,19 +529,46 @@ impl<'a> DatapointBoxesSource for DatapointStage<'a> {
     }
 }
 
-/// Register scans and save in scanIDs.json (if it doesn't already exist), and wait for rescan to complete
+/// Register scans and save them in the scanIDs.json of every node, see
+/// `register_and_save_node_scans`. With several nodes the unreachable ones are skipped, their
+/// scans are registered by the main loop once they are reachable (see `MissingScansRegistration`).
 pub fn register_and_save_scans() -> std::result::Result<(), Error> {
+    let endpoints = ORACLE_CONFIG.node_endpoints();
+    let mut registered = 0;
+    for (index, endpoint) in endpoints.iter().enumerate() {
+        match register_and_save_node_scans(index) {
+            Ok(()) => registered += 1,
+            Err(e) if endpoints.len() > 1 => {
+                log::error!("Failed to register the scans in node {}: {:?}", endpoint, e)
+            }
+            Err(e) => return Err(e),
+        }
+    }
+    if registered == 0 {
+        return Err(anyhow!("failed to register the scans in any node"));
+    }
+    Ok(())
+}
+
+/// Register scans and save in the scanIDs.json of the node at `node_index` (if it doesn't already
+/// exist), and wait for rescan to complete
+fn register_and_save_node_scans(node_index: usize) -> std::result::Result<(), Error> {
     let config = &ORACLE_CONFIG;
-    if !Path::new("scanIDs.json").exists() {
-        register_and_save_scans_inner()?;
+    let scan_ids_path = scan_ids_path(node_index, &node_endpoint(node_index));
+    if !scan_ids_path.exists() {
+        register_and_save_scans_inner(node_index)?;
     } else {
         // If the UpdatePool command was issued values relating to the pool box in `scanIDs.json` will be out
         // of date. So we regenerate `scanIDs.json` and initiate a wallet rescan.
 
-        // Note that the following variable was created from the existing `scanIDs.json`.
-        let oracle_pool = OraclePool::new()?;
-
-        let scan_pool_box_wrapper = oracle_pool.get_pool_box_source().get_pool_box()?;
+        // Note that the following box is found by the scan of the existing `scanIDs.json`.
+        let scan_pool_box_wrapper = PoolBoxWrapper::new(
+            NodeScanIds::load()?
+                .scan(POOL_BOX_SCAN_NAME, POOL_BOX_SCAN_NAME)
+                .get_node_box(node_index)?
+                .ok_or(StageError::PoolBoxNotFoundError)?,
+            &config.pool_box_wrapper_inputs,
+        )?;
         let config_pool_box_bytes = &config
             .pool_box_wrapper_inputs
             .contract_inputs
@@ -471,14 +587,25 @@ pub fn register_and_save_scans() -> std::result::Result<(), Error> {
 
         // The UpdatePool command will lead to either a change in the pool box script and/or a
         // change in the reward tokens.
-        if pool_hash_changed || reward_tokens_changed {
-            register_and_save_scans_inner()?;
+        // The local scans added to the config since (a local wallet, new oracle identities) are
+        // registered along with all the others.
+        let scan_ids = json::parse(&std::fs::read_to_string(&scan_ids_path)?)?;
+        let local_scan_missing = local_scan_names(config)
+            .iter()
+            .any(|name| scan_ids[name.as_str()].is_null());
+        if pool_hash_changed || reward_tokens_changed || local_scan_missing {
+            register_and_save_scans_inner(node_index)?;
         }
     }
 
+    if config.local_wallet_secret_file.is_some() {
+        return wait_for_pool_scans(node_index);
+    }
+    // Wait for the rescan
+    let node = new_node_interface_for(node_index);
     loop {
-        let wallet_height = get_wallet_status()?.height;
-        let block_height = current_block_height()?;
+        let wallet_height = node.wallet_status()?.height;
+        let block_height = node.current_block_height()?;
         if wallet_height == block_height {
             break;
         }
@@ -488,17 +615,101 @@ pub fn register_and_save_scans() -> std::result::Result<(), Error> {
     Ok(())
 }
 
-/// Registers and saves scans to `scanIDs.json` as well as performing wallet rescanning.
+/// Waits for the scans of the node at `node_index` to find the pool and refresh boxes, without the
+/// node wallet API (used with a local wallet)
+fn wait_for_pool_scans(node_index: usize) -> std::result::Result<(), Error> {
+    loop {
+        let sync_status = node_sync_status_for(node_index)?;
+        if pool_scans_caught_up(node_index, &sync_status)? {
+            return Ok(());
+        }
+        std::thread::sleep(std::time::Duration::from_secs(1));
+        println!(
+            "Waiting for the scans to find the pool boxes (node at {}/{} blocks)",
+            sync_status.full_height, sync_status.headers_height
+        );
+    }
+}
+
+/// Whether the node at `node_index` is synced and its scans found the pool and refresh boxes. Both
+/// boxes are recreated every epoch, so once they are found the scans have caught up to a recent
+/// height.
+fn pool_scans_caught_up(
+    node_index: usize,
+    sync_status: &NodeSyncStatus,
+) -> std::result::Result<bool, Error> {
+    let scan_ids = NodeScanIds::load()?;
+    Ok(sync_status.lag() <= MAX_NODE_SYNC_LAG
+        && scan_ids
+            .scan(POOL_BOX_SCAN_NAME, POOL_BOX_SCAN_NAME)
+            .get_node_box(node_index)?
+            .is_some()
+        && scan_ids
+            .scan(REFRESH_BOX_SCAN_NAME, REFRESH_BOX_SCAN_NAME)
+            .get_node_box(node_index)?
+            .is_some())
+}
+
+/// Registers the scans in the nodes which become reachable after the start, without waiting for
+/// their rescan (the main loop keeps running on the other nodes meanwhile)
+pub struct MissingScansRegistration;
+
+impl NodeScansRegistration for MissingScansRegistration {
+    fn register(&self, index: usize) -> std::result::Result<(), String> {
+        register_and_save_scans_inner(index).map_err(|e| format!("{:?}", e))
+    }
+
+    /// Same checks as `register_and_save_node_scans`, without waiting
+    fn caught_up(&self, index: usize) -> std::result::Result<bool, String> {
+        node_scans_caught_up(index).map_err(|e| format!("{:?}", e))
+    }
+}
+
+fn node_scans_caught_up(node_index: usize) -> std::result::Result<bool, Error> {
+    if ORACLE_CONFIG.local_wallet_secret_file.is_some() {
+        pool_scans_caught_up(node_index, &node_sync_status_for(node_index)?)
+    } else {
+        let node = new_node_interface_for(node_index);
+        Ok(node.wallet_status()?.height == node.current_block_height()?)
+    }
+}
+
+/// Names of the scans of the oracle identities and of the local wallet
+fn local_scan_names(config: &OracleConfig) -> Vec<String> {
+    let mut names = Vec::new();
+    for (index, oracle_address) in config.oracle_addresses().iter().enumerate() {
+        names.push(local_scan_name(
+            LOCAL_ORACLE_DATAPOINT_SCAN_NAME,
+            index,
+            oracle_address,
+        ));
+        names.push(local_scan_name(
+            LOCAL_BALLOT_BOX_SCAN_NAME,
+            index,
+            oracle_address,
+        ));
+    }
+    if oracle_address_scan_needed(config) {
+        names.push(LOCAL_WALLET_SCAN_NAME.to_string());
+    }
+    names
+}
+
+/// The boxes of the oracle address are scanned when they are the wallet inputs (with a local
+/// wallet or when exporting txs for offline signing)
+fn oracle_address_scan_needed(config: &OracleConfig) -> bool {
+    config.local_wallet_secret_file.is_some() || export_path().is_some()
+}
+
+/// Registers and saves scans to the `scanIDs.json` of the node at `node_index` as well as
+/// performing wallet rescanning.
 ///
 /// WARNING: will overwrite existing `scanIDs.json`!
-fn register_and_save_scans_inner() -> std::result::Result<(), Error> {
+fn register_and_save_scans_inner(node_index: usize) -> std::result::Result<(), Error> {
     let config = &ORACLE_CONFIG;
-    let local_oracle_address = config.oracle_address.clone();
 
     let oracle_pool_participant_token_id = config.token_ids.oracle_token_id.clone();
 
-    let refresh_box_scan_name = "Refresh Box Scan";
-
     let datapoint_contract_address =
         OracleContract::checked_load(&config.oracle_box_wrapper_inputs.contract_inputs)?
             .ergo_tree();
@@ -507,33 +718,61 @@ fn register_and_save_scans_inner() -> std::result::Result<(), Error> {
         BallotContract::checked_load(&config.ballot_box_wrapper_inputs.contract_inputs)?
             .ergo_tree();
 
-    let scans = vec![
+    let mut scans = vec![
         register_datapoint_scan(
+            node_index,
             &oracle_pool_participant_token_id,
             &datapoint_contract_address,
         )?,
-        register_update_box_scan(&config.token_ids.update_nft_token_id)?,
-        register_pool_box_scan(config.pool_box_wrapper_inputs.clone())?,
+        register_update_box_scan(node_index, &config.token_ids.update_nft_token_id)?,
+        register_pool_box_scan(node_index, config.pool_box_wrapper_inputs.clone())?,
         register_refresh_box_scan(
-            refresh_box_scan_name,
+            node_index,
+            REFRESH_BOX_SCAN_NAME,
             config.refresh_box_wrapper_inputs.clone(),
         )?,
-        register_local_oracle_datapoint_scan(
+        register_ballot_box_scan(
+            node_index,
+            &ballot_contract_address,
+            &config.token_ids.ballot_token_id,
+        )?,
+    ];
+    for (index, oracle_address) in config.oracle_addresses().iter().enumerate() {
+        scans.push(register_local_oracle_datapoint_scan(
+            node_index,
+            &local_scan_name(LOCAL_ORACLE_DATAPOINT_SCAN_NAME, index, oracle_address),
             &oracle_pool_participant_token_id,
             &datapoint_contract_address,
-            &local_oracle_address,
-        )?,
-        register_local_ballot_box_scan(
+            oracle_address,
+        )?);
+        scans.push(register_local_ballot_box_scan(
+            node_index,
+            &local_scan_name(LOCAL_BALLOT_BOX_SCAN_NAME, index, oracle_address),
             &ballot_contract_address,
             &config.token_ids.ballot_token_id,
+            oracle_address,
+        )?);
+    }
+    if oracle_address_scan_needed(config) {
+        scans.push(register_local_wallet_scan(
+            node_index,
             &config.oracle_address,
-        )?,
-        register_ballot_box_scan(&ballot_contract_address, &config.token_ids.ballot_token_id)?,
-    ];
+        )?);
+    }
 
     log::info!("Registering UTXO-Set Scans");
-    save_scan_ids_locally(scans)?;
+    save_scan_ids_locally(node_index, scans)?;
     log::info!("Triggering wallet rescan");
-    rescan_from_height(ORACLE_CONFIG.rescan_height)?;
+    match rescan_from_height(node_index, ORACLE_CONFIG.rescan_height) {
+        Ok(()) => (),
+        // The scans still pick up the boxes of the new blocks, the pool boxes are recreated every
+        // epoch
+        Err(e) if config.local_wallet_secret_file.is_some() => log::warn!(
+            "Failed to trigger the rescan ({}), the boxes created before the scans were registered \
+             (e.g. the local wallet boxes) are only found after a rescan",
+            e
+        ),
+        Err(e) => return Err(e.into()),
+    }
     Ok(())
 }
@@ -6,7 +6,7 @@ use thiserror::Error;
 use crate::actions::PoolAction;
 use crate::box_kind::PoolBox;
 use crate::oracle_config::ORACLE_CONFIG;
-use crate::oracle_state::{OraclePool, StageError};
+use crate::oracle_state::{OracleIdentity, OraclePool, StageError};
 use crate::wallet::WalletDataSource;
 
 use self::publish_datapoint::build_publish_first_datapoint_action;
@@ -46,9 +46,11 @@ pub enum PoolCommandError {
     WrongOracleAddressType,
 }
 
+/// Builds the action of the command for the given oracle identity
 pub fn build_action(
     cmd: PoolCommand,
     op: &OraclePool,
+    identity: &OracleIdentity,
     wallet: &dyn WalletDataSource,
     height: u32,
     change_address: Address,
@@ -57,12 +59,11 @@ pub fn build_action(
     let datapoint_stage_src = op.get_datapoint_boxes_source();
     let pool_box = op.get_pool_box_source().get_pool_box()?;
     let current_epoch_counter = pool_box.epoch_counter();
-    let oracle_public_key =
-        if let Address::P2Pk(public_key) = ORACLE_CONFIG.oracle_address.address() {
-            public_key
-        } else {
-            return Err(PoolCommandError::WrongOracleAddressType);
-        };
+    let oracle_public_key = if let Address::P2Pk(public_key) = identity.address.address() {
+        public_key
+    } else {
+        return Err(PoolCommandError::WrongOracleAddressType);
+    };
 
     match cmd {
         PoolCommand::PublishFirstDataPoint => build_publish_first_datapoint_action(
@@ -72,11 +73,13 @@ pub fn build_action(
             oracle_public_key,
             ORACLE_CONFIG.oracle_box_wrapper_inputs.clone(),
             &*op.data_point_source,
+            pool_box.rate(),
+            &ORACLE_CONFIG.data_point_guards,
         )
         .map_err(Into::into)
         .map(Into::into),
         PoolCommand::PublishSubsequentDataPoint { republish: _ } => {
-            if let Some(local_datapoint_box) = op
+            if let Some(local_datapoint_box) = identity
                 .get_local_datapoint_box_source()
                 .get_local_oracle_datapoint_box()?
             {
@@ -89,6 +92,7 @@ pub fn build_action(
                     &*op.data_point_source,
                     new_epoch_counter,
                     pool_box.rate(),
+                    &ORACLE_CONFIG.data_point_guards,
                 )
                 .map_err(Into::into)
                 .map(Into::into)
@@ -16,13 +16,14 @@ use ergo_lib::{
         tx_builder::{TxBuilder, TxBuilderError},
     },
 };
+use serde::{Deserialize, Serialize};
 use thiserror::Error;
 
 use crate::{
     actions::PublishDataPointAction,
     box_kind::{make_oracle_box_candidate, OracleBox, OracleBoxWrapper, OracleBoxWrapperInputs},
     contracts::oracle::{OracleContract, OracleContractError},
-    datapoint_source::{DataPointSource, DataPointSourceError},
+    datapoint_source::{DataPoint, DataPointSource, DataPointSourceError},
     oracle_config::BASE_FEE,
     oracle_state::StageError,
     wallet::{WalletDataError, WalletDataSource},
@@ -46,8 +47,121 @@ pub enum PublishDatapointActionError {
     DataPointSource(DataPointSourceError),
     #[error("oracle contract error: {0}")]
     OracleContract(OracleContractError),
+    #[error("datapoint rejected: {0}")]
+    DatapointRejected(DatapointGuardError),
 }
 
+#[derive(Debug, Error)]
+pub enum DatapointGuardError {
+    #[error("datapoint {datapoint} is below the minimum {min}")]
+    BelowMin { datapoint: i64, min: i64 },
+    #[error("datapoint {datapoint} is above the maximum {max}")]
+    AboveMax { datapoint: i64, max: i64 },
+    #[error(
+        "datapoint {datapoint} deviates more than {max_percent}% from the pool rate {pool_rate}"
+    )]
+    PoolRateChange {
+        datapoint: i64,
+        pool_rate: i64,
+        max_percent: u32,
+    },
+    #[error(
+        "datapoint {datapoint} deviates more than {max_percent}% from our last posted rate {own_rate}"
+    )]
+    OwnRateChange {
+        datapoint: i64,
+        own_rate: i64,
+        max_percent: u32,
+    },
+    #[error(
+        "datapoint from {source_name} is {age_secs} seconds old, maximum age is {max_age_secs}"
+    )]
+    Stale {
+        source_name: String,
+        age_secs: u64,
+        max_age_secs: u64,
+    },
+}
+
+/// Limits a new datapoint must satisfy to be published. Every limit is optional.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DatapointGuards {
+    pub min_datapoint: Option<i64>,
+    pub max_datapoint: Option<i64>,
+    /// Maximum change (in percent) versus the current pool rate
+    pub max_change_from_pool_rate_percent: Option<u32>,
+    /// Maximum change (in percent) versus the datapoint in our own oracle box
+    pub max_change_from_own_rate_percent: Option<u32>,
+    /// Maximum time since the source observed the datapoint. Only checked for sources which
+    /// report the observation time.
+    pub max_datapoint_age_secs: Option<u64>,
+}
+
+impl DatapointGuards {
+    pub fn check(
+        &self,
+        new_datapoint: &DataPoint,
+        pool_rate: Option<i64>,
+        own_rate: Option<i64>,
+    ) -> Result<(), DatapointGuardError> {
+        if let (Some(max_age_secs), Some(age)) = (self.max_datapoint_age_secs, new_datapoint.age())
+        {
+            if age.as_secs() > max_age_secs {
+                return Err(DatapointGuardError::Stale {
+                    source_name: new_datapoint.source.clone(),
+                    age_secs: age.as_secs(),
+                    max_age_secs,
+                });
+            }
+        }
+        let datapoint = new_datapoint.value;
+        if let Some(min) = self.min_datapoint {
+            if datapoint < min {
+                return Err(DatapointGuardError::BelowMin { datapoint, min });
+            }
+        }
+        if let Some(max) = self.max_datapoint {
+            if datapoint > max {
+                return Err(DatapointGuardError::AboveMax { datapoint, max });
+            }
+        }
+        if let (Some(max_percent), Some(pool_rate)) =
+            (self.max_change_from_pool_rate_percent, pool_rate)
+        {
+            if exceeds_change(datapoint, pool_rate, max_percent) {
+                return Err(DatapointGuardError::PoolRateChange {
+                    datapoint,
+                    pool_rate,
+                    max_percent,
+                });
+            }
+        }
+        if let (Some(max_percent), Some(own_rate)) =
+            (self.max_change_from_own_rate_percent, own_rate)
+        {
+            if exceeds_change(datapoint, own_rate, max_percent) {
+                return Err(DatapointGuardError::OwnRateChange {
+                    datapoint,
+                    own_rate,
+                    max_percent,
+                });
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Whether `datapoint` differs from `reference` by more than `max_percent`. A non-positive
+/// reference (e.g. the pool box right after bootstrap) can't be compared against.
+fn exceeds_change(datapoint: i64, reference: i64, max_percent: u32) -> bool {
+    if reference <= 0 {
+        return false;
+    }
+    let delta = (datapoint as i128 - reference as i128).abs();
+    delta * 100 > reference as i128 * max_percent as i128
+}
+
+#[allow(clippy::too_many_arguments)]
 pub fn build_subsequent_publish_datapoint_action(
     local_datapoint_box: &OracleBoxWrapper,
     wallet: &dyn WalletDataSource,
@@ -55,10 +169,16 @@ pub fn build_subsequent_publish_datapoint_action(
     change_address: Address,
     datapoint_source: &dyn DataPointSource,
     new_epoch_counter: u32,
-    _pool_datapoint: i64,
+    pool_datapoint: i64,
+    guards: &DatapointGuards,
 ) -> Result<PublishDataPointAction, PublishDatapointActionError> {
-    let new_datapoint = datapoint_source.get_datapoint_retry(3)?;
+    let new_datapoint = datapoint_source.get_datapoint_with_metadata_retry(3)?;
     let in_oracle_box = local_datapoint_box;
+    let own_datapoint = match in_oracle_box {
+        OracleBoxWrapper::Posted(posted) => Some(posted.rate() as i64),
+        OracleBoxWrapper::Collected(_) => None,
+    };
+    guards.check(&new_datapoint, Some(pool_datapoint), own_datapoint)?;
     if *in_oracle_box.reward_token().amount.as_u64() == 0 {
         return Err(PublishDatapointActionError::NoRewardTokenInOracleBox);
     }
@@ -66,7 +186,7 @@ pub fn build_subsequent_publish_datapoint_action(
     let output_candidate = make_oracle_box_candidate(
         in_oracle_box.contract(),
         in_oracle_box.public_key(),
-        new_datapoint,
+        new_datapoint.value,
         new_epoch_counter,
         in_oracle_box.oracle_token(),
         in_oracle_box.reward_token(),
@@ -98,7 +218,11 @@ pub fn build_subsequent_publish_datapoint_action(
     };
     tx_builder.set_context_extension(in_oracle_box.get_box().box_id(), ctx_ext);
     let tx = tx_builder.build()?;
-    Ok(PublishDataPointAction { tx })
+    Ok(PublishDataPointAction {
+        tx,
+        datapoint: new_datapoint.value,
+        fee: tx_fee,
+    })
 }
 
 #[allow(clippy::too_many_arguments)]
@@ -109,8 +233,11 @@ pub fn build_publish_first_datapoint_action(
     public_key: ProveDlog,
     inputs: OracleBoxWrapperInputs,
     datapoint_source: &dyn DataPointSource,
+    pool_datapoint: i64,
+    guards: &DatapointGuards,
 ) -> Result<PublishDataPointAction, PublishDatapointActionError> {
-    let new_datapoint = datapoint_source.get_datapoint_retry(3)?;
+    let new_datapoint = datapoint_source.get_datapoint_with_metadata_retry(3)?;
+    guards.check(&new_datapoint, Some(pool_datapoint), None)?;
     let unspent_boxes = wallet.get_unspent_wallet_boxes()?;
     let tx_fee = *BASE_FEE;
     let box_selector = SimpleBoxSelector::new();
@@ -136,7 +263,7 @@ pub fn build_publish_first_datapoint_action(
     let output_candidate = make_oracle_box_candidate(
         &contract,
         public_key,
-        new_datapoint,
+        new_datapoint.value,
         1,
         oracle_token,
         reward_token,
@@ -159,7 +286,11 @@ pub fn build_publish_first_datapoint_action(
     };
     tx_builder.set_context_extension(box_id, ctx_ext);
     let tx = tx_builder.build()?;
-    Ok(PublishDataPointAction { tx })
+    Ok(PublishDataPointAction {
+        tx,
+        datapoint: new_datapoint.value,
+        fee: tx_fee,
+    })
 }
 
 #[cfg(test)]
@@ -266,6 +397,7 @@ mod tests {
             &datapoint_source,
             pool_box_epoch_id as u32,
             datapoint_source.datapoint - 1,
+            &DatapointGuards::default(),
         )
         .unwrap();
 
@@ -365,13 +497,38 @@ mod tests {
                 unspent_boxes: unspent_boxes.clone(),
             },
             height,
-            change_address,
+            change_address.clone(),
             secret.public_image(),
-            oracle_box_wrapper_inputs,
+            oracle_box_wrapper_inputs.clone(),
             &MockDatapointSource { datapoint: 201 },
+            200,
+            &DatapointGuards::default(),
         )
         .unwrap();
 
+        // The first datapoint of an oracle is checked against the pool rate as well
+        let guards = DatapointGuards {
+            max_change_from_pool_rate_percent: Some(10),
+            ..Default::default()
+        };
+        assert!(matches!(
+            build_publish_first_datapoint_action(
+                &WalletDataMock {
+                    unspent_boxes: unspent_boxes.clone(),
+                },
+                height,
+                change_address,
+                secret.public_image(),
+                oracle_box_wrapper_inputs,
+                &MockDatapointSource { datapoint: 201 },
+                100,
+                &guards,
+            ),
+            Err(PublishDatapointActionError::DatapointRejected(
+                DatapointGuardError::PoolRateChange { .. }
+            ))
+        ));
+
         assert_eq!(
             action.tx.output_candidates.first().value,
             oracle_contract_parameters.min_storage_rent
@@ -382,4 +539,65 @@ mod tests {
 
         let _signed_tx = wallet.sign_transaction(tx_context, &ctx, None).unwrap();
     }
+
+    #[test]
+    fn test_datapoint_guards() {
+        let guards = DatapointGuards {
+            min_datapoint: Some(100),
+            max_datapoint: Some(1000),
+            max_change_from_pool_rate_percent: Some(10),
+            max_change_from_own_rate_percent: Some(5),
+            max_datapoint_age_secs: Some(600),
+        };
+        let datapoint = |value: i64| DataPoint {
+            value,
+            observed_at: None,
+            source: "mock".to_string(),
+        };
+        assert!(guards.check(&datapoint(200), Some(200), Some(200)).is_ok());
+        assert!(guards.check(&datapoint(220), Some(200), Some(210)).is_ok());
+        assert!(guards.check(&datapoint(200), Some(0), None).is_ok());
+        assert!(matches!(
+            guards.check(&datapoint(99), None, None),
+            Err(DatapointGuardError::BelowMin { .. })
+        ));
+        assert!(matches!(
+            guards.check(&datapoint(1001), None, None),
+            Err(DatapointGuardError::AboveMax { .. })
+        ));
+        assert!(matches!(
+            guards.check(&datapoint(221), Some(200), None),
+            Err(DatapointGuardError::PoolRateChange { .. })
+        ));
+        assert!(matches!(
+            guards.check(&datapoint(200), Some(200), Some(211)),
+            Err(DatapointGuardError::OwnRateChange { .. })
+        ));
+        assert!(DatapointGuards::default()
+            .check(&datapoint(i64::MAX), Some(1), Some(1))
+            .is_ok());
+    }
+
+    #[test]
+    fn test_datapoint_age_guard() {
+        let guards = DatapointGuards {
+            max_datapoint_age_secs: Some(600),
+            ..Default::default()
+        };
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+        let datapoint = |observed_at: Option<u64>| DataPoint {
+            value: 200,
+            observed_at,
+            source: "mock".to_string(),
+        };
+        assert!(guards.check(&datapoint(None), None, None).is_ok());
+        assert!(guards.check(&datapoint(Some(now - 60)), None, None).is_ok());
+        assert!(matches!(
+            guards.check(&datapoint(Some(now - 3600)), None, None),
+            Err(DatapointGuardError::Stale { .. })
+        ));
+    }
 }
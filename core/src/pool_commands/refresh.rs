@@ -164,7 +164,11 @@ pub fn build_refresh_action(
             b.set_context_extension(ob.get_box().box_id(), ob_ctx_ext);
         });
     let tx = b.build()?;
-    Ok(RefreshAction { tx })
+    Ok(RefreshAction {
+        tx,
+        rate,
+        fee: tx_fee,
+    })
 }
 
 fn filtered_oracle_boxes_by_rate(
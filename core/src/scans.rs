@@ -3,7 +3,8 @@ use crate::box_kind::{PoolBoxWrapperInputs, RefreshBoxWrapperInputs};
 use crate::contracts::pool::{PoolContract, PoolContractError};
 use crate::contracts::refresh::{RefreshContract, RefreshContractError};
 /// This file holds logic related to UTXO-set scans
-use crate::node_interface::{get_scan_boxes, register_scan};
+use crate::node_interface::{active_node, get_node_scan_boxes, node_endpoint, register_scan};
+use crate::oracle_config::{NodeEndpoint, ORACLE_CONFIG};
 
 use derive_more::From;
 use ergo_lib::ergotree_ir::chain::address::NetworkAddress;
@@ -11,10 +12,11 @@ use ergo_lib::ergotree_ir::chain::ergo_box::ErgoBox;
 use ergo_lib::ergotree_ir::chain::token::TokenId;
 use ergo_lib::ergotree_ir::ergo_tree::ErgoTree;
 use ergo_lib::ergotree_ir::mir::constant::Constant;
-use ergo_lib::ergotree_ir::serialization::SigmaSerializable;
+use ergo_lib::ergotree_ir::serialization::{SigmaParsingError, SigmaSerializable};
 use ergo_node_interface::node_interface::NodeError;
 use log::info;
 use serde_json::json;
+use std::path::PathBuf;
 use thiserror::Error;
 
 /// Integer which is provided by the Ergo node to reference a given scan.
@@ -22,6 +24,14 @@ pub type ScanID = String;
 
 pub type Result<T> = std::result::Result<T, ScanError>;
 
+/// Names of the pool and refresh box scans, checked to know whether the scans caught up
+pub const POOL_BOX_SCAN_NAME: &str = "Pool Box Scan";
+pub const REFRESH_BOX_SCAN_NAME: &str = "Refresh Box Scan";
+/// Name of the scan of the oracle address boxes, registered when signing with a local wallet
+pub const LOCAL_WALLET_SCAN_NAME: &str = "Local Wallet Scan";
+pub const LOCAL_ORACLE_DATAPOINT_SCAN_NAME: &str = "Local Oracle Datapoint Scan";
+pub const LOCAL_BALLOT_BOX_SCAN_NAME: &str = "Local Ballot Box Scan";
+
 #[derive(Debug, From, Error)]
 pub enum ScanError {
     #[error("node error: {0}")]
@@ -38,26 +48,57 @@ pub enum ScanError {
     PoolContract(PoolContractError),
     #[error("address util error: {0}")]
     AddressUtilError(AddressUtilError),
+    #[error("sigma parsing error: {0}")]
+    SigmaParsing(SigmaParsingError),
+    #[error("scan ids parsing error: {0}")]
+    ScanIdsParsing(json::Error),
 }
 
 /// A `Scan` is a name + scan_id for a given scan with extra methods for acquiring boxes.
 #[derive(Debug, Clone)]
 pub struct Scan {
-    name: &'static str,
-    id: ScanID,
+    name: String,
+    /// Id of the scan in each node, in the `OracleConfig::node_endpoints` order
+    ids: Vec<ScanID>,
 }
 
 impl Scan {
-    /// Create a new `Scan` with provided name & scan_id
-    pub fn new(name: &'static str, scan_id: &String) -> Scan {
+    /// Create a new `Scan` with provided name & scan_id (the same in every node)
+    pub fn new(name: &str, scan_id: &String) -> Scan {
         Scan {
-            name,
-            id: scan_id.clone(),
+            name: name.to_string(),
+            ids: vec![scan_id.clone()],
+        }
+    }
+
+    /// Create a new `Scan` with its id in each node
+    pub fn with_node_ids(name: &str, ids: Vec<ScanID>) -> Scan {
+        Scan {
+            name: name.to_string(),
+            ids,
         }
     }
 
-    /// Registers a scan in the node and returns a `Scan` as a result
-    pub fn register(name: &'static str, tracking_rule: serde_json::Value) -> Result<Scan> {
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Id of the scan in the node in use
+    pub fn id(&self) -> &ScanID {
+        self.node_id(active_node())
+    }
+
+    /// Id of the scan in the node at `node_index` in `OracleConfig::node_endpoints`
+    pub fn node_id(&self, node_index: usize) -> &ScanID {
+        self.ids.get(node_index).unwrap_or(&self.ids[0])
+    }
+
+    /// Registers a scan in the node at `node_index` and returns a `Scan` as a result
+    pub fn register(
+        node_index: usize,
+        name: &str,
+        tracking_rule: serde_json::Value,
+    ) -> Result<Scan> {
         let scan_json = json!({
             "scanName": name,
             "trackingRule": tracking_rule,
@@ -68,7 +109,7 @@ impl Scan {
             serde_json::to_string_pretty(&scan_json).unwrap()
         );
 
-        let scan_id = register_scan(&scan_json)?;
+        let scan_id = register_scan(node_index, &scan_json)?;
         info!("Scan Successfully Set.\nID: {}", scan_id);
 
         Ok(Scan::new(name, &scan_id))
@@ -76,34 +117,86 @@ impl Scan {
 
     /// Returns all boxes found by the scan
     pub fn get_boxes(&self) -> Result<Vec<ErgoBox>> {
-        let boxes = get_scan_boxes(&self.id)?;
-        Ok(boxes)
+        self.get_node_boxes(active_node())
     }
 
     /// Returns the first box found by the scan
     pub fn get_box(&self) -> Result<Option<ErgoBox>> {
-        Ok(self.get_boxes()?.first().cloned())
+        self.get_node_box(active_node())
+    }
+
+    /// Returns all boxes found by the scan in the node at `node_index`
+    pub fn get_node_boxes(&self, node_index: usize) -> Result<Vec<ErgoBox>> {
+        let boxes = get_node_scan_boxes(node_index, self.node_id(node_index))?;
+        Ok(boxes)
+    }
+
+    /// Returns the first box found by the scan in the node at `node_index`
+    pub fn get_node_box(&self, node_index: usize) -> Result<Option<ErgoBox>> {
+        Ok(self.get_node_boxes(node_index)?.first().cloned())
     }
 }
 
-/// Saves UTXO-set scans (specifically id) to scanIDs.json
-pub fn save_scan_ids_locally(scans: Vec<Scan>) -> Result<()> {
+/// Saves UTXO-set scans (specifically id) to the scanIDs.json of the node at `node_index`
+pub fn save_scan_ids_locally(node_index: usize, scans: Vec<Scan>) -> Result<()> {
     let mut id_json = json!({});
     for scan in scans {
-        if &scan.id == "null" {
+        let scan_id = scan.node_id(node_index);
+        if scan_id == "null" {
             return Err(ScanError::FailedToRegister);
         }
-        id_json[scan.name] = scan.id.into();
+        id_json[scan.name()] = scan_id.clone().into();
     }
     std::fs::write(
-        "scanIDs.json",
+        scan_ids_path(node_index, &node_endpoint(node_index)),
         serde_json::to_string_pretty(&id_json).unwrap(),
     )?;
     Ok(())
 }
 
+/// File with the ids of the scans registered in the node at `node_index` in
+/// `OracleConfig::node_endpoints`. The first node keeps `scanIDs.json`.
+pub fn scan_ids_path(node_index: usize, endpoint: &NodeEndpoint) -> PathBuf {
+    if node_index == 0 {
+        PathBuf::from("scanIDs.json")
+    } else {
+        let node_name: String = format!("{}_{}", endpoint.node_ip, endpoint.node_port)
+            .chars()
+            .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+            .collect();
+        PathBuf::from(format!("scanIDs_{}.json", node_name))
+    }
+}
+
+/// The scan ids of every node, read from their scanIDs.json (none for the nodes which don't have
+/// one yet)
+pub struct NodeScanIds(Vec<json::JsonValue>);
+
+impl NodeScanIds {
+    pub fn load() -> Result<NodeScanIds> {
+        let mut scan_ids = Vec::new();
+        for (index, endpoint) in ORACLE_CONFIG.node_endpoints().iter().enumerate() {
+            let path = scan_ids_path(index, endpoint);
+            scan_ids.push(if path.exists() {
+                json::parse(&std::fs::read_to_string(path)?)?
+            } else {
+                json::JsonValue::new_object()
+            });
+        }
+        Ok(NodeScanIds(scan_ids))
+    }
+
+    /// The scan saved as `key`, its id is "null" in the nodes it's not registered in
+    pub fn scan(&self, name: &str, key: &str) -> Scan {
+        Scan::with_node_ids(
+            name,
+            self.0.iter().map(|ids| ids[key].to_string()).collect(),
+        )
+    }
+}
+
 /// This function registers scanning for the pool box
-pub fn register_pool_box_scan(inputs: PoolBoxWrapperInputs) -> Result<Scan> {
+pub fn register_pool_box_scan(node_index: usize, inputs: PoolBoxWrapperInputs) -> Result<Scan> {
     // ErgoTree bytes of the P2S address/script
     let pool_box_tree_bytes = PoolContract::checked_load(&inputs.contract_inputs)?
         .ergo_tree()
@@ -124,11 +217,12 @@ pub fn register_pool_box_scan(inputs: PoolBoxWrapperInputs) -> Result<Scan> {
     ]
     } );
 
-    Scan::register("Pool Box Scan", scan_json)
+    Scan::register(node_index, POOL_BOX_SCAN_NAME, scan_json)
 }
 
 /// This function registers scanning for the refresh box
 pub fn register_refresh_box_scan(
+    node_index: usize,
     scan_name: &'static str,
     inputs: RefreshBoxWrapperInputs,
 ) -> Result<Scan> {
@@ -152,11 +246,28 @@ pub fn register_refresh_box_scan(
     ]
     } );
 
-    Scan::register(scan_name, scan_json)
+    Scan::register(node_index, scan_name, scan_json)
+}
+
+/// Name of a local scan of the oracle identity at `identity_index` in
+/// `OracleConfig::oracle_addresses`. The scans of `oracle_address` keep the plain name so that the
+/// existing `scanIDs.json` files stay valid.
+pub fn local_scan_name(
+    scan_name: &str,
+    identity_index: usize,
+    oracle_address: &NetworkAddress,
+) -> String {
+    if identity_index == 0 {
+        scan_name.to_string()
+    } else {
+        format!("{} {}", scan_name, oracle_address.to_base58())
+    }
 }
 
 /// This function registers scanning for the oracle's personal Datapoint box
 pub fn register_local_oracle_datapoint_scan(
+    node_index: usize,
+    scan_name: &str,
     oracle_pool_participant_token: &TokenId,
     datapoint_address: &ErgoTree,
     oracle_address: &NetworkAddress,
@@ -185,11 +296,12 @@ pub fn register_local_oracle_datapoint_scan(
     ]
     } );
 
-    Scan::register("Local Oracle Datapoint Scan", scan_json)
+    Scan::register(node_index, scan_name, scan_json)
 }
 
 /// This function registers scanning for all of the pools oracles' Datapoint boxes for datapoint collection
 pub fn register_datapoint_scan(
+    node_index: usize,
     oracle_pool_participant_token: &TokenId,
     datapoint_address: &ErgoTree,
 ) -> Result<Scan> {
@@ -209,11 +321,13 @@ pub fn register_datapoint_scan(
     ]
     } );
 
-    Scan::register("All Datapoints Scan", scan_json)
+    Scan::register(node_index, "All Datapoints Scan", scan_json)
 }
 
 /// This function registers scanning for the local ballot box
 pub fn register_local_ballot_box_scan(
+    node_index: usize,
+    scan_name: &str,
     ballot_contract_address: &ErgoTree,
     ballot_token_id: &TokenId,
     ballot_token_owner_address: &NetworkAddress,
@@ -241,11 +355,27 @@ pub fn register_local_ballot_box_scan(
     ]
     } );
 
-    Scan::register("Local Ballot Box Scan", scan_json)
+    Scan::register(node_index, scan_name, scan_json)
+}
+
+/// Scan for the boxes guarded by the oracle address, the wallet inputs of the local wallet and of
+/// the exported txs
+pub fn register_local_wallet_scan(
+    node_index: usize,
+    wallet_address: &NetworkAddress,
+) -> Result<Scan> {
+    let wallet_tree_bytes = wallet_address.address().script()?.to_scan_bytes();
+    let scan_json = json! ( {
+        "predicate": "equals",
+        "value": wallet_tree_bytes,
+    } );
+
+    Scan::register(node_index, LOCAL_WALLET_SCAN_NAME, scan_json)
 }
 
 /// Scan for all ballot boxes matching token id of oracle pool. When updating the pool box only ballot boxes voting for the new pool will be spent
 pub fn register_ballot_box_scan(
+    node_index: usize,
     ballot_contract_address: &ErgoTree,
     ballot_token_id: &TokenId,
 ) -> Result<Scan> {
@@ -261,10 +391,10 @@ pub fn register_ballot_box_scan(
             "value": ballot_contract_address.to_scan_bytes(),
         }
         ] });
-    Scan::register("Ballot Box Scan", scan_json)
+    Scan::register(node_index, "Ballot Box Scan", scan_json)
 }
 
-pub fn register_update_box_scan(update_nft_token_id: &TokenId) -> Result<Scan> {
+pub fn register_update_box_scan(node_index: usize, update_nft_token_id: &TokenId) -> Result<Scan> {
     let scan_json = json! ( {
         "predicate": "and",
         "args": [
@@ -273,7 +403,7 @@ pub fn register_update_box_scan(update_nft_token_id: &TokenId) -> Result<Scan> {
             "assetId": update_nft_token_id.clone(),
         },
         ] });
-    Scan::register("Update Box Scan", scan_json)
+    Scan::register(node_index, "Update Box Scan", scan_json)
 }
 
 /// Convert a chain type to Coll[Byte] for scans
@@ -1,10 +1,12 @@
 //! Types to allow oracle configuration to convert to and from Serde.
 
 use std::convert::{TryFrom, TryInto};
+use std::net::IpAddr;
+use std::path::PathBuf;
 
 use derive_more::From;
 use ergo_lib::ergotree_ir::chain::{
-    address::{AddressEncoder, AddressEncoderError},
+    address::{AddressEncoder, AddressEncoderError, NetworkAddress},
     ergo_box::box_value::BoxValueError,
     token::TokenId,
 };
@@ -31,8 +33,13 @@ use crate::{
         },
         update::{UpdateContractParameters, UpdateContractParametersError},
     },
-    datapoint_source::PredefinedDataPointSource,
-    oracle_config::{OracleConfig, OracleConfigError, TokenIds},
+    datapoint_source::{
+        registry::NamedDataPointSourceConfig, AggregateDataPointSourceConfig,
+        DerivedDataPointSourceConfig, ExternalScript, HttpJsonDataPointSource,
+        PredefinedDataPointSource,
+    },
+    oracle_config::{CoreApiTlsConfig, NodeEndpoint, OracleConfig, OracleConfigError, TokenIds},
+    pool_commands::publish_datapoint::DatapointGuards,
 };
 
 /// Used to (de)serialize `OracleConfig` instance.
@@ -41,12 +48,31 @@ pub(crate) struct OracleConfigSerde {
     node_ip: String,
     node_port: u16,
     node_api_key: String,
+    #[serde(default)]
+    fallback_nodes: Vec<NodeEndpoint>,
     base_fee: u64,
     log_level: Option<LevelFilter>,
     core_api_port: u16,
+    core_api_auth_token: Option<String>,
+    core_api_bind_address: Option<IpAddr>,
+    core_api_tls: Option<CoreApiTlsConfig>,
+    core_api_allowed_origins: Option<Vec<String>>,
     oracle_address: String,
+    #[serde(default)]
+    additional_oracle_addresses: Vec<String>,
     data_point_source: Option<PredefinedDataPointSource>,
-    data_point_source_custom_script: Option<String>,
+    data_point_source_custom_script: Option<ExternalScript>,
+    data_point_source_http_json: Option<HttpJsonDataPointSource>,
+    data_point_source_aggregate: Option<AggregateDataPointSourceConfig>,
+    data_point_source_named: Option<NamedDataPointSourceConfig>,
+    data_point_source_derived: Option<DerivedDataPointSourceConfig>,
+    data_point_source_record_path: Option<PathBuf>,
+    #[serde(default)]
+    data_point_guards: DatapointGuards,
+    data_point_prefetch_interval_secs: Option<u64>,
+    action_history_path: Option<PathBuf>,
+    local_wallet_secret_file: Option<PathBuf>,
+    local_wallet_password_file: Option<PathBuf>,
     oracle_contract_parameters: OracleContractParametersSerde,
     pool_contract_parameters: PoolContractParametersSerde,
     refresh_contract_parameters: RefreshContractParametersSerde,
@@ -115,12 +141,32 @@ impl From<OracleConfig> for OracleConfigSerde {
             node_ip: c.node_ip,
             node_port: c.node_port,
             node_api_key: c.node_api_key,
+            fallback_nodes: c.fallback_nodes,
             base_fee: c.base_fee,
             log_level: c.log_level,
             core_api_port: c.core_api_port,
+            core_api_auth_token: c.core_api_auth_token,
+            core_api_bind_address: c.core_api_bind_address,
+            core_api_tls: c.core_api_tls,
+            core_api_allowed_origins: c.core_api_allowed_origins,
             oracle_address: c.oracle_address.to_base58(),
+            additional_oracle_addresses: c
+                .additional_oracle_addresses
+                .iter()
+                .map(NetworkAddress::to_base58)
+                .collect(),
             data_point_source: c.data_point_source,
             data_point_source_custom_script: c.data_point_source_custom_script,
+            data_point_source_http_json: c.data_point_source_http_json,
+            data_point_source_aggregate: c.data_point_source_aggregate,
+            data_point_source_named: c.data_point_source_named,
+            data_point_source_derived: c.data_point_source_derived,
+            data_point_source_record_path: c.data_point_source_record_path,
+            data_point_guards: c.data_point_guards,
+            data_point_prefetch_interval_secs: c.data_point_prefetch_interval_secs,
+            action_history_path: c.action_history_path,
+            local_wallet_secret_file: c.local_wallet_secret_file,
+            local_wallet_password_file: c.local_wallet_password_file,
             oracle_contract_parameters,
             pool_contract_parameters,
             refresh_contract_parameters,
@@ -192,6 +238,11 @@ impl TryFrom<OracleConfigSerde> for OracleConfig {
 
         let oracle_address =
             AddressEncoder::unchecked_parse_network_address_from_str(&c.oracle_address)?;
+        let additional_oracle_addresses = c
+            .additional_oracle_addresses
+            .iter()
+            .map(|address| AddressEncoder::unchecked_parse_network_address_from_str(address))
+            .collect::<Result<Vec<_>, _>>()?;
 
         let refresh_box_wrapper_inputs = RefreshBoxWrapperInputs::checked_load(
             refresh_contract_parameters.clone(),
@@ -229,12 +280,28 @@ impl TryFrom<OracleConfigSerde> for OracleConfig {
             node_ip: c.node_ip,
             node_port: c.node_port,
             node_api_key: c.node_api_key,
+            fallback_nodes: c.fallback_nodes,
             base_fee: c.base_fee,
             log_level: c.log_level,
             core_api_port: c.core_api_port,
+            core_api_auth_token: c.core_api_auth_token,
+            core_api_bind_address: c.core_api_bind_address,
+            core_api_tls: c.core_api_tls,
+            core_api_allowed_origins: c.core_api_allowed_origins,
             oracle_address,
+            additional_oracle_addresses,
             data_point_source: c.data_point_source,
             data_point_source_custom_script: c.data_point_source_custom_script,
+            data_point_source_http_json: c.data_point_source_http_json,
+            data_point_source_aggregate: c.data_point_source_aggregate,
+            data_point_source_named: c.data_point_source_named,
+            data_point_source_derived: c.data_point_source_derived,
+            data_point_source_record_path: c.data_point_source_record_path,
+            data_point_guards: c.data_point_guards,
+            data_point_prefetch_interval_secs: c.data_point_prefetch_interval_secs,
+            action_history_path: c.action_history_path,
+            local_wallet_secret_file: c.local_wallet_secret_file,
+            local_wallet_password_file: c.local_wallet_password_file,
             oracle_box_wrapper_inputs,
             pool_box_wrapper_inputs,
             refresh_box_wrapper_inputs,
@@ -260,7 +327,7 @@ pub struct BootstrapConfigSerde {
     node_api_key: String,
     core_api_port: u16,
     data_point_source: Option<PredefinedDataPointSource>,
-    data_point_source_custom_script: Option<String>,
+    data_point_source_custom_script: Option<ExternalScript>,
     oracle_address: String,
     base_fee: u64,
 }
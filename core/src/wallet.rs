@@ -1,14 +1,23 @@
+use std::collections::HashSet;
+
 use derive_more::From;
-use ergo_lib::ergotree_ir::chain::ergo_box::ErgoBox;
+use ergo_lib::chain::transaction::unsigned::UnsignedTransaction;
+use ergo_lib::ergotree_ir::chain::ergo_box::{BoxId, ErgoBox};
 use ergo_node_interface::node_interface::NodeError;
 use thiserror::Error;
 
+use crate::local_wallet::local_wallet;
 use crate::node_interface;
+use crate::offline_signing::{export_change_address, export_path};
+use crate::oracle_config::ORACLE_CONFIG;
+use crate::scans::{NodeScanIds, ScanError, LOCAL_WALLET_SCAN_NAME};
 
 #[derive(Debug, Error, From)]
 pub enum WalletDataError {
     #[error("node error: {0}")]
     NodeError(NodeError),
+    #[error("scan error: {0}")]
+    ScanError(ScanError),
 }
 
 pub trait WalletDataSource {
@@ -25,6 +34,130 @@ impl WalletData {
 
 impl WalletDataSource for WalletData {
     fn get_unspent_wallet_boxes(&self) -> Result<Vec<ErgoBox>, WalletDataError> {
-        node_interface::get_unspent_wallet_boxes().map_err(Into::into)
+        match local_wallet() {
+            Some(local_wallet) => local_wallet.get_unspent_wallet_boxes(),
+            // The exported txs are signed offline by the wallet of the oracle address
+            None if export_path().is_some() => Ok(NodeScanIds::load()?
+                .scan(LOCAL_WALLET_SCAN_NAME, LOCAL_WALLET_SCAN_NAME)
+                .get_boxes()?),
+            None => node_interface::get_unspent_wallet_boxes().map_err(Into::into),
+        }
+    }
+}
+
+/// Wallet boxes minus the inputs of the txs built so far, so that the actions of the oracle
+/// identities built in the same main loop iteration don't spend the same boxes
+pub struct ReservingWallet<'a> {
+    wallet: &'a dyn WalletDataSource,
+    reserved: HashSet<BoxId>,
+}
+
+impl<'a> ReservingWallet<'a> {
+    pub fn new(wallet: &'a dyn WalletDataSource) -> Self {
+        ReservingWallet {
+            wallet,
+            reserved: HashSet::new(),
+        }
+    }
+
+    /// Whether the tx spends a box spent by one of the txs built before (a wallet box, the pool
+    /// box, a datapoint box collected by a refresh)
+    pub fn spends_reserved(&self, tx: &UnsignedTransaction) -> bool {
+        tx.inputs
+            .as_vec()
+            .iter()
+            .any(|input| self.reserved.contains(&input.box_id))
+    }
+
+    /// Excludes the inputs of the tx from the next box selections
+    pub fn reserve_inputs(&mut self, tx: &UnsignedTransaction) {
+        self.reserved
+            .extend(tx.inputs.as_vec().iter().map(|input| input.box_id.clone()));
+    }
+
+    /// Excludes the boxes (e.g. the inputs of a pending tx) from the next box selections
+    pub fn reserve(&mut self, box_ids: &[BoxId]) {
+        self.reserved.extend(box_ids.iter().cloned());
+    }
+}
+
+impl<'a> WalletDataSource for ReservingWallet<'a> {
+    fn get_unspent_wallet_boxes(&self) -> Result<Vec<ErgoBox>, WalletDataError> {
+        Ok(self
+            .wallet
+            .get_unspent_wallet_boxes()?
+            .into_iter()
+            .filter(|b| !self.reserved.contains(&b.box_id()))
+            .collect())
+    }
+}
+
+/// Change address of the wallet signing the txs: the oracle address with a local wallet, the
+/// `--change-address` of the exported txs, the node wallet change address otherwise (none if the
+/// node wallet is locked)
+pub fn change_address() -> Result<Option<String>, NodeError> {
+    match local_wallet() {
+        Some(_) => Ok(Some(ORACLE_CONFIG.oracle_address.to_base58())),
+        None if export_path().is_some() => Ok(Some(export_change_address())),
+        None => Ok(node_interface::get_wallet_status()?.change_address),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::oracle_config::BASE_FEE;
+    use crate::pool_commands::test_utils::{make_wallet_unspent_box, WalletDataMock};
+    use ergo_lib::chain::ergo_box::box_builder::ErgoBoxCandidateBuilder;
+    use ergo_lib::ergotree_interpreter::sigma_protocol::private_input::DlogProverInput;
+    use ergo_lib::ergotree_ir::chain::address::Address;
+    use ergo_lib::wallet::box_selector::{BoxSelector, SimpleBoxSelector};
+    use ergo_lib::wallet::tx_builder::TxBuilder;
+    use sigma_test_util::force_any_val;
+
+    #[test]
+    fn test_reserving_wallet() {
+        let secret = force_any_val::<DlogProverInput>();
+        let address = Address::P2Pk(secret.public_image());
+        let wallet_mock = WalletDataMock {
+            unspent_boxes: (1..=2)
+                .map(|n| {
+                    make_wallet_unspent_box(
+                        secret.public_image(),
+                        BASE_FEE.checked_mul_u32(n * 10).unwrap(),
+                        None,
+                    )
+                })
+                .collect(),
+        };
+        let mut wallet = ReservingWallet::new(&wallet_mock);
+        let build_tx = |wallet: &dyn WalletDataSource| {
+            let output = ErgoBoxCandidateBuilder::new(*BASE_FEE, address.script().unwrap(), 1)
+                .build()
+                .unwrap();
+            let box_selection = SimpleBoxSelector::new()
+                .select(
+                    wallet.get_unspent_wallet_boxes().unwrap(),
+                    BASE_FEE.checked_mul_u32(2).unwrap(),
+                    &[],
+                )
+                .unwrap();
+            TxBuilder::new(box_selection, vec![output], 1, *BASE_FEE, address.clone())
+                .build()
+                .unwrap()
+        };
+
+        let first_tx = build_tx(&wallet);
+        wallet.reserve_inputs(&first_tx);
+        let second_tx = build_tx(&wallet);
+        wallet.reserve_inputs(&second_tx);
+        assert!(wallet.spends_reserved(&first_tx));
+        assert!(!ReservingWallet::new(&wallet_mock).spends_reserved(&first_tx));
+        assert!(first_tx.inputs.as_vec().iter().all(|first| second_tx
+            .inputs
+            .as_vec()
+            .iter()
+            .all(|i| i.box_id != first.box_id)));
+        assert!(wallet.get_unspent_wallet_boxes().unwrap().is_empty());
     }
 }